@@ -23,6 +23,7 @@ async fn read_file(file_path: &str) -> io::Result<(String, Vec<u8>)> {
 async fn upload_file(
     client: &Client,
     url: &str,
+    token: Option<&str>,
     file_name: String,
     file_data: Vec<u8>,
 ) -> Result<String, reqwest::Error> {
@@ -33,7 +34,11 @@ async fn upload_file(
 
     let form = multipart::Form::new().part("file", part);
 
-    let response = client.post(url).multipart(form).send().await?;
+    let mut request = client.post(url).multipart(form);
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+    let response = request.send().await?;
 
     if response.status().is_success() {
         println!("File '{}' uploaded successfully", file_name);
@@ -46,9 +51,23 @@ async fn upload_file(
 
 #[tokio::main]
 async fn main() {
-    let args: Vec<String> = env::args().collect();
+    let mut args: Vec<String> = env::args().collect();
+
+    // The API key or JWT sent as bearer token, the flag takes precedence over UPLOADER_TOKEN
+    let mut token = env::var("UPLOADER_TOKEN")
+        .ok()
+        .filter(|token| !token.is_empty());
+    if let Some(index) = args.iter().position(|arg| arg == "--token") {
+        if index + 1 >= args.len() {
+            eprintln!("Missing value for --token");
+            return;
+        }
+        token = Some(args.remove(index + 1));
+        args.remove(index);
+    }
+
     if args.len() < 2 {
-        eprintln!("Usage: {} <file_path>", args[0]);
+        eprintln!("Usage: {} [--token <token>] <file_path>", args[0]);
         return;
     }
 
@@ -57,7 +76,7 @@ async fn main() {
         Ok((file_name, file_data)) => {
             let client = Client::new();
             let url = "http://localhost:3000/uploader/upload";
-            match upload_file(&client, url, file_name, file_data).await {
+            match upload_file(&client, url, token.as_deref(), file_name, file_data).await {
                 Ok(data) => println!("{data}"),
                 Err(err) => {
                     eprintln!("Error uploading file: {}", err);
@@ -0,0 +1,192 @@
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+use crate::compressor::{
+    BrotliCompressor, Bzip2Compressor, Compressor, GzipCompressor, Lz4Compressor, XzCompressor, ZstdCompressor,
+};
+
+/// Compression algorithms supported by the library
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Algorithm {
+    #[default]
+    Gzip,
+    Zstd,
+    Brotli,
+    Xz,
+    Bzip2,
+    Lz4,
+}
+
+impl Algorithm {
+    pub const ALL: [Algorithm; 6] = [
+        Algorithm::Gzip,
+        Algorithm::Zstd,
+        Algorithm::Brotli,
+        Algorithm::Xz,
+        Algorithm::Bzip2,
+        Algorithm::Lz4,
+    ];
+
+    /// Canonical name, as stored in `compressed_files.alg`
+    pub fn name(&self) -> &'static str {
+        match self {
+            Algorithm::Gzip => "gzip",
+            Algorithm::Zstd => "zstd",
+            Algorithm::Brotli => "brotli",
+            Algorithm::Xz => "xz",
+            Algorithm::Bzip2 => "bzip2",
+            Algorithm::Lz4 => "lz4",
+        }
+    }
+
+    /// File extension used for compressed outputs (without the leading dot)
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Algorithm::Gzip => "gz",
+            Algorithm::Zstd => "zst",
+            Algorithm::Brotli => "br",
+            Algorithm::Xz => "xz",
+            Algorithm::Bzip2 => "bz2",
+            Algorithm::Lz4 => "lz4",
+        }
+    }
+
+    /// Media type of a compressed output
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            Algorithm::Gzip => "application/gzip",
+            Algorithm::Zstd => "application/zstd",
+            Algorithm::Brotli => "application/x-brotli",
+            Algorithm::Xz => "application/x-xz",
+            Algorithm::Bzip2 => "application/x-bzip2",
+            Algorithm::Lz4 => "application/x-lz4",
+        }
+    }
+
+    /// HTTP `Content-Encoding` token, for the algorithms browsers can decode
+    pub fn content_encoding(&self) -> Option<&'static str> {
+        match self {
+            Algorithm::Gzip => Some("gzip"),
+            Algorithm::Zstd => Some("zstd"),
+            Algorithm::Brotli => Some("br"),
+            Algorithm::Xz | Algorithm::Bzip2 | Algorithm::Lz4 => None,
+        }
+    }
+
+    /// Algorithm of an HTTP `Content-Encoding` token, `x-gzip` included
+    pub fn from_content_encoding(token: &str) -> Option<Algorithm> {
+        match token.to_ascii_lowercase().as_str() {
+            "gzip" | "x-gzip" => Some(Algorithm::Gzip),
+            "zstd" => Some(Algorithm::Zstd),
+            "br" => Some(Algorithm::Brotli),
+            _ => None,
+        }
+    }
+
+    /// Levels accepted by the algorithm
+    pub fn levels(&self) -> RangeInclusive<u32> {
+        match self {
+            Algorithm::Gzip => 0..=9,
+            Algorithm::Zstd => 1..=22,
+            Algorithm::Brotli => 0..=11,
+            Algorithm::Xz => 0..=9,
+            Algorithm::Bzip2 => 1..=9,
+            // lz4_flex only has a single (fast) mode
+            Algorithm::Lz4 => 0..=0,
+        }
+    }
+
+    pub fn default_level(&self) -> u32 {
+        match self {
+            Algorithm::Gzip => 6,
+            Algorithm::Zstd => 3,
+            Algorithm::Brotli => 6,
+            Algorithm::Xz => 6,
+            Algorithm::Bzip2 => 6,
+            Algorithm::Lz4 => 0,
+        }
+    }
+
+    /// Returns `level` if the algorithm supports it, for levels chosen by callers that must not be altered
+    pub fn check_level(&self, level: u32) -> Result<u32, UnsupportedLevel> {
+        if self.levels().contains(&level) {
+            Ok(level)
+        } else {
+            Err(UnsupportedLevel(*self, level))
+        }
+    }
+
+    /// Returns `level` if the algorithm supports it, otherwise the default level
+    pub fn normalize_level(&self, level: u32) -> u32 {
+        if self.levels().contains(&level) {
+            level
+        } else {
+            self.default_level()
+        }
+    }
+
+    /// Builds a compressor for this algorithm. Out of range levels fall back to the default.
+    pub fn compressor(&self, level: u32) -> Box<dyn Compressor> {
+        let level = self.normalize_level(level);
+        match self {
+            Algorithm::Gzip => Box::new(GzipCompressor::new(flate2::Compression::new(level))),
+            Algorithm::Zstd => Box::new(ZstdCompressor::new(level as i32)),
+            Algorithm::Brotli => Box::new(BrotliCompressor::new(level)),
+            Algorithm::Xz => Box::new(XzCompressor::new(level)),
+            Algorithm::Bzip2 => Box::new(Bzip2Compressor::new(level)),
+            Algorithm::Lz4 => Box::new(Lz4Compressor),
+        }
+    }
+}
+
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Returned when parsing an algorithm name that isn't supported
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownAlgorithm(pub String);
+
+impl fmt::Display for UnknownAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Unknown compression algorithm: {}", self.0)
+    }
+}
+
+impl std::error::Error for UnknownAlgorithm {}
+
+/// Returned when a level is outside of the range the algorithm supports
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnsupportedLevel(pub Algorithm, pub u32);
+
+impl fmt::Display for UnsupportedLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let levels = self.0.levels();
+        write!(
+            f,
+            "Unsupported {} level: {} (expected {} to {})",
+            self.0,
+            self.1,
+            levels.start(),
+            levels.end()
+        )
+    }
+}
+
+impl std::error::Error for UnsupportedLevel {}
+
+impl FromStr for Algorithm {
+    type Err = UnknownAlgorithm;
+
+    /// Accepts the canonical name or the file extension, case-insensitively
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lowercase = s.trim().to_ascii_lowercase();
+        Algorithm::ALL
+            .into_iter()
+            .find(|alg| alg.name() == lowercase || alg.extension() == lowercase)
+            .ok_or_else(|| UnknownAlgorithm(s.to_string()))
+    }
+}
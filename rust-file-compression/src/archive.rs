@@ -0,0 +1,797 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Seek, Write};
+use std::path::{Component, Path};
+use std::str::FromStr;
+use std::time::Instant;
+
+use cpu_time::ThreadTime;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::algorithm::Algorithm;
+use crate::cancel::{CancellableReader, CancellationToken};
+use crate::stats::{to_hex, CompressionStats, HashingWriter};
+
+/// Members of at least this size need the zip64 extensions
+const ZIP64_THRESHOLD: u64 = u32::MAX as u64;
+/// Local file header, and the end of central directory record of an empty zip
+const ZIP_SIGNATURES: [&[u8]; 2] = [&[b'P', b'K', 0x03, 0x04], &[b'P', b'K', 0x05, 0x06]];
+/// Size of a tar header block
+const TAR_BLOCK_SIZE: usize = 512;
+
+/// Archive formats that bundle several files into one output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ArchiveFormat {
+    #[default]
+    TarGz,
+    TarZst,
+    Zip,
+}
+
+impl ArchiveFormat {
+    pub const ALL: [ArchiveFormat; 3] = [ArchiveFormat::TarGz, ArchiveFormat::TarZst, ArchiveFormat::Zip];
+
+    /// Canonical name, as stored in `compressed_files.alg`. It doubles as the file extension.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ArchiveFormat::TarGz => "tar.gz",
+            ArchiveFormat::TarZst => "tar.zst",
+            ArchiveFormat::Zip => "zip",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        self.name()
+    }
+
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            ArchiveFormat::Zip => "application/zip",
+            // A compressed tarball is typed by its outer compression, like `tar.gz` is `application/gzip`
+            format => format.algorithm().mime_type(),
+        }
+    }
+
+    /// Algorithm the members are compressed with, which also decides the accepted levels
+    pub fn algorithm(&self) -> Algorithm {
+        match self {
+            ArchiveFormat::TarGz => Algorithm::Gzip,
+            ArchiveFormat::TarZst => Algorithm::Zstd,
+            // Zip members are deflated, the same levels as gzip
+            ArchiveFormat::Zip => Algorithm::Gzip,
+        }
+    }
+
+    pub fn default_level(&self) -> u32 {
+        self.algorithm().default_level()
+    }
+
+    /// Guesses the format from the first bytes of an archive.
+    /// Any gzip or zstd stream is taken for a tarball, [`probe_archive`] confirms it by reading it.
+    pub fn detect(header: &[u8]) -> Option<ArchiveFormat> {
+        if ZIP_SIGNATURES.iter().any(|magic| header.starts_with(magic)) {
+            return Some(ArchiveFormat::Zip);
+        }
+
+        match Algorithm::detect(header)? {
+            Algorithm::Gzip => Some(ArchiveFormat::TarGz),
+            Algorithm::Zstd => Some(ArchiveFormat::TarZst),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ArchiveFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Returned when parsing an archive format name that isn't supported
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownArchiveFormat(pub String);
+
+impl fmt::Display for UnknownArchiveFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Unknown archive format: {} (expected tar.gz, tar.zst or zip)", self.0)
+    }
+}
+
+impl std::error::Error for UnknownArchiveFormat {}
+
+impl FromStr for ArchiveFormat {
+    type Err = UnknownArchiveFormat;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "tar.gz" | "tgz" => Ok(ArchiveFormat::TarGz),
+            "tar.zst" | "tzst" => Ok(ArchiveFormat::TarZst),
+            "zip" => Ok(ArchiveFormat::Zip),
+            _ => Err(UnknownArchiveFormat(s.to_string())),
+        }
+    }
+}
+
+/// A file to add to an archive, under `name`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveEntry {
+    pub name: String,
+    pub path: String,
+}
+
+/// Guards against archives crafted to exhaust the disk when extracted
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExtractionLimits {
+    pub max_entries: usize,
+    /// Total size of the extracted files, whatever the archive headers claim
+    pub max_total_size: u64,
+    /// Total extracted size divided by the archive size
+    pub max_ratio: f64,
+}
+
+impl Default for ExtractionLimits {
+    fn default() -> Self {
+        Self {
+            max_entries: 10_000,
+            max_total_size: 1024 * 1024 * 1024,
+            max_ratio: 100.0,
+        }
+    }
+}
+
+/// A file written out of an archive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtractedEntry {
+    /// Path of the member inside the archive
+    pub name: String,
+    /// Where it was written
+    pub path: String,
+    pub size: u64,
+    pub sha256: [u8; 32],
+}
+
+impl ExtractedEntry {
+    pub fn sha256_hex(&self) -> String {
+        to_hex(&self.sha256)
+    }
+}
+
+/// Writes `entries` into `writer` as one archive and returns the total size of the members.
+/// Only the file name part of each entry name is kept, and repeated names get a numeric suffix.
+pub fn write_archive<W: Write + Seek + Send>(
+    writer: W,
+    entries: &[ArchiveEntry],
+    format: ArchiveFormat,
+    level: u32,
+) -> io::Result<u64> {
+    write_archive_cancellable(writer, entries, format, level, &CancellationToken::new())
+}
+
+/// Same as [`write_archive`], failing while reading the members once `cancellation` is cancelled
+pub fn write_archive_cancellable<W: Write + Seek + Send>(
+    writer: W,
+    entries: &[ArchiveEntry],
+    format: ArchiveFormat,
+    level: u32,
+    cancellation: &CancellationToken,
+) -> io::Result<u64> {
+    let level = format.algorithm().normalize_level(level);
+    let names = member_names(entries)?;
+
+    match format {
+        ArchiveFormat::TarGz | ArchiveFormat::TarZst => {
+            let compressor = format.algorithm().compressor(level);
+            let mut builder = tar::Builder::new(compressor.encoder(Box::new(writer))?);
+            let mut total = 0;
+            for (entry, name) in entries.iter().zip(&names) {
+                // What `append_file` does, with the member read through the cancellation check
+                let file = File::open(&entry.path)?;
+                let metadata = file.metadata()?;
+                let mut header = tar::Header::new_gnu();
+                header.set_metadata(&metadata);
+                builder.append_data(&mut header, name, CancellableReader::new(file, cancellation.clone()))?;
+                total += metadata.len();
+            }
+            builder.into_inner()?.finish()?;
+
+            Ok(total)
+        }
+        ArchiveFormat::Zip => {
+            let mut zip = ZipWriter::new(writer);
+            let mut total = 0;
+            for (entry, name) in entries.iter().zip(&names) {
+                let file = File::open(&entry.path)?;
+                let size = file.metadata()?.len();
+                let options = SimpleFileOptions::default()
+                    .compression_method(CompressionMethod::Deflated)
+                    .compression_level(Some(level as i64))
+                    .large_file(size >= ZIP64_THRESHOLD);
+                zip.start_file(name.as_str(), options).map_err(io::Error::other)?;
+                total += io::copy(&mut CancellableReader::new(file, cancellation.clone()), &mut zip)?;
+            }
+            zip.finish().map_err(io::Error::other)?.flush()?;
+
+            Ok(total)
+        }
+    }
+}
+
+/// Builds the archive in `output_file`, on tokio's blocking pool.
+/// Returns the same statistics as a single-file compression, with the members' total size as input.
+pub async fn create_archive(
+    output_file: &str,
+    entries: Vec<ArchiveEntry>,
+    format: ArchiveFormat,
+    level: u32,
+) -> io::Result<CompressionStats> {
+    create_archive_cancellable(output_file, entries, format, level, CancellationToken::new()).await
+}
+
+/// Same as [`create_archive`], stopping once `cancellation` is cancelled.
+/// The archive is built next to `output_file` with a `.partial` suffix and only renamed once complete,
+/// so `output_file` never holds a truncated archive. On failure the partial archive is removed.
+pub async fn create_archive_cancellable(
+    output_file: &str,
+    entries: Vec<ArchiveEntry>,
+    format: ArchiveFormat,
+    level: u32,
+    cancellation: CancellationToken,
+) -> io::Result<CompressionStats> {
+    let output_file = output_file.to_owned();
+
+    tokio::task::spawn_blocking(move || {
+        let started = Instant::now();
+        let cpu_started = ThreadTime::now();
+
+        let partial_file = format!("{output_file}.partial");
+        let result = (|| {
+            let output = BufWriter::new(File::create(&partial_file)?);
+            let input_size = write_archive_cancellable(output, &entries, format, level, &cancellation)?;
+
+            // Zip writes its central directory by seeking back, so hash the finished file instead of the stream
+            let mut hashing = HashingWriter::new(io::sink());
+            let output_size = io::copy(&mut File::open(&partial_file)?, &mut hashing)?;
+            fs::rename(&partial_file, &output_file)?;
+
+            Ok(CompressionStats {
+                input_size,
+                output_size,
+                wall_time: started.elapsed(),
+                cpu_time: cpu_started.elapsed(),
+                sha256: hashing.finalize(),
+            })
+        })();
+
+        if result.is_err() {
+            let _ = fs::remove_file(&partial_file);
+        }
+        result
+    })
+    .await
+    .map_err(io::Error::other)?
+}
+
+/// Detects the format of the archive in `input_file`, None when it isn't one.
+/// A gzip or zstd stream is only a tarball when it starts with a valid tar header, `data.json.gz` isn't.
+pub fn probe_archive(input_file: &str) -> io::Result<Option<ArchiveFormat>> {
+    let mut input = File::open(input_file)?;
+    let mut header = Vec::with_capacity(8);
+    (&mut input).take(8).read_to_end(&mut header)?;
+    input.rewind()?;
+
+    let format = match ArchiveFormat::detect(&header) {
+        Some(ArchiveFormat::Zip) => return Ok(Some(ArchiveFormat::Zip)),
+        Some(format) => format,
+        None => return Ok(None),
+    };
+
+    // A stream that doesn't decode, or is too short for a header, isn't a tarball either
+    let mut block = Vec::with_capacity(TAR_BLOCK_SIZE);
+    let decoded = format
+        .algorithm()
+        .decoder(Box::new(BufReader::new(input)))
+        .and_then(|decoder| decoder.take(TAR_BLOCK_SIZE as u64).read_to_end(&mut block));
+    if decoded.is_err() || block.len() < TAR_BLOCK_SIZE {
+        return Ok(None);
+    }
+
+    Ok(is_tar_header(&block).then_some(format))
+}
+
+/// Same as [`probe_archive`], on tokio's blocking pool
+pub async fn detect_archive(input_file: &str) -> io::Result<Option<ArchiveFormat>> {
+    let input_file = input_file.to_owned();
+
+    tokio::task::spawn_blocking(move || probe_archive(&input_file))
+        .await
+        .map_err(io::Error::other)?
+}
+
+// The checksum covers the whole block, with its own field counted as spaces
+fn is_tar_header(block: &[u8]) -> bool {
+    let Ok(expected) = tar::Header::from_byte_slice(block).cksum() else {
+        return false;
+    };
+    let sum: u32 = block[..148]
+        .iter()
+        .chain(&[b' '; 8])
+        .chain(&block[156..])
+        .map(|byte| *byte as u32)
+        .sum();
+
+    sum == expected
+}
+
+/// Extracts the regular files of `input_file` into `output_dir`, detecting the format.
+/// Members are flattened to `{prefix}{dir}_{file}` and never overwrite an existing file.
+/// Paths escaping the archive (zip-slip) and exceeded `limits` fail the whole extraction,
+/// in which case nothing is left behind. Symlinks, hard links and directories are skipped.
+pub fn unpack_archive(
+    input_file: &str,
+    output_dir: &str,
+    prefix: &str,
+    limits: &ExtractionLimits,
+) -> io::Result<Vec<ExtractedEntry>> {
+    let mut input = File::open(input_file)?;
+    let archive_size = input.metadata()?.len();
+    let mut header = Vec::with_capacity(8);
+    (&mut input).take(8).read_to_end(&mut header)?;
+    input.rewind()?;
+
+    let format = ArchiveFormat::detect(&header)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Unrecognized archive format"))?;
+
+    let mut extractor = Extractor {
+        output_dir: Path::new(output_dir),
+        prefix,
+        max_entries: limits.max_entries,
+        // The ratio limit is turned into a size so both are enforced while copying
+        budget: limits
+            .max_total_size
+            .min((archive_size as f64 * limits.max_ratio) as u64),
+        used_names: HashSet::new(),
+        entries: Vec::new(),
+    };
+    let result = match format {
+        ArchiveFormat::TarGz | ArchiveFormat::TarZst => {
+            extractor.unpack_tar(format.algorithm().decoder(Box::new(BufReader::new(input)))?)
+        }
+        ArchiveFormat::Zip => extractor.unpack_zip(input),
+    };
+
+    if let Err(e) = result {
+        for entry in &extractor.entries {
+            let _ = fs::remove_file(&entry.path);
+        }
+        return Err(e);
+    }
+
+    Ok(extractor.entries)
+}
+
+/// Same as [`unpack_archive`], on tokio's blocking pool
+pub async fn extract_archive(
+    input_file: &str,
+    output_dir: &str,
+    prefix: &str,
+    limits: ExtractionLimits,
+) -> io::Result<Vec<ExtractedEntry>> {
+    let (input_file, output_dir, prefix) = (input_file.to_owned(), output_dir.to_owned(), prefix.to_owned());
+
+    tokio::task::spawn_blocking(move || unpack_archive(&input_file, &output_dir, &prefix, &limits))
+        .await
+        .map_err(io::Error::other)?
+}
+
+struct Extractor<'a> {
+    output_dir: &'a Path,
+    prefix: &'a str,
+    max_entries: usize,
+    // Bytes that can still be extracted
+    budget: u64,
+    used_names: HashSet<String>,
+    entries: Vec<ExtractedEntry>,
+}
+
+impl Extractor<'_> {
+    fn unpack_tar<R: Read>(&mut self, reader: R) -> io::Result<()> {
+        let mut archive = tar::Archive::new(reader);
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+            let name = String::from_utf8_lossy(&entry.path_bytes()).into_owned();
+            self.extract(&name, &mut entry)?;
+        }
+
+        Ok(())
+    }
+
+    fn unpack_zip<R: Read + Seek>(&mut self, reader: R) -> io::Result<()> {
+        let mut archive = ZipArchive::new(reader).map_err(io::Error::other)?;
+        if archive.len() > self.max_entries {
+            return Err(too_many_entries(self.max_entries));
+        }
+
+        for index in 0..archive.len() {
+            let mut member = archive.by_index(index).map_err(io::Error::other)?;
+            if member.is_dir() || member.is_symlink() {
+                continue;
+            }
+            let name = member.name().to_string();
+            self.extract(&name, &mut member)?;
+        }
+
+        Ok(())
+    }
+
+    fn extract(&mut self, name: &str, reader: &mut dyn Read) -> io::Result<()> {
+        if self.entries.len() >= self.max_entries {
+            return Err(too_many_entries(self.max_entries));
+        }
+
+        let flat_name = unique_name(&mut self.used_names, &flatten(name)?);
+        let path = self.output_dir.join(format!("{}{flat_name}", self.prefix));
+        let path_str = path
+            .to_str()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Output path is not valid UTF-8"))?
+            .to_string();
+
+        let file = OpenOptions::new().write(true).create_new(true).open(&path)?;
+        // Recorded before copying so a failure halfway still cleans it up
+        self.entries.push(ExtractedEntry {
+            name: name.to_string(),
+            path: path_str,
+            size: 0,
+            sha256: [0; 32],
+        });
+
+        // Headers can lie about sizes, so count what is actually written
+        let mut writer = HashingWriter::new(BufWriter::new(file));
+        let size = io::copy(&mut reader.take(self.budget + 1), &mut writer)?;
+        writer.flush()?;
+        if size > self.budget {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Archive expands beyond the extraction limits",
+            ));
+        }
+        self.budget -= size;
+        if let Some(entry) = self.entries.last_mut() {
+            entry.size = size;
+            entry.sha256 = writer.finalize();
+        }
+
+        Ok(())
+    }
+}
+
+fn too_many_entries(max_entries: usize) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Archive has more than {max_entries} entries"),
+    )
+}
+
+// Joins the path components with `_`, rejecting anything that would escape the output directory
+fn flatten(name: &str) -> io::Result<String> {
+    let unsafe_path = || io::Error::new(io::ErrorKind::InvalidData, format!("Unsafe path in archive: {name}"));
+
+    // Zip paths may use either separator
+    let normalized = name.replace('\\', "/");
+    let mut parts = Vec::new();
+    for component in Path::new(&normalized).components() {
+        match component {
+            Component::Normal(part) => parts.push(part.to_string_lossy().into_owned()),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => return Err(unsafe_path()),
+        }
+    }
+
+    if parts.is_empty() {
+        return Err(unsafe_path());
+    }
+    Ok(parts.join("_"))
+}
+
+// Appends `-1`, `-2`, ... to the stem until the name hasn't been used yet
+fn unique_name(used: &mut HashSet<String>, file_name: &str) -> String {
+    let path = Path::new(file_name);
+    let mut name = file_name.to_string();
+    let mut suffix = 0;
+    while !used.insert(name.clone()) {
+        suffix += 1;
+        name = match (path.file_stem().and_then(|stem| stem.to_str()), path.extension()) {
+            (Some(stem), Some(extension)) => format!("{stem}-{suffix}.{}", extension.to_string_lossy()),
+            _ => format!("{file_name}-{suffix}"),
+        };
+    }
+
+    name
+}
+
+// Archive member names are plain file names, made unique with [`unique_name`]
+fn member_names(entries: &[ArchiveEntry]) -> io::Result<Vec<String>> {
+    let mut used = HashSet::new();
+    entries
+        .iter()
+        .map(|entry| {
+            let file_name = Path::new(&entry.name)
+                .file_name()
+                .and_then(|name| name.to_str())
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("Invalid archive member name: {}", entry.name),
+                    )
+                })?;
+
+            Ok(unique_name(&mut used, file_name))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cancel::is_cancellation;
+    use sha2::{Digest, Sha256};
+    use std::fs;
+    use std::io::{Cursor, Read};
+
+    fn entries(prefix: &str) -> io::Result<Vec<ArchiveEntry>> {
+        let mut entries = Vec::new();
+        for (i, name) in ["notes.txt", "data.csv", "notes.txt"].iter().enumerate() {
+            let path = format!("test_archive_{prefix}_{i}.tmp");
+            fs::write(&path, format!("member {i}\n").repeat(1000))?;
+            entries.push(ArchiveEntry {
+                name: name.to_string(),
+                path,
+            });
+        }
+        Ok(entries)
+    }
+
+    fn cleanup(entries: &[ArchiveEntry]) {
+        for entry in entries {
+            let _ = fs::remove_file(&entry.path);
+        }
+    }
+
+    #[test]
+    fn test_tar_archives_round_trip() -> io::Result<()> {
+        let entries = entries("tar")?;
+
+        for format in [ArchiveFormat::TarGz, ArchiveFormat::TarZst] {
+            let mut archive = Cursor::new(Vec::new());
+            let total = write_archive(&mut archive, &entries, format, 6)?;
+            assert_eq!(total, 3 * 9000);
+
+            let decoder = format.algorithm().decoder(Box::new(Cursor::new(archive.into_inner())))?;
+            let mut members = Vec::new();
+            for member in tar::Archive::new(decoder).entries()? {
+                let mut member = member?;
+                let mut contents = String::new();
+                member.read_to_string(&mut contents)?;
+                members.push((member.path()?.display().to_string(), contents.len()));
+            }
+            assert_eq!(
+                members,
+                [("notes.txt".to_string(), 9000), ("data.csv".to_string(), 9000), ("notes-1.txt".to_string(), 9000)]
+            );
+        }
+
+        cleanup(&entries);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_create_zip_archive() -> io::Result<()> {
+        let entries = entries("zip")?;
+        let output_path = "test_archive_output.zip";
+
+        let stats = create_archive(output_path, entries.clone(), ArchiveFormat::Zip, 9).await?;
+        assert_eq!(stats.input_size, 3 * 9000);
+        assert_eq!(stats.output_size, fs::metadata(output_path)?.len());
+        assert!(stats.ratio() > 1.0);
+
+        let mut zip = zip::ZipArchive::new(File::open(output_path)?).map_err(io::Error::other)?;
+        let mut third = String::new();
+        zip.by_name("notes-1.txt").map_err(io::Error::other)?.read_to_string(&mut third)?;
+        assert!(third.starts_with("member 2\n"));
+        assert_eq!(zip.len(), 3);
+
+        fs::remove_file(output_path)?;
+        cleanup(&entries);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_create_archive_cancellation() -> io::Result<()> {
+        let entries = entries("cancel")?;
+        let cancellation = CancellationToken::new();
+        cancellation.cancel();
+
+        for format in ArchiveFormat::ALL {
+            let output_path = format!("test_archive_cancel.{}", format.extension());
+            let result =
+                create_archive_cancellable(&output_path, entries.clone(), format, 6, cancellation.clone()).await;
+
+            assert!(is_cancellation(&result.unwrap_err()));
+            assert!(!Path::new(&output_path).exists());
+            assert!(!Path::new(&format!("{output_path}.partial")).exists());
+        }
+
+        cleanup(&entries);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_create_archive_failure() -> io::Result<()> {
+        let mut entries = entries("failure")?;
+        // The last member is gone by the time the archive is built
+        fs::remove_file(&entries[2].path)?;
+
+        for format in ArchiveFormat::ALL {
+            let output_path = format!("test_archive_failure.{}", format.extension());
+            // Left by an earlier archive, a failed one must not truncate it
+            fs::write(&output_path, b"previous archive")?;
+
+            let result = create_archive(&output_path, entries.clone(), format, 6).await;
+
+            assert_eq!(result.unwrap_err().kind(), io::ErrorKind::NotFound);
+            assert_eq!(fs::read(&output_path)?, b"previous archive");
+            assert!(!Path::new(&format!("{output_path}.partial")).exists());
+            fs::remove_file(&output_path)?;
+        }
+
+        entries.pop();
+        cleanup(&entries);
+        Ok(())
+    }
+
+    #[test]
+    fn test_probe_archive() -> io::Result<()> {
+        let entries = entries("probe")?;
+        for format in ArchiveFormat::ALL {
+            let path = format!("test_probe.{}", format.extension());
+            write_archive(File::create(&path)?, &entries, format, format.default_level())?;
+            assert_eq!(probe_archive(&path)?, Some(format), "{format}");
+            fs::remove_file(&path)?;
+        }
+        cleanup(&entries);
+
+        // Compressed files that aren't tarballs, e.g. `data.json.gz`, longer and shorter than a tar header
+        for data in [br#"{"data": [1, 2, 3]}"#.repeat(100), b"{}".to_vec()] {
+            for algorithm in [Algorithm::Gzip, Algorithm::Zstd] {
+                let path = format!("test_probe.json.{}", algorithm.extension());
+                let mut compressed = Vec::new();
+                crate::compress_stream_with(data.as_slice(), &mut compressed, algorithm.compressor(1).as_ref())?;
+                fs::write(&path, compressed)?;
+                assert_eq!(probe_archive(&path)?, None, "{algorithm}");
+                fs::remove_file(&path)?;
+            }
+        }
+
+        let path = "test_probe.txt";
+        for content in [&b"plain text"[..], b"\x1f\x8bcorrupt gzip body", b""] {
+            fs::write(path, content)?;
+            assert_eq!(probe_archive(path)?, None);
+        }
+        fs::remove_file(path)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unpack_archive_round_trips() -> io::Result<()> {
+        let entries = entries("unpack")?;
+        let output_dir = "test_unpack_output";
+        fs::create_dir_all(output_dir)?;
+        // The repetitive members compress far better than real files
+        let limits = ExtractionLimits {
+            max_ratio: 1000.0,
+            ..ExtractionLimits::default()
+        };
+
+        for format in ArchiveFormat::ALL {
+            let archive_path = format!("test_unpack_input.{}", format.extension());
+            write_archive(File::create(&archive_path)?, &entries, format, 6)?;
+
+            let prefix = format!("{}_", format.extension());
+            let extracted = unpack_archive(&archive_path, output_dir, &prefix, &limits)?;
+            let names: Vec<_> = extracted.iter().map(|entry| entry.name.as_str()).collect();
+            assert_eq!(names, ["notes.txt", "data.csv", "notes-1.txt"]);
+            assert_eq!(extracted[2].path, format!("{output_dir}/{prefix}notes-1.txt"));
+            assert_eq!(fs::read_to_string(&extracted[2].path)?, "member 2\n".repeat(1000));
+            assert_eq!(extracted[2].size, 9000);
+            assert_eq!(
+                extracted[2].sha256_hex(),
+                format!("{:x}", Sha256::digest("member 2\n".repeat(1000)))
+            );
+
+            // Extracting again never overwrites what is already there
+            assert!(unpack_archive(&archive_path, output_dir, &prefix, &limits).is_err());
+            assert!(fs::metadata(&extracted[0].path).is_ok());
+
+            fs::remove_file(archive_path)?;
+        }
+
+        fs::remove_dir_all(output_dir)?;
+        cleanup(&entries);
+        Ok(())
+    }
+
+    #[test]
+    fn test_unpack_archive_rejects_unsafe_archives() -> io::Result<()> {
+        let output_dir = "test_unsafe_output";
+        fs::create_dir_all(output_dir)?;
+        let options = SimpleFileOptions::default();
+
+        let write_zip = |path: &str, members: &[(&str, Vec<u8>)], symlink: bool| -> io::Result<()> {
+            let mut zip = ZipWriter::new(File::create(path)?);
+            if symlink {
+                zip.add_symlink("passwd", "/etc/passwd", options)
+                    .map_err(io::Error::other)?;
+            }
+            for (name, data) in members {
+                zip.start_file(*name, options).map_err(io::Error::other)?;
+                zip.write_all(data)?;
+            }
+            zip.finish().map_err(io::Error::other)?;
+            Ok(())
+        };
+
+        // Zip-slip: the first member is extracted, then removed again when the second one is refused
+        write_zip(
+            "test_unsafe_slip.zip",
+            &[("ok.txt", b"ok".to_vec()), ("../evil.txt", b"evil".to_vec())],
+            false,
+        )?;
+        let error = unpack_archive("test_unsafe_slip.zip", output_dir, "", &ExtractionLimits::default()).unwrap_err();
+        assert!(error.to_string().contains("Unsafe path"), "{error}");
+        assert_eq!(fs::read_dir(output_dir)?.count(), 0);
+        assert!(fs::metadata("evil.txt").is_err());
+
+        // Symlinks are left out
+        write_zip("test_unsafe_symlink.zip", &[("ok.txt", b"ok".to_vec())], true)?;
+        let extracted = unpack_archive("test_unsafe_symlink.zip", output_dir, "", &ExtractionLimits::default())?;
+        assert_eq!(extracted.len(), 1);
+        assert_eq!(extracted[0].name, "ok.txt");
+        fs::remove_file(&extracted[0].path)?;
+
+        // Zip bomb: a megabyte of zeros deflates far beyond the allowed ratio
+        write_zip("test_unsafe_bomb.zip", &[("zeros.bin", vec![0; 1024 * 1024])], false)?;
+        let limits = ExtractionLimits {
+            max_ratio: 10.0,
+            ..ExtractionLimits::default()
+        };
+        let error = unpack_archive("test_unsafe_bomb.zip", output_dir, "", &limits).unwrap_err();
+        assert!(error.to_string().contains("extraction limits"), "{error}");
+        assert_eq!(fs::read_dir(output_dir)?.count(), 0);
+
+        for path in [
+            "test_unsafe_slip.zip",
+            "test_unsafe_symlink.zip",
+            "test_unsafe_bomb.zip",
+        ] {
+            fs::remove_file(path)?;
+        }
+        fs::remove_dir_all(output_dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_archive_format_parsing() {
+        assert_eq!("TAR.GZ".parse(), Ok(ArchiveFormat::TarGz));
+        assert_eq!("tzst".parse(), Ok(ArchiveFormat::TarZst));
+        assert!("rar".parse::<ArchiveFormat>().is_err());
+        assert_eq!(ArchiveFormat::Zip.default_level(), 6);
+        assert_eq!(ArchiveFormat::detect(b"PK\x03\x04"), Some(ArchiveFormat::Zip));
+        assert_eq!(ArchiveFormat::detect(&[0x1f, 0x8b, 0x08]), Some(ArchiveFormat::TarGz));
+        assert_eq!(ArchiveFormat::detect(b"BZh9"), None);
+    }
+}
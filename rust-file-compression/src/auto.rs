@@ -0,0 +1,251 @@
+use std::fmt;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::str::FromStr;
+use std::time::Instant;
+
+use crate::algorithm::Algorithm;
+use crate::compress_stream_with;
+
+/// Algorithm name that asks for automatic selection instead of a fixed algorithm
+pub const AUTO: &str = "auto";
+
+/// Size of each slice read from the file when sampling it
+pub const SAMPLE_SLICE_SIZE: usize = 128 * 1024;
+
+/// Under the balanced goal, candidates slower than this share of the fastest one are ruled out
+const BALANCED_MIN_SPEED_SHARE: f64 = 0.2;
+
+/// Algorithms and levels tried on the sample, from fast to thorough
+const CANDIDATES: [(Algorithm, u32); 13] = [
+    (Algorithm::Lz4, 0),
+    (Algorithm::Zstd, 1),
+    (Algorithm::Zstd, 3),
+    (Algorithm::Zstd, 9),
+    (Algorithm::Zstd, 19),
+    (Algorithm::Gzip, 1),
+    (Algorithm::Gzip, 6),
+    (Algorithm::Gzip, 9),
+    (Algorithm::Brotli, 5),
+    (Algorithm::Brotli, 9),
+    (Algorithm::Bzip2, 9),
+    (Algorithm::Xz, 3),
+    (Algorithm::Xz, 6),
+];
+
+/// What automatic selection optimizes for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Goal {
+    /// Smallest output, whatever it costs
+    Ratio,
+    /// Fastest compression
+    Speed,
+    /// Best ratio among the reasonably fast candidates
+    #[default]
+    Balanced,
+}
+
+impl Goal {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Goal::Ratio => "ratio",
+            Goal::Speed => "speed",
+            Goal::Balanced => "balanced",
+        }
+    }
+}
+
+impl fmt::Display for Goal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Returned when parsing a goal name that isn't supported
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownGoal(pub String);
+
+impl fmt::Display for UnknownGoal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Unknown compression goal: {} (expected ratio, speed or balanced)", self.0)
+    }
+}
+
+impl std::error::Error for UnknownGoal {}
+
+impl FromStr for Goal {
+    type Err = UnknownGoal;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "ratio" => Ok(Goal::Ratio),
+            "speed" => Ok(Goal::Speed),
+            "balanced" => Ok(Goal::Balanced),
+            _ => Err(UnknownGoal(s.to_string())),
+        }
+    }
+}
+
+/// Result of one candidate on the sample
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Trial {
+    pub algorithm: Algorithm,
+    pub level: u32,
+    /// Sample size divided by compressed size
+    pub ratio: f64,
+    /// Compression speed on the sample, in MB/s
+    pub throughput: f64,
+}
+
+/// The winning candidate, with a short explanation of why it won
+#[derive(Debug, Clone, PartialEq)]
+pub struct Selection {
+    pub algorithm: Algorithm,
+    pub level: u32,
+    pub goal: Goal,
+    pub reason: String,
+}
+
+/// Trial-compresses `sample` with every candidate and picks the best one for `goal`
+pub fn select(sample: &[u8], goal: Goal) -> io::Result<Selection> {
+    let trials = CANDIDATES
+        .iter()
+        .map(|(algorithm, level)| trial(sample, *algorithm, *level))
+        .collect::<io::Result<Vec<_>>>()?;
+
+    Ok(pick(&trials, goal, sample.len()))
+}
+
+/// Reads up to three slices of [`SAMPLE_SLICE_SIZE`] from the start, middle and end of the file
+pub fn sample_file(path: &str) -> io::Result<Vec<u8>> {
+    let mut file = std::fs::File::open(path)?;
+    let size = file.metadata()?.len();
+    let slice_size = SAMPLE_SLICE_SIZE as u64;
+
+    if size <= slice_size * 3 {
+        let mut sample = Vec::with_capacity(size as usize);
+        file.read_to_end(&mut sample)?;
+        return Ok(sample);
+    }
+
+    let mut sample = Vec::with_capacity(SAMPLE_SLICE_SIZE * 3);
+    for offset in [0, size / 2 - slice_size / 2, size - slice_size] {
+        file.seek(SeekFrom::Start(offset))?;
+        (&mut file).take(slice_size).read_to_end(&mut sample)?;
+    }
+
+    Ok(sample)
+}
+
+/// Samples the file and selects an algorithm for it, on tokio's blocking pool
+pub async fn select_for_file(path: &str, goal: Goal) -> io::Result<Selection> {
+    let path = path.to_owned();
+
+    tokio::task::spawn_blocking(move || select(&sample_file(&path)?, goal))
+        .await
+        .map_err(io::Error::other)?
+}
+
+fn trial(sample: &[u8], algorithm: Algorithm, level: u32) -> io::Result<Trial> {
+    let compressor = algorithm.compressor(level);
+    let mut compressed = Vec::with_capacity(sample.len() / 2);
+
+    let started = Instant::now();
+    compress_stream_with(sample, &mut compressed, compressor.as_ref())?;
+    let seconds = started.elapsed().as_secs_f64().max(f64::EPSILON);
+
+    Ok(Trial {
+        algorithm,
+        level: compressor.level(),
+        ratio: sample.len() as f64 / compressed.len().max(1) as f64,
+        throughput: sample.len() as f64 / 1_000_000.0 / seconds,
+    })
+}
+
+fn pick(trials: &[Trial], goal: Goal, sample_size: usize) -> Selection {
+    let best_ratio = |a: &&Trial, b: &&Trial| a.ratio.total_cmp(&b.ratio).then(a.throughput.total_cmp(&b.throughput));
+    let fastest = trials
+        .iter()
+        .max_by(|a, b| a.throughput.total_cmp(&b.throughput))
+        .expect("there is always at least one candidate");
+
+    let (winner, why) = match goal {
+        Goal::Ratio => (
+            trials.iter().max_by(best_ratio).unwrap_or(fastest),
+            "best ratio".to_string(),
+        ),
+        Goal::Speed => (fastest, "fastest".to_string()),
+        Goal::Balanced => (
+            trials
+                .iter()
+                .filter(|trial| trial.throughput >= fastest.throughput * BALANCED_MIN_SPEED_SHARE)
+                .max_by(best_ratio)
+                .unwrap_or(fastest),
+            format!(
+                "best ratio among candidates at least {:.0}% as fast as the fastest ({} level {} at {:.1} MB/s)",
+                BALANCED_MIN_SPEED_SHARE * 100.0,
+                fastest.algorithm,
+                fastest.level,
+                fastest.throughput
+            ),
+        ),
+    };
+
+    Selection {
+        algorithm: winner.algorithm,
+        level: winner.level,
+        goal,
+        reason: format!(
+            "{} level {}: ratio {:.2} at {:.1} MB/s on a {} KiB sample, {} of {} candidates",
+            winner.algorithm,
+            winner.level,
+            winner.ratio,
+            winner.throughput,
+            sample_size / 1024,
+            why,
+            trials.len()
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trial(algorithm: Algorithm, level: u32, ratio: f64, throughput: f64) -> Trial {
+        Trial {
+            algorithm,
+            level,
+            ratio,
+            throughput,
+        }
+    }
+
+    #[test]
+    fn test_pick_follows_goal() {
+        let trials = [
+            trial(Algorithm::Lz4, 0, 2.0, 500.0),
+            trial(Algorithm::Zstd, 3, 3.0, 200.0),
+            trial(Algorithm::Xz, 6, 4.0, 10.0),
+        ];
+
+        assert_eq!(pick(&trials, Goal::Ratio, 1024).algorithm, Algorithm::Xz);
+        assert_eq!(pick(&trials, Goal::Speed, 1024).algorithm, Algorithm::Lz4);
+
+        let balanced = pick(&trials, Goal::Balanced, 1024);
+        assert_eq!((balanced.algorithm, balanced.level), (Algorithm::Zstd, 3));
+        assert!(balanced.reason.starts_with("zstd level 3: ratio 3.00"), "{}", balanced.reason);
+    }
+
+    #[test]
+    fn test_select_on_real_sample() -> io::Result<()> {
+        let sample = "The quick brown fox jumps over the lazy dog. ".repeat(2000);
+
+        let selection = select(sample.as_bytes(), Goal::Ratio)?;
+        assert!(CANDIDATES.contains(&(selection.algorithm, selection.level)));
+        assert_eq!(selection.goal, Goal::Ratio);
+        assert_eq!("Balanced".parse(), Ok(Goal::Balanced));
+        assert!("smallest".parse::<Goal>().is_err());
+
+        Ok(())
+    }
+}
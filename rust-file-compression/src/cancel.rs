@@ -0,0 +1,69 @@
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Handle to stop a running compression from another thread.
+/// The work checks it between chunks, so it stops shortly after [`CancellationToken::cancel`].
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Fails with the error [`is_cancellation`] recognizes once cancelled
+    pub fn check(&self) -> io::Result<()> {
+        match self.is_cancelled() {
+            true => Err(io::Error::other(Cancelled)),
+            false => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Compression was cancelled")
+    }
+}
+
+impl Error for Cancelled {}
+
+/// Whether the error comes from a cancelled [`CancellationToken`] rather than a failure
+pub fn is_cancellation(error: &io::Error) -> bool {
+    error.get_ref().is_some_and(|inner| inner.is::<Cancelled>())
+}
+
+/// Reader that fails once its token is cancelled, for loops the library doesn't drive itself
+pub struct CancellableReader<R> {
+    inner: R,
+    cancellation: CancellationToken,
+}
+
+impl<R: Read> CancellableReader<R> {
+    pub fn new(inner: R, cancellation: CancellationToken) -> Self {
+        Self { inner, cancellation }
+    }
+}
+
+impl<R: Read> Read for CancellableReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.cancellation.check()?;
+        self.inner.read(buf)
+    }
+}
@@ -0,0 +1,214 @@
+use std::io::{self, Write};
+use std::time::Duration;
+
+use crate::algorithm::Algorithm;
+
+/// Buffer size handed to the brotli writer
+const BROTLI_BUFFER_SIZE: usize = 4096;
+/// Brotli window size (log2), 22 is the reference encoder's default
+const BROTLI_LG_WINDOW: u32 = 22;
+
+/// An encoder that must be explicitly finished to write its trailer
+pub trait Encoder: Write + Send {
+    fn finish(self: Box<Self>) -> io::Result<()>;
+}
+
+/// Creates encoders for one algorithm at one level
+pub trait Compressor: Send + Sync {
+    fn algorithm(&self) -> Algorithm;
+
+    /// The level actually used, after normalization
+    fn level(&self) -> u32;
+
+    /// Wraps `writer` so that everything written to the result is compressed into it
+    fn encoder<'a>(&self, writer: Box<dyn Write + Send + 'a>) -> io::Result<Box<dyn Encoder + 'a>>;
+
+    /// CPU time its encoders spent on threads other than the caller's, e.g. a thread pool
+    fn offloaded_cpu_time(&self) -> Duration {
+        Duration::ZERO
+    }
+}
+
+pub struct GzipCompressor {
+    level: flate2::Compression,
+}
+
+impl GzipCompressor {
+    pub fn new(level: flate2::Compression) -> Self {
+        Self { level }
+    }
+}
+
+impl Compressor for GzipCompressor {
+    fn algorithm(&self) -> Algorithm {
+        Algorithm::Gzip
+    }
+
+    fn level(&self) -> u32 {
+        self.level.level()
+    }
+
+    fn encoder<'a>(&self, writer: Box<dyn Write + Send + 'a>) -> io::Result<Box<dyn Encoder + 'a>> {
+        Ok(Box::new(flate2::write::GzEncoder::new(writer, self.level)))
+    }
+}
+
+impl<W: Write + Send> Encoder for flate2::write::GzEncoder<W> {
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        flate2::write::GzEncoder::finish(*self)?.flush()
+    }
+}
+
+pub struct ZstdCompressor {
+    level: i32,
+}
+
+impl ZstdCompressor {
+    pub fn new(level: i32) -> Self {
+        Self { level }
+    }
+}
+
+impl Compressor for ZstdCompressor {
+    fn algorithm(&self) -> Algorithm {
+        Algorithm::Zstd
+    }
+
+    fn level(&self) -> u32 {
+        self.level as u32
+    }
+
+    fn encoder<'a>(&self, writer: Box<dyn Write + Send + 'a>) -> io::Result<Box<dyn Encoder + 'a>> {
+        Ok(Box::new(zstd::stream::write::Encoder::new(writer, self.level)?))
+    }
+}
+
+impl<W: Write + Send> Encoder for zstd::stream::write::Encoder<'_, W> {
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        zstd::stream::write::Encoder::finish(*self)?.flush()
+    }
+}
+
+pub struct BrotliCompressor {
+    quality: u32,
+}
+
+impl BrotliCompressor {
+    pub fn new(quality: u32) -> Self {
+        Self { quality }
+    }
+}
+
+impl Compressor for BrotliCompressor {
+    fn algorithm(&self) -> Algorithm {
+        Algorithm::Brotli
+    }
+
+    fn level(&self) -> u32 {
+        self.quality
+    }
+
+    fn encoder<'a>(&self, writer: Box<dyn Write + Send + 'a>) -> io::Result<Box<dyn Encoder + 'a>> {
+        Ok(Box::new(brotli::CompressorWriter::new(
+            writer,
+            BROTLI_BUFFER_SIZE,
+            self.quality,
+            BROTLI_LG_WINDOW,
+        )))
+    }
+}
+
+impl<W: Write + Send> Encoder for brotli::CompressorWriter<W> {
+    fn finish(mut self: Box<Self>) -> io::Result<()> {
+        // `into_inner` swallows errors, so flush first to surface anything pending
+        self.flush()?;
+        self.into_inner().flush()
+    }
+}
+
+pub struct XzCompressor {
+    level: u32,
+}
+
+impl XzCompressor {
+    pub fn new(level: u32) -> Self {
+        Self { level }
+    }
+}
+
+impl Compressor for XzCompressor {
+    fn algorithm(&self) -> Algorithm {
+        Algorithm::Xz
+    }
+
+    fn level(&self) -> u32 {
+        self.level
+    }
+
+    fn encoder<'a>(&self, writer: Box<dyn Write + Send + 'a>) -> io::Result<Box<dyn Encoder + 'a>> {
+        Ok(Box::new(xz2::write::XzEncoder::new(writer, self.level)))
+    }
+}
+
+impl<W: Write + Send> Encoder for xz2::write::XzEncoder<W> {
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        xz2::write::XzEncoder::finish(*self)?.flush()
+    }
+}
+
+pub struct Bzip2Compressor {
+    level: u32,
+}
+
+impl Bzip2Compressor {
+    pub fn new(level: u32) -> Self {
+        Self { level }
+    }
+}
+
+impl Compressor for Bzip2Compressor {
+    fn algorithm(&self) -> Algorithm {
+        Algorithm::Bzip2
+    }
+
+    fn level(&self) -> u32 {
+        self.level
+    }
+
+    fn encoder<'a>(&self, writer: Box<dyn Write + Send + 'a>) -> io::Result<Box<dyn Encoder + 'a>> {
+        Ok(Box::new(bzip2::write::BzEncoder::new(
+            writer,
+            bzip2::Compression::new(self.level),
+        )))
+    }
+}
+
+impl<W: Write + Send> Encoder for bzip2::write::BzEncoder<W> {
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        bzip2::write::BzEncoder::finish(*self)?.flush()
+    }
+}
+
+pub struct Lz4Compressor;
+
+impl Compressor for Lz4Compressor {
+    fn algorithm(&self) -> Algorithm {
+        Algorithm::Lz4
+    }
+
+    fn level(&self) -> u32 {
+        0
+    }
+
+    fn encoder<'a>(&self, writer: Box<dyn Write + Send + 'a>) -> io::Result<Box<dyn Encoder + 'a>> {
+        Ok(Box::new(lz4_flex::frame::FrameEncoder::new(writer)))
+    }
+}
+
+impl<W: Write + Send> Encoder for lz4_flex::frame::FrameEncoder<W> {
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        lz4_flex::frame::FrameEncoder::finish(*self)
+            .map_err(io::Error::other)?
+            .flush()
+    }
+}
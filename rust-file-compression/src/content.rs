@@ -0,0 +1,161 @@
+use std::io;
+use std::sync::Arc;
+
+use crate::algorithm::Algorithm;
+use crate::auto::sample_file;
+use crate::compress_stream_with;
+use crate::compressor::Compressor;
+
+/// Above this many bits of entropy per byte the data is treated as random, no trial needed
+pub const MAX_ENTROPY: f64 = 7.9;
+
+/// Signatures of formats that are already compressed, as (offset, magic bytes, name)
+const COMPRESSED_FORMATS: [(usize, &[u8], &str); 14] = [
+    (0, &[0xff, 0xd8, 0xff], "jpeg"),
+    (0, &[0x89, b'P', b'N', b'G'], "png"),
+    (0, b"GIF8", "gif"),
+    (8, b"WEBP", "webp"),
+    // ISO base media: mp4, mov, heic, ...
+    (4, b"ftyp", "mp4"),
+    (0, &[0x1a, 0x45, 0xdf, 0xa3], "matroska"),
+    (0, b"OggS", "ogg"),
+    (0, b"ID3", "mp3"),
+    (0, b"fLaC", "flac"),
+    (0, &[b'P', b'K', 0x03, 0x04], "zip"),
+    (0, &[0x37, 0x7a, 0xbc, 0xaf, 0x27, 0x1c], "7z"),
+    (0, b"Rar!", "rar"),
+    (0, b"%PDF", "pdf"),
+    (0, &[0x1f, 0x9d], "compress"),
+];
+
+/// Whether compressing some content is worth it
+#[derive(Debug, Clone, PartialEq)]
+pub enum Assessment {
+    Compressible { estimated_ratio: f64 },
+    Incompressible { reason: String },
+}
+
+/// Names the already-compressed format the header belongs to, if any
+pub fn compressed_format(header: &[u8]) -> Option<&'static str> {
+    if let Some(algorithm) = Algorithm::detect(header) {
+        return Some(algorithm.name());
+    }
+
+    COMPRESSED_FORMATS
+        .iter()
+        .find(|(offset, magic, _)| header.get(*offset..).is_some_and(|rest| rest.starts_with(magic)))
+        .map(|(_, _, name)| *name)
+}
+
+/// Shannon entropy in bits per byte, from 0 (constant) to 8 (random)
+pub fn entropy(data: &[u8]) -> f64 {
+    if data.is_empty() {
+        return 0.0;
+    }
+
+    let mut counts = [0usize; 256];
+    for byte in data {
+        counts[*byte as usize] += 1;
+    }
+
+    let len = data.len() as f64;
+    counts
+        .iter()
+        .filter(|count| **count > 0)
+        .map(|count| {
+            let p = *count as f64 / len;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+/// Decides from a sample whether `compressor` would beat `min_ratio`.
+/// Known formats and random-looking data are rejected before trying to compress the sample.
+pub fn assess(sample: &[u8], compressor: &dyn Compressor, min_ratio: f64) -> io::Result<Assessment> {
+    if let Some(format) = compressed_format(sample) {
+        return Ok(Assessment::Incompressible {
+            reason: format!("content is already compressed ({format})"),
+        });
+    }
+
+    let entropy = entropy(sample);
+    if entropy > MAX_ENTROPY {
+        return Ok(Assessment::Incompressible {
+            reason: format!("content looks random ({entropy:.2} bits per byte)"),
+        });
+    }
+
+    let mut compressed = Vec::with_capacity(sample.len() / 2);
+    compress_stream_with(sample, &mut compressed, compressor)?;
+    let estimated_ratio = sample.len() as f64 / compressed.len().max(1) as f64;
+
+    if estimated_ratio < min_ratio {
+        return Ok(Assessment::Incompressible {
+            reason: format!("estimated ratio {estimated_ratio:.2} is below {min_ratio:.2}"),
+        });
+    }
+
+    Ok(Assessment::Compressible { estimated_ratio })
+}
+
+/// Samples the file and assesses it, on tokio's blocking pool
+pub async fn assess_file(path: &str, compressor: Arc<dyn Compressor>, min_ratio: f64) -> io::Result<Assessment> {
+    let path = path.to_owned();
+
+    tokio::task::spawn_blocking(move || assess(&sample_file(&path)?, compressor.as_ref(), min_ratio))
+        .await
+        .map_err(io::Error::other)?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compressed_format() {
+        assert_eq!(compressed_format(&[0xff, 0xd8, 0xff, 0xe0, 0, 0x10]), Some("jpeg"));
+        assert_eq!(compressed_format(b"\0\0\0\x18ftypmp42"), Some("mp4"));
+        assert_eq!(compressed_format(b"RIFF\0\0\0\0WEBPVP8 "), Some("webp"));
+        assert_eq!(compressed_format(&[0x1f, 0x8b, 0x08]), Some("gzip"));
+        assert_eq!(compressed_format(b"plain text"), None);
+        assert_eq!(compressed_format(b""), None);
+    }
+
+    #[test]
+    fn test_entropy() {
+        assert_eq!(entropy(b""), 0.0);
+        assert_eq!(entropy(b"aaaa"), 0.0);
+        assert_eq!(entropy(b"abab"), 1.0);
+
+        let all_bytes: Vec<u8> = (0..=255).collect();
+        assert_eq!(entropy(&all_bytes), 8.0);
+    }
+
+    #[test]
+    fn test_assess() -> io::Result<()> {
+        let compressor = Algorithm::Gzip.compressor(6);
+
+        let text = "Lorem ipsum dolor sit amet, consectetur adipiscing elit. ".repeat(500);
+        assert!(matches!(
+            assess(text.as_bytes(), compressor.as_ref(), 1.1)?,
+            Assessment::Compressible { estimated_ratio } if estimated_ratio > 10.0
+        ));
+
+        // Already gzip-compressed text no longer compresses
+        let mut gzipped = Vec::new();
+        compress_stream_with(text.as_bytes(), &mut gzipped, compressor.as_ref())?;
+        assert!(matches!(
+            assess(&gzipped, compressor.as_ref(), 1.1)?,
+            Assessment::Incompressible { reason } if reason.contains("gzip")
+        ));
+
+        // Low entropy, but every byte pattern is unique enough that gzip can't reach 50:1
+        let sequence: Vec<u8> = (0..4096u32).flat_map(|i| i.to_le_bytes()).collect();
+        assert!(matches!(
+            assess(&sequence, compressor.as_ref(), 50.0)?,
+            Assessment::Incompressible { reason } if reason.starts_with("estimated ratio")
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,39 @@
+use std::io::{self, Read};
+
+use crate::algorithm::Algorithm;
+
+/// Buffer size handed to the brotli reader
+const BROTLI_BUFFER_SIZE: usize = 4096;
+
+/// Magic bytes at the start of each format.
+/// Brotli streams have no signature, so they can only be decoded when named explicitly.
+const SIGNATURES: [(Algorithm, &[u8]); 5] = [
+    (Algorithm::Gzip, &[0x1f, 0x8b]),
+    (Algorithm::Zstd, &[0x28, 0xb5, 0x2f, 0xfd]),
+    (Algorithm::Xz, &[0xfd, 0x37, 0x7a, 0x58, 0x5a, 0x00]),
+    (Algorithm::Bzip2, b"BZh"),
+    (Algorithm::Lz4, &[0x04, 0x22, 0x4d, 0x18]),
+];
+
+impl Algorithm {
+    /// Guesses the algorithm from the first bytes of a compressed stream
+    pub fn detect(header: &[u8]) -> Option<Algorithm> {
+        SIGNATURES
+            .iter()
+            .find(|(_, magic)| header.starts_with(magic))
+            .map(|(algorithm, _)| *algorithm)
+    }
+
+    /// Wraps `reader` so that reading from the result yields the decompressed data.
+    /// Concatenated streams (e.g. multi-member gzip) are decoded as a whole.
+    pub fn decoder<'a>(&self, reader: Box<dyn Read + Send + 'a>) -> io::Result<Box<dyn Read + Send + 'a>> {
+        Ok(match self {
+            Algorithm::Gzip => Box::new(flate2::read::MultiGzDecoder::new(reader)),
+            Algorithm::Zstd => Box::new(zstd::stream::read::Decoder::new(reader)?),
+            Algorithm::Brotli => Box::new(brotli::Decompressor::new(reader, BROTLI_BUFFER_SIZE)),
+            Algorithm::Xz => Box::new(xz2::read::XzDecoder::new_multi_decoder(reader)),
+            Algorithm::Bzip2 => Box::new(bzip2::read::MultiBzDecoder::new(reader)),
+            Algorithm::Lz4 => Box::new(lz4_flex::frame::FrameDecoder::new(reader)),
+        })
+    }
+}
@@ -1,27 +1,302 @@
-use flate2::write::GzEncoder;
+pub mod algorithm;
+pub mod archive;
+pub mod auto;
+pub mod cancel;
+pub mod compressor;
+pub mod content;
+pub mod decompressor;
+pub mod parallel;
+pub mod progress;
+pub mod stats;
+
+use cpu_time::ThreadTime;
 use flate2::Compression;
-use std::fs::File;
-use std::io::{self, Read, Write};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+pub use algorithm::{Algorithm, UnknownAlgorithm, UnsupportedLevel};
+pub use archive::{ArchiveEntry, ArchiveFormat, ExtractionLimits};
+pub use auto::{Goal, Selection};
+pub use cancel::{is_cancellation, CancellationToken};
+pub use compressor::{Compressor, Encoder, GzipCompressor};
+pub use parallel::ParallelGzipCompressor;
+pub use progress::Progress;
+pub use stats::CompressionStats;
+
+/// Size of the chunks read from the input while streaming
+pub const CHUNK_SIZE: usize = 64 * 1024;
 
 /// Compresses a file using gzip and saves it in the `compressed` directory
 pub async fn compress_file(input_file: &str, output_file: &str, compression_level: Compression) -> io::Result<()> {
-    let mut input = File::open(input_file)?;
-    let mut data = Vec::new();
-    input.read_to_end(&mut data)?;
+    compress_file_with(input_file, output_file, Arc::new(GzipCompressor::new(compression_level))).await
+}
 
-    let output = File::create(output_file)?;
-    let mut encoder = GzEncoder::new(output, compression_level);
-    encoder.write_all(&data)?;
+/// Compresses a file with the given compressor.
+/// The work runs on tokio's blocking pool so it never stalls the async workers.
+pub async fn compress_file_with(
+    input_file: &str,
+    output_file: &str,
+    compressor: Arc<dyn Compressor>,
+) -> io::Result<()> {
+    compress_file_with_progress(input_file, output_file, compressor, |_| {}).await?;
 
     Ok(())
 }
 
+/// Same as [`compress_file_with`], calling `on_progress` after every chunk.
+/// Returns sizes, timings and the checksum of the output.
+pub async fn compress_file_with_progress<F>(
+    input_file: &str,
+    output_file: &str,
+    compressor: Arc<dyn Compressor>,
+    on_progress: F,
+) -> io::Result<CompressionStats>
+where
+    F: FnMut(Progress) + Send + 'static,
+{
+    compress_file_cancellable(input_file, output_file, compressor, on_progress, CancellationToken::new()).await
+}
+
+/// Same as [`compress_file_with_progress`], stopping at the next chunk once `cancellation` is cancelled.
+/// The output is written next to `output_file` with a `.partial` suffix and only renamed once complete,
+/// so `output_file` never holds a truncated result. On failure the partial output is removed, and a
+/// cancellation's error satisfies [`is_cancellation`].
+pub async fn compress_file_cancellable<F>(
+    input_file: &str,
+    output_file: &str,
+    compressor: Arc<dyn Compressor>,
+    mut on_progress: F,
+    cancellation: CancellationToken,
+) -> io::Result<CompressionStats>
+where
+    F: FnMut(Progress) + Send + 'static,
+{
+    let (input_file, output_file) = (input_file.to_owned(), output_file.to_owned());
+
+    tokio::task::spawn_blocking(move || {
+        let started = Instant::now();
+        let cpu_started = ThreadTime::now();
+        let offloaded_cpu_started = compressor.offloaded_cpu_time();
+
+        let input = std::fs::File::open(input_file)?;
+        let total_bytes = input.metadata()?.len();
+        let partial_file = format!("{output_file}.partial");
+        let mut output = stats::HashingWriter::new(io::BufWriter::new(std::fs::File::create(&partial_file)?));
+        let progress = compress_stream_cancellable(
+            input,
+            &mut output,
+            compressor.as_ref(),
+            Some(total_bytes),
+            &mut on_progress,
+            &cancellation,
+        );
+        let cpu_time = cpu_started.elapsed() + (compressor.offloaded_cpu_time() - offloaded_cpu_started);
+        let sha256 = output.finalize();
+
+        match progress.and_then(|progress| std::fs::rename(&partial_file, &output_file).map(|_| progress)) {
+            Ok(progress) => Ok(CompressionStats {
+                input_size: progress.bytes_read,
+                output_size: progress.bytes_written,
+                wall_time: started.elapsed(),
+                cpu_time,
+                sha256,
+            }),
+            Err(e) => {
+                let _ = std::fs::remove_file(&partial_file);
+                Err(e)
+            }
+        }
+    })
+    .await
+    .map_err(io::Error::other)?
+}
+
+/// Gzip-compresses everything from `reader` into `writer` one chunk at a time.
+/// Returns the number of uncompressed bytes read.
+pub fn compress_stream<R: Read, W: Write>(reader: R, writer: W, compression_level: Compression) -> io::Result<u64> {
+    compress_stream_with(reader, writer, &GzipCompressor::new(compression_level))
+}
+
+/// Same as [`compress_stream`], for any [`Compressor`]
+pub fn compress_stream_with<R: Read, W: Write>(reader: R, writer: W, compressor: &dyn Compressor) -> io::Result<u64> {
+    compress_stream_with_progress(reader, writer, compressor, None, &mut |_| {}).map(|progress| progress.bytes_read)
+}
+
+/// Same as [`compress_stream_with`], calling `on_progress` after every chunk and once more when done.
+/// `total_bytes` is only used to fill in [`Progress::total_bytes`].
+pub fn compress_stream_with_progress<R: Read, W: Write>(
+    reader: R,
+    writer: W,
+    compressor: &dyn Compressor,
+    total_bytes: Option<u64>,
+    on_progress: &mut dyn FnMut(Progress),
+) -> io::Result<Progress> {
+    compress_stream_cancellable(reader, writer, compressor, total_bytes, on_progress, &CancellationToken::new())
+}
+
+/// Same as [`compress_stream_with_progress`], failing before the next chunk once `cancellation` is cancelled
+pub fn compress_stream_cancellable<R: Read, W: Write>(
+    mut reader: R,
+    mut writer: W,
+    compressor: &dyn Compressor,
+    total_bytes: Option<u64>,
+    on_progress: &mut dyn FnMut(Progress),
+    cancellation: &CancellationToken,
+) -> io::Result<Progress> {
+    let sink = Sink::default();
+    let mut encoder = compressor.encoder(Box::new(sink.clone()))?;
+    let mut buffer = vec![0; CHUNK_SIZE];
+    let mut progress = Progress {
+        total_bytes,
+        ..Progress::default()
+    };
+
+    loop {
+        cancellation.check()?;
+        let read = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        encoder.write_all(&buffer[..read])?;
+        progress.bytes_read += read as u64;
+
+        let compressed = sink.take();
+        writer.write_all(&compressed)?;
+        progress.bytes_written += compressed.len() as u64;
+        on_progress(progress);
+    }
+
+    encoder.finish()?;
+    let compressed = sink.take();
+    writer.write_all(&compressed)?;
+    writer.flush()?;
+    progress.bytes_written += compressed.len() as u64;
+    on_progress(progress);
+
+    Ok(progress)
+}
+
+/// Async counterpart of [`compress_stream`].
+/// The encoder output is drained to `writer` after every chunk so memory stays bounded.
+pub async fn compress_stream_async<R, W>(reader: R, writer: W, compression_level: Compression) -> io::Result<u64>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    compress_stream_async_with(reader, writer, &GzipCompressor::new(compression_level)).await
+}
+
+/// Same as [`compress_stream_async`], for any [`Compressor`]
+pub async fn compress_stream_async_with<R, W>(mut reader: R, mut writer: W, compressor: &dyn Compressor) -> io::Result<u64>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let sink = Sink::default();
+    let mut encoder = compressor.encoder(Box::new(sink.clone()))?;
+    let mut buffer = vec![0; CHUNK_SIZE];
+    let mut total_read = 0;
+
+    loop {
+        let read = reader.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        encoder.write_all(&buffer[..read])?;
+        total_read += read as u64;
+
+        writer.write_all(&sink.take()).await?;
+    }
+
+    encoder.finish()?;
+    writer.write_all(&sink.take()).await?;
+    writer.flush().await?;
+
+    Ok(total_read)
+}
+
+/// Decompresses `input_file` into `output_file`, detecting the format when `algorithm` is `None`.
+/// Returns the algorithm used and the number of decompressed bytes.
+pub async fn decompress_file(
+    input_file: &str,
+    output_file: &str,
+    algorithm: Option<Algorithm>,
+) -> io::Result<(Algorithm, u64)> {
+    let (input_file, output_file) = (input_file.to_owned(), output_file.to_owned());
+
+    // Decoders are blocking readers, keep them off the async workers
+    tokio::task::spawn_blocking(move || {
+        let input = std::fs::File::open(input_file)?;
+        let output = std::fs::File::create(output_file)?;
+        decompress_stream(input, io::BufWriter::new(output), algorithm)
+    })
+    .await
+    .map_err(io::Error::other)?
+}
+
+/// Decompresses `input_file` without keeping the output, to check that it is intact
+pub async fn validate_file(input_file: &str, algorithm: Option<Algorithm>) -> io::Result<(Algorithm, u64)> {
+    let input_file = input_file.to_owned();
+
+    tokio::task::spawn_blocking(move || decompress_stream(std::fs::File::open(input_file)?, io::sink(), algorithm))
+        .await
+        .map_err(io::Error::other)?
+}
+
+/// Decompresses everything from `reader` into `writer`, detecting the format when `algorithm` is `None`.
+/// Returns the algorithm used and the number of decompressed bytes.
+pub fn decompress_stream<R, W>(reader: R, mut writer: W, algorithm: Option<Algorithm>) -> io::Result<(Algorithm, u64)>
+where
+    R: Read + Send,
+    W: Write,
+{
+    let mut reader = BufReader::with_capacity(CHUNK_SIZE, reader);
+    let algorithm = match algorithm {
+        Some(algorithm) => algorithm,
+        None => Algorithm::detect(reader.fill_buf()?)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Unrecognized compression format"))?,
+    };
+
+    let mut decoder = algorithm.decoder(Box::new(reader))?;
+    let written = io::copy(&mut decoder, &mut writer)?;
+    writer.flush()?;
+
+    Ok((algorithm, written))
+}
+
+/// In-memory buffer the encoders write into. It is drained after every chunk,
+/// which keeps encoder errors separate from errors of the real writer.
+#[derive(Clone, Default)]
+struct Sink(Arc<Mutex<Vec<u8>>>);
+
+impl Sink {
+    fn take(&self) -> Vec<u8> {
+        std::mem::take(&mut *self.0.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+}
+
+impl Write for Sink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::fs;
+    use flate2::read::GzDecoder;
+    use std::fs::{self, File};
     use std::io::{self, Write};
-    use tokio;
+    use std::time::Duration;
 
     #[tokio::test]
     async fn test_compress_file() -> io::Result<()> {
@@ -45,4 +320,269 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_compress_stream_round_trip() -> io::Result<()> {
+        // Larger than a single chunk so the loop runs several times
+        let data: Vec<u8> = (0..CHUNK_SIZE * 3 + 17).map(|i| (i % 251) as u8).collect();
+
+        let mut compressed = Vec::new();
+        let read = compress_stream(data.as_slice(), &mut compressed, Compression::default())?;
+        assert_eq!(read, data.len() as u64);
+
+        let mut decompressed = Vec::new();
+        GzDecoder::new(compressed.as_slice()).read_to_end(&mut decompressed)?;
+        assert_eq!(decompressed, data);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_compress_stream_async_round_trip() -> io::Result<()> {
+        let data: Vec<u8> = (0..CHUNK_SIZE * 2 + 5).map(|i| (i % 13) as u8).collect();
+
+        let mut compressed = Vec::new();
+        let read = compress_stream_async(data.as_slice(), &mut compressed, Compression::best()).await?;
+        assert_eq!(read, data.len() as u64);
+
+        let mut decompressed = Vec::new();
+        GzDecoder::new(compressed.as_slice()).read_to_end(&mut decompressed)?;
+        assert_eq!(decompressed, data);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_every_algorithm_round_trips() -> io::Result<()> {
+        let data: Vec<u8> = (0..CHUNK_SIZE + 99).map(|i| (i % 7) as u8).collect();
+
+        for algorithm in Algorithm::ALL {
+            let compressor = algorithm.compressor(algorithm.default_level());
+            let mut compressed = Vec::new();
+            compress_stream_with(data.as_slice(), &mut compressed, compressor.as_ref())?;
+            assert!(compressed.len() < data.len(), "{algorithm} should shrink repetitive data");
+
+            let mut decompressed = Vec::new();
+            match algorithm {
+                Algorithm::Gzip => GzDecoder::new(compressed.as_slice()).read_to_end(&mut decompressed)?,
+                Algorithm::Zstd => zstd::stream::read::Decoder::new(compressed.as_slice())?.read_to_end(&mut decompressed)?,
+                Algorithm::Brotli => brotli::Decompressor::new(compressed.as_slice(), 4096).read_to_end(&mut decompressed)?,
+                Algorithm::Xz => xz2::read::XzDecoder::new(compressed.as_slice()).read_to_end(&mut decompressed)?,
+                Algorithm::Bzip2 => bzip2::read::BzDecoder::new(compressed.as_slice()).read_to_end(&mut decompressed)?,
+                Algorithm::Lz4 => lz4_flex::frame::FrameDecoder::new(compressed.as_slice()).read_to_end(&mut decompressed)?,
+            };
+            assert_eq!(decompressed, data, "{algorithm} round trip");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_decompress_stream_detects_format() -> io::Result<()> {
+        let data: Vec<u8> = (0..CHUNK_SIZE * 2).map(|i| (i % 31) as u8).collect();
+
+        for algorithm in Algorithm::ALL {
+            let mut compressed = Vec::new();
+            compress_stream_with(data.as_slice(), &mut compressed, algorithm.compressor(1).as_ref())?;
+
+            // Brotli has no magic bytes and needs to be named
+            let hint = (algorithm == Algorithm::Brotli).then_some(algorithm);
+            let mut decompressed = Vec::new();
+            let (detected, written) = decompress_stream(compressed.as_slice(), &mut decompressed, hint)?;
+
+            assert_eq!(detected, algorithm);
+            assert_eq!(written, data.len() as u64);
+            assert_eq!(decompressed, data, "{algorithm} round trip");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_decompress_stream_rejects_garbage() {
+        let result = decompress_stream(&b"definitely not compressed"[..], io::sink(), None);
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        let result = decompress_stream(&b"\x1f\x8bcorrupt gzip body"[..], io::sink(), None);
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_decompress_file() -> io::Result<()> {
+        let input_path = "test_decompress_input.txt";
+        let compressed_path = "test_decompress_input.txt.zst";
+        let restored_path = "test_decompress_restored.txt";
+
+        fs::write(input_path, "Restore me after compression.")?;
+        compress_file_with(input_path, compressed_path, Algorithm::Zstd.compressor(3).into()).await?;
+
+        assert_eq!(validate_file(compressed_path, None).await?, (Algorithm::Zstd, 29));
+        decompress_file(compressed_path, restored_path, None).await?;
+        assert_eq!(fs::read(restored_path)?, fs::read(input_path)?);
+
+        fs::remove_file(input_path)?;
+        fs::remove_file(compressed_path)?;
+        fs::remove_file(restored_path)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parallel_gzip_is_multi_member() -> io::Result<()> {
+        let data: Vec<u8> = (0..CHUNK_SIZE * 5 + 3).map(|i| (i % 97) as u8).collect();
+        let pool = Arc::new(parallel::build_thread_pool(4).map_err(io::Error::other)?);
+        let compressor = ParallelGzipCompressor::new(Compression::default(), pool).with_block_size(CHUNK_SIZE / 2);
+
+        let mut compressed = Vec::new();
+        compress_stream_with(data.as_slice(), &mut compressed, &compressor)?;
+
+        // A plain decoder stops after the first member
+        let mut first_member = Vec::new();
+        GzDecoder::new(compressed.as_slice()).read_to_end(&mut first_member)?;
+        assert_eq!(first_member.len(), CHUNK_SIZE / 2);
+
+        let mut decompressed = Vec::new();
+        decompress_stream(compressed.as_slice(), &mut decompressed, None)?;
+        assert_eq!(decompressed, data);
+
+        // Empty input still yields a valid stream
+        let mut compressed = Vec::new();
+        compress_stream_with(&b""[..], &mut compressed, &compressor)?;
+        assert_eq!(decompress_stream(compressed.as_slice(), io::sink(), None)?, (Algorithm::Gzip, 0));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_compress_file_reports_progress() -> io::Result<()> {
+        let input_path = "test_progress_input.bin";
+        let output_path = "test_progress_input.bin.xz";
+        let data: Vec<u8> = (0..CHUNK_SIZE * 4).map(|i| (i % 200) as u8).collect();
+        fs::write(input_path, &data)?;
+
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let recorded = reports.clone();
+        let compressor = Algorithm::Xz.compressor(1).into();
+        let stats =
+            compress_file_with_progress(input_path, output_path, compressor, move |p| recorded.lock().unwrap().push(p))
+                .await?;
+
+        let reports = reports.lock().unwrap();
+        // One report per chunk plus the final one
+        assert_eq!(reports.len(), 5);
+        assert!(reports.windows(2).all(|w| w[0].bytes_read <= w[1].bytes_read));
+        assert_eq!(reports[0].percent(), Some(25.0));
+
+        let last = reports.last().unwrap();
+        assert_eq!(last.percent(), Some(100.0));
+        assert_eq!(last.bytes_read, stats.input_size);
+        assert_eq!(last.bytes_written, stats.output_size);
+        assert_eq!(stats.input_size, data.len() as u64);
+        assert_eq!(stats.output_size, fs::metadata(output_path)?.len());
+
+        fs::remove_file(input_path)?;
+        fs::remove_file(output_path)?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_compress_file_cancellation() -> io::Result<()> {
+        let input_path = "test_cancel_input.bin";
+        let output_path = "test_cancel_input.bin.zst";
+        fs::write(input_path, vec![7u8; CHUNK_SIZE * 8])?;
+        // Left by an earlier compression, a cancelled one must not truncate it
+        fs::write(output_path, b"previous output")?;
+
+        // Cancelled from the second progress report on, as another task would
+        let cancellation = CancellationToken::new();
+        let canceller = cancellation.clone();
+        let compressor = Algorithm::Zstd.compressor(1).into();
+        let result = compress_file_cancellable(
+            input_path,
+            output_path,
+            compressor,
+            move |p| {
+                if p.bytes_read >= 2 * CHUNK_SIZE as u64 {
+                    canceller.cancel();
+                }
+            },
+            cancellation.clone(),
+        )
+        .await;
+
+        let error = result.unwrap_err();
+        assert!(is_cancellation(&error));
+        assert!(!is_cancellation(&io::Error::other("disk full")));
+        // The partial output doesn't outlive the compression, nor does it replace the existing one
+        assert!(!std::path::Path::new("test_cancel_input.bin.zst.partial").exists());
+        assert_eq!(fs::read(output_path)?, b"previous output");
+
+        // A token that is already cancelled stops before the first chunk
+        let mut compressed = Vec::new();
+        let error = compress_stream_cancellable(
+            &[1u8; 16][..],
+            &mut compressed,
+            &GzipCompressor::new(Compression::default()),
+            None,
+            &mut |_| {},
+            &cancellation,
+        )
+        .unwrap_err();
+        assert!(is_cancellation(&error));
+
+        fs::remove_file(input_path)?;
+        fs::remove_file(output_path)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_compress_file_stats() -> io::Result<()> {
+        use sha2::{Digest, Sha256};
+
+        let input_path = "test_stats_input.txt";
+        let output_path = "test_stats_input.txt.gz";
+        fs::write(input_path, "aaaa".repeat(CHUNK_SIZE))?;
+
+        let pool = Arc::new(parallel::build_thread_pool(2).map_err(io::Error::other)?);
+        let compressor = Arc::new(ParallelGzipCompressor::new(Compression::best(), pool));
+        let stats = compress_file_with_progress(input_path, output_path, compressor, |_| {}).await?;
+
+        let output = fs::read(output_path)?;
+        assert_eq!(stats.input_size, 4 * CHUNK_SIZE as u64);
+        assert_eq!(stats.output_size, output.len() as u64);
+        assert!(stats.ratio() > 100.0, "ratio was {}", stats.ratio());
+        assert!(stats.wall_time > Duration::ZERO);
+        assert_eq!(stats.sha256_hex(), format!("{:x}", Sha256::digest(&output)));
+
+        fs::remove_file(input_path)?;
+        fs::remove_file(output_path)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_algorithm_parsing_and_levels() {
+        assert_eq!("gzip".parse(), Ok(Algorithm::Gzip));
+        assert_eq!("ZST".parse(), Ok(Algorithm::Zstd));
+        assert_eq!("br".parse(), Ok(Algorithm::Brotli));
+        assert!("rar".parse::<Algorithm>().is_err());
+
+        assert_eq!(Algorithm::Zstd.normalize_level(19), 19);
+        assert_eq!(Algorithm::Gzip.normalize_level(19), 6);
+        assert_eq!(Algorithm::Zstd.check_level(19), Ok(19));
+        assert_eq!(
+            Algorithm::Gzip.check_level(19),
+            Err(UnsupportedLevel(Algorithm::Gzip, 19))
+        );
+        assert_eq!(Algorithm::Bzip2.compressor(0).level(), 6);
+
+        assert_eq!(Algorithm::from_content_encoding("X-GZIP"), Some(Algorithm::Gzip));
+        assert_eq!(Algorithm::from_content_encoding("deflate"), None);
+        for algorithm in Algorithm::ALL {
+            if let Some(token) = algorithm.content_encoding() {
+                assert_eq!(Algorithm::from_content_encoding(token), Some(algorithm));
+            }
+        }
+    }
 }
@@ -0,0 +1,152 @@
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use cpu_time::ThreadTime;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use rayon::prelude::*;
+use rayon::ThreadPool;
+
+use crate::algorithm::Algorithm;
+use crate::compressor::{Compressor, Encoder};
+
+/// Default size of the blocks compressed independently, same as pigz's default of 128 KiB
+pub const DEFAULT_BLOCK_SIZE: usize = 128 * 1024;
+
+/// Builds a thread pool for parallel compression
+pub fn build_thread_pool(threads: usize) -> Result<ThreadPool, rayon::ThreadPoolBuildError> {
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .thread_name(|index| format!("compression-{index}"))
+        .build()
+}
+
+/// pigz-style gzip: the input is split into blocks that are compressed on a thread pool
+/// and written out, in order, as the members of a multi-member gzip stream
+pub struct ParallelGzipCompressor {
+    level: Compression,
+    block_size: usize,
+    pool: Arc<ThreadPool>,
+    // Nanoseconds of CPU time spent by the pool on behalf of this compressor
+    cpu_time: Arc<AtomicU64>,
+}
+
+impl ParallelGzipCompressor {
+    pub fn new(level: Compression, pool: Arc<ThreadPool>) -> Self {
+        Self {
+            level,
+            block_size: DEFAULT_BLOCK_SIZE,
+            pool,
+            cpu_time: Arc::default(),
+        }
+    }
+
+    pub fn with_block_size(mut self, block_size: usize) -> Self {
+        self.block_size = block_size.max(1);
+        self
+    }
+}
+
+impl Compressor for ParallelGzipCompressor {
+    fn algorithm(&self) -> Algorithm {
+        Algorithm::Gzip
+    }
+
+    fn level(&self) -> u32 {
+        self.level.level()
+    }
+
+    fn encoder<'a>(&self, writer: Box<dyn Write + Send + 'a>) -> io::Result<Box<dyn Encoder + 'a>> {
+        Ok(Box::new(ParallelGzEncoder {
+            writer,
+            level: self.level,
+            block_size: self.block_size,
+            pool: self.pool.clone(),
+            blocks: vec![Vec::with_capacity(self.block_size)],
+            members: 0,
+            cpu_time: self.cpu_time.clone(),
+        }))
+    }
+
+    fn offloaded_cpu_time(&self) -> Duration {
+        Duration::from_nanos(self.cpu_time.load(Ordering::Relaxed))
+    }
+}
+
+/// Buffers one block per pool thread, then compresses the whole batch at once
+struct ParallelGzEncoder<W: Write> {
+    writer: W,
+    level: Compression,
+    block_size: usize,
+    pool: Arc<ThreadPool>,
+    // The last block is the one being filled
+    blocks: Vec<Vec<u8>>,
+    members: usize,
+    cpu_time: Arc<AtomicU64>,
+}
+
+impl<W: Write> ParallelGzEncoder<W> {
+    fn compress_blocks(&mut self, blocks: Vec<Vec<u8>>) -> io::Result<()> {
+        let level = self.level;
+        let cpu_time = &self.cpu_time;
+        let members = self.pool.install(|| {
+            blocks
+                .par_iter()
+                .map(|block| {
+                    let started = ThreadTime::now();
+                    let mut encoder = GzEncoder::new(Vec::with_capacity(block.len() / 2), level);
+                    encoder.write_all(block)?;
+                    let member = encoder.finish();
+                    cpu_time.fetch_add(started.elapsed().as_nanos() as u64, Ordering::Relaxed);
+                    member
+                })
+                .collect::<io::Result<Vec<_>>>()
+        })?;
+
+        for member in members {
+            self.writer.write_all(&member)?;
+            self.members += 1;
+        }
+
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for ParallelGzEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let current = self.blocks.last_mut().expect("there is always a block being filled");
+        let written = buf.len().min(self.block_size - current.len());
+        current.extend_from_slice(&buf[..written]);
+
+        if current.len() == self.block_size {
+            if self.blocks.len() >= self.pool.current_num_threads() {
+                let blocks = std::mem::take(&mut self.blocks);
+                self.compress_blocks(blocks)?;
+            }
+            self.blocks.push(Vec::with_capacity(self.block_size));
+        }
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl<W: Write + Send> Encoder for ParallelGzEncoder<W> {
+    fn finish(mut self: Box<Self>) -> io::Result<()> {
+        let mut blocks = std::mem::take(&mut self.blocks);
+        blocks.retain(|block| !block.is_empty());
+
+        // An empty input still has to produce a valid (empty) gzip member
+        if blocks.is_empty() && self.members == 0 {
+            blocks.push(Vec::new());
+        }
+
+        self.compress_blocks(blocks)?;
+        self.writer.flush()
+    }
+}
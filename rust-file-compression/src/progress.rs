@@ -0,0 +1,21 @@
+/// Snapshot of a running compression, reported after every chunk
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Progress {
+    /// Uncompressed bytes consumed from the input so far
+    pub bytes_read: u64,
+    /// Compressed bytes handed to the output so far
+    pub bytes_written: u64,
+    /// Size of the input, when known up front
+    pub total_bytes: Option<u64>,
+}
+
+impl Progress {
+    /// Share of the input consumed, between 0 and 100. `None` when the total is unknown.
+    pub fn percent(&self) -> Option<f64> {
+        match self.total_bytes {
+            Some(0) => Some(100.0),
+            Some(total) => Some((self.bytes_read as f64 / total as f64 * 100.0).min(100.0)),
+            None => None,
+        }
+    }
+}
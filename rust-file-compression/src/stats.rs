@@ -0,0 +1,72 @@
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::time::Duration;
+
+use sha2::{Digest, Sha256};
+
+/// Summary of a finished compression
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompressionStats {
+    pub input_size: u64,
+    pub output_size: u64,
+    pub wall_time: Duration,
+    /// CPU time of the compressing thread plus any pool threads it used
+    pub cpu_time: Duration,
+    /// SHA-256 of the compressed output
+    pub sha256: [u8; 32],
+}
+
+impl CompressionStats {
+    /// Input size divided by output size, e.g. 4.0 when the output is a quarter of the input
+    pub fn ratio(&self) -> f64 {
+        if self.output_size == 0 {
+            return 0.0;
+        }
+        self.input_size as f64 / self.output_size as f64
+    }
+
+    pub fn sha256_hex(&self) -> String {
+        to_hex(&self.sha256)
+    }
+}
+
+/// Lowercase hex encoding of a digest
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .fold(String::with_capacity(bytes.len() * 2), |mut hex, byte| {
+            let _ = write!(hex, "{byte:02x}");
+            hex
+        })
+}
+
+/// Passes writes through while hashing them
+pub(crate) struct HashingWriter<W: Write> {
+    writer: W,
+    hasher: Sha256,
+}
+
+impl<W: Write> HashingWriter<W> {
+    pub(crate) fn new(writer: W) -> Self {
+        Self {
+            writer,
+            hasher: Sha256::new(),
+        }
+    }
+
+    pub(crate) fn finalize(self) -> [u8; 32] {
+        self.hasher.finalize().into()
+    }
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.writer.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
@@ -0,0 +1,4 @@
+-- Add down migration script here
+
+DROP TABLE decompressions;
+DROP TYPE decompression_mode_enum;
@@ -0,0 +1,13 @@
+-- Add migration script here
+
+CREATE TYPE decompression_mode_enum AS ENUM ('restore', 'validate');
+CREATE TABLE decompressions (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    compressed_file_id UUID REFERENCES compressed_files(id) NOT NULL,
+    mode decompression_mode_enum NOT NULL,
+    status status_enum NOT NULL,
+    alg VARCHAR(255) NOT NULL,
+    size BIGINT,
+    error TEXT,
+    created_at TIMESTAMPTZ DEFAULT now() NOT NULL
+);
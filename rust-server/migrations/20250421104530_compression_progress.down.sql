@@ -0,0 +1,6 @@
+-- Add down migration script here
+
+ALTER TABLE compressed_files
+    DROP COLUMN bytes_processed,
+    DROP COLUMN total_bytes,
+    DROP COLUMN percent;
@@ -0,0 +1,6 @@
+-- Add migration script here
+
+ALTER TABLE compressed_files
+    ADD COLUMN bytes_processed BIGINT DEFAULT 0 NOT NULL,
+    ADD COLUMN total_bytes BIGINT,
+    ADD COLUMN percent DOUBLE PRECISION DEFAULT 0 NOT NULL;
@@ -0,0 +1,9 @@
+-- Add down migration script here
+
+ALTER TABLE compressed_files
+    DROP COLUMN input_size,
+    DROP COLUMN output_size,
+    DROP COLUMN ratio,
+    DROP COLUMN wall_time_ms,
+    DROP COLUMN cpu_time_ms,
+    DROP COLUMN sha256;
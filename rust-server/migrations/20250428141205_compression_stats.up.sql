@@ -0,0 +1,9 @@
+-- Add migration script here
+
+ALTER TABLE compressed_files
+    ADD COLUMN input_size BIGINT,
+    ADD COLUMN output_size BIGINT,
+    ADD COLUMN ratio DOUBLE PRECISION,
+    ADD COLUMN wall_time_ms BIGINT,
+    ADD COLUMN cpu_time_ms BIGINT,
+    ADD COLUMN sha256 CHAR(64);
@@ -0,0 +1,5 @@
+-- Add down migration script here
+
+ALTER TABLE compressed_files
+    DROP COLUMN goal,
+    DROP COLUMN selection_reason;
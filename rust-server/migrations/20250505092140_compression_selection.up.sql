@@ -0,0 +1,5 @@
+-- Add migration script here
+
+ALTER TABLE compressed_files
+    ADD COLUMN goal VARCHAR(16),
+    ADD COLUMN selection_reason TEXT;
@@ -0,0 +1,14 @@
+-- Add down migration script here
+
+-- Postgres can't drop an enum value, so the type is rebuilt without it
+ALTER TABLE compressed_files DROP COLUMN skip_reason;
+UPDATE compressed_files SET status = 'failed' WHERE status = 'skipped';
+UPDATE decompressions SET status = 'failed' WHERE status = 'skipped';
+
+ALTER TYPE status_enum RENAME TO status_enum_old;
+CREATE TYPE status_enum AS ENUM ('compressing', 'passed', 'failed');
+ALTER TABLE compressed_files ALTER COLUMN status DROP DEFAULT;
+ALTER TABLE compressed_files ALTER COLUMN status TYPE status_enum USING status::text::status_enum;
+ALTER TABLE compressed_files ALTER COLUMN status SET DEFAULT 'compressing';
+ALTER TABLE decompressions ALTER COLUMN status TYPE status_enum USING status::text::status_enum;
+DROP TYPE status_enum_old;
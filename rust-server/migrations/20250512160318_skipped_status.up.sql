@@ -0,0 +1,4 @@
+-- Add migration script here
+
+ALTER TYPE status_enum ADD VALUE 'skipped';
+ALTER TABLE compressed_files ADD COLUMN skip_reason TEXT;
@@ -0,0 +1,9 @@
+-- Add migration script here
+
+CREATE TABLE archive_members (
+    compressed_file_id UUID REFERENCES compressed_files(id) NOT NULL,
+    file_id UUID REFERENCES files(id) NOT NULL,
+    name VARCHAR(255) NOT NULL,
+    position INTEGER NOT NULL,
+    PRIMARY KEY (compressed_file_id, file_id)
+);
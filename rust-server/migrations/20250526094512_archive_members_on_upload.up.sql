@@ -0,0 +1,5 @@
+-- Add migration script here
+
+ALTER TABLE files
+    ADD COLUMN parent_id UUID REFERENCES files(id),
+    ADD COLUMN archive_path TEXT;
@@ -0,0 +1,3 @@
+-- Add migration script here
+
+ALTER TABLE files ADD COLUMN sha256 CHAR(64);
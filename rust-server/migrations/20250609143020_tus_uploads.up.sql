@@ -0,0 +1,11 @@
+-- Add migration script here
+
+CREATE TABLE tus_uploads (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    file_name VARCHAR(255) NOT NULL,
+    upload_length BIGINT NOT NULL,
+    upload_offset BIGINT DEFAULT 0 NOT NULL,
+    metadata TEXT,
+    file_id UUID REFERENCES files(id),
+    created_at TIMESTAMPTZ DEFAULT now() NOT NULL
+);
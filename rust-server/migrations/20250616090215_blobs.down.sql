@@ -0,0 +1,6 @@
+-- Add down migration script here
+
+DROP INDEX compressed_files_input_idx;
+ALTER TABLE compressed_files DROP COLUMN input_sha256;
+ALTER TABLE files DROP COLUMN blob_sha256;
+DROP TABLE blobs;
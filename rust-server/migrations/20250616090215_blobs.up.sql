@@ -0,0 +1,14 @@
+-- Add migration script here
+
+CREATE TABLE blobs (
+    sha256 CHAR(64) PRIMARY KEY,
+    size BIGINT NOT NULL,
+    ref_count INTEGER DEFAULT 0 NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT now() NOT NULL
+);
+
+-- Files stored before blobs keep their own copy under `file_ref`
+ALTER TABLE files ADD COLUMN blob_sha256 CHAR(64) REFERENCES blobs(sha256);
+
+ALTER TABLE compressed_files ADD COLUMN input_sha256 CHAR(64);
+CREATE INDEX compressed_files_input_idx ON compressed_files (input_sha256, alg, level);
@@ -0,0 +1,7 @@
+-- Add down migration script here
+
+DROP INDEX compressed_files_created_at_idx;
+DROP INDEX files_size_idx;
+DROP INDEX files_created_at_idx;
+ALTER TABLE compressed_files DROP COLUMN created_at;
+ALTER TABLE files DROP COLUMN created_at;
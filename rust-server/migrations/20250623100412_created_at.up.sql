@@ -0,0 +1,10 @@
+-- Add migration script here
+
+-- Existing rows get the migration time, their real creation time was never recorded
+ALTER TABLE files ADD COLUMN created_at TIMESTAMPTZ DEFAULT now() NOT NULL;
+ALTER TABLE compressed_files ADD COLUMN created_at TIMESTAMPTZ DEFAULT now() NOT NULL;
+
+-- Keyset pagination orders by the sort key, then by ID to break ties
+CREATE INDEX files_created_at_idx ON files (created_at, id);
+CREATE INDEX files_size_idx ON files (size, id);
+CREATE INDEX compressed_files_created_at_idx ON compressed_files (created_at, id);
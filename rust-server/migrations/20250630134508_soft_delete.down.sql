@@ -0,0 +1,23 @@
+-- Add down migration script here
+
+ALTER TABLE files
+    DROP CONSTRAINT files_parent_id_fkey,
+    ADD CONSTRAINT files_parent_id_fkey FOREIGN KEY (parent_id) REFERENCES files(id);
+ALTER TABLE tus_uploads
+    DROP CONSTRAINT tus_uploads_file_id_fkey,
+    ADD CONSTRAINT tus_uploads_file_id_fkey FOREIGN KEY (file_id) REFERENCES files(id);
+ALTER TABLE archive_members
+    DROP CONSTRAINT archive_members_file_id_fkey,
+    ADD CONSTRAINT archive_members_file_id_fkey FOREIGN KEY (file_id) REFERENCES files(id),
+    DROP CONSTRAINT archive_members_compressed_file_id_fkey,
+    ADD CONSTRAINT archive_members_compressed_file_id_fkey
+        FOREIGN KEY (compressed_file_id) REFERENCES compressed_files(id);
+ALTER TABLE decompressions
+    DROP CONSTRAINT decompressions_compressed_file_id_fkey,
+    ADD CONSTRAINT decompressions_compressed_file_id_fkey
+        FOREIGN KEY (compressed_file_id) REFERENCES compressed_files(id);
+
+DROP INDEX compressed_files_deleted_at_idx;
+DROP INDEX files_deleted_at_idx;
+ALTER TABLE compressed_files DROP COLUMN deleted_at;
+ALTER TABLE files DROP COLUMN deleted_at;
@@ -0,0 +1,30 @@
+-- Add migration script here
+
+-- Deleted rows are hidden, and purged for good once the restore window has passed
+ALTER TABLE files ADD COLUMN deleted_at TIMESTAMPTZ;
+ALTER TABLE compressed_files ADD COLUMN deleted_at TIMESTAMPTZ;
+CREATE INDEX files_deleted_at_idx ON files (deleted_at) WHERE deleted_at IS NOT NULL;
+CREATE INDEX compressed_files_deleted_at_idx ON compressed_files (deleted_at) WHERE deleted_at IS NOT NULL;
+
+-- Purging a row takes the rows that only describe it along
+ALTER TABLE decompressions
+    DROP CONSTRAINT decompressions_compressed_file_id_fkey,
+    ADD CONSTRAINT decompressions_compressed_file_id_fkey
+        FOREIGN KEY (compressed_file_id) REFERENCES compressed_files(id) ON DELETE CASCADE;
+ALTER TABLE archive_members
+    DROP CONSTRAINT archive_members_compressed_file_id_fkey,
+    ADD CONSTRAINT archive_members_compressed_file_id_fkey
+        FOREIGN KEY (compressed_file_id) REFERENCES compressed_files(id) ON DELETE CASCADE,
+    DROP CONSTRAINT archive_members_file_id_fkey,
+    ADD CONSTRAINT archive_members_file_id_fkey
+        FOREIGN KEY (file_id) REFERENCES files(id) ON DELETE CASCADE;
+ALTER TABLE tus_uploads
+    DROP CONSTRAINT tus_uploads_file_id_fkey,
+    ADD CONSTRAINT tus_uploads_file_id_fkey
+        FOREIGN KEY (file_id) REFERENCES files(id) ON DELETE CASCADE;
+
+-- Files extracted from an archive outlive it
+ALTER TABLE files
+    DROP CONSTRAINT files_parent_id_fkey,
+    ADD CONSTRAINT files_parent_id_fkey
+        FOREIGN KEY (parent_id) REFERENCES files(id) ON DELETE SET NULL;
@@ -0,0 +1,4 @@
+-- Add down migration script here
+
+DROP INDEX compressed_files_file_id_idx;
+ALTER TABLE compressed_files DROP CONSTRAINT compressed_files_source_check;
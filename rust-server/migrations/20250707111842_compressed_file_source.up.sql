@@ -0,0 +1,15 @@
+-- Add migration script here
+
+-- Rows were only linked by `file_ref` so far
+UPDATE compressed_files
+SET file_id = files.id
+FROM files
+WHERE compressed_files.file_id IS NULL AND files.file_ref = compressed_files.file_ref;
+
+-- Archives bundle several files and have no single source, everything else must have one.
+-- Rows whose file was lost before they were linked are left as they are.
+ALTER TABLE compressed_files
+    ADD CONSTRAINT compressed_files_source_check
+        CHECK (file_id IS NOT NULL OR alg IN ('tar.gz', 'tar.zst', 'zip')) NOT VALID;
+
+CREATE INDEX compressed_files_file_id_idx ON compressed_files (file_id);
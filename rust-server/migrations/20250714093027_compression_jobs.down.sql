@@ -0,0 +1,5 @@
+-- Add down migration script here
+
+ALTER TABLE compressed_files DROP COLUMN error;
+DROP TABLE compression_jobs;
+DROP TYPE job_kind_enum;
@@ -0,0 +1,27 @@
+-- Add migration script here
+
+CREATE TYPE job_kind_enum AS ENUM ('compress', 'archive');
+
+CREATE TABLE compression_jobs (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    compressed_file_id UUID NOT NULL UNIQUE REFERENCES compressed_files(id) ON DELETE CASCADE,
+    kind job_kind_enum NOT NULL,
+    attempts INTEGER DEFAULT 0 NOT NULL,
+    max_attempts INTEGER NOT NULL,
+    run_at TIMESTAMPTZ DEFAULT now() NOT NULL,
+    -- Worker running the job, the job goes back to the queue once `locked_until` has passed
+    locked_by VARCHAR(255),
+    locked_until TIMESTAMPTZ,
+    last_error TEXT,
+    created_at TIMESTAMPTZ DEFAULT now() NOT NULL
+);
+
+CREATE INDEX compression_jobs_run_at_idx ON compression_jobs (run_at);
+
+ALTER TABLE compressed_files ADD COLUMN error TEXT;
+
+-- Compressions left behind by the in-process tasks are queued again
+INSERT INTO compression_jobs (compressed_file_id, kind, max_attempts)
+SELECT id, CASE WHEN file_id IS NULL THEN 'archive' ELSE 'compress' END::job_kind_enum, 3
+FROM compressed_files
+WHERE status = 'compressing';
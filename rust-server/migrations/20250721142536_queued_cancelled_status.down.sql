@@ -0,0 +1,14 @@
+-- Add down migration script here
+
+-- Postgres can't drop an enum value, so the type is rebuilt without them
+UPDATE compressed_files SET status = 'compressing' WHERE status = 'queued';
+UPDATE compressed_files SET status = 'failed' WHERE status = 'cancelled';
+UPDATE decompressions SET status = 'failed' WHERE status IN ('queued', 'cancelled');
+
+ALTER TYPE status_enum RENAME TO status_enum_old;
+CREATE TYPE status_enum AS ENUM ('compressing', 'passed', 'failed', 'skipped');
+ALTER TABLE compressed_files ALTER COLUMN status DROP DEFAULT;
+ALTER TABLE compressed_files ALTER COLUMN status TYPE status_enum USING status::text::status_enum;
+ALTER TABLE compressed_files ALTER COLUMN status SET DEFAULT 'compressing';
+ALTER TABLE decompressions ALTER COLUMN status TYPE status_enum USING status::text::status_enum;
+DROP TYPE status_enum_old;
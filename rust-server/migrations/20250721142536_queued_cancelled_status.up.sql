@@ -0,0 +1,5 @@
+-- Add migration script here
+
+-- Compressions wait as `queued` until a worker starts them, and can be `cancelled` before finishing
+ALTER TYPE status_enum ADD VALUE 'queued';
+ALTER TYPE status_enum ADD VALUE 'cancelled';
@@ -0,0 +1,5 @@
+-- Add down migration script here
+
+DROP TABLE webhook_deliveries;
+DROP TABLE webhooks;
+DROP TYPE delivery_status_enum;
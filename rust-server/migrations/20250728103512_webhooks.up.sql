@@ -0,0 +1,36 @@
+-- Add migration script here
+
+CREATE TYPE delivery_status_enum AS ENUM ('pending', 'delivered', 'failed');
+
+CREATE TABLE webhooks (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    url TEXT NOT NULL,
+    -- Key the deliveries are signed with, shared with the receiver
+    secret VARCHAR(255) NOT NULL,
+    -- Names of the events the webhook is subscribed to, e.g. file.uploaded
+    events TEXT[] NOT NULL,
+    active BOOLEAN DEFAULT true NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT now() NOT NULL
+);
+
+CREATE TABLE webhook_deliveries (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    webhook_id UUID NOT NULL REFERENCES webhooks(id) ON DELETE CASCADE,
+    event VARCHAR(255) NOT NULL,
+    -- Body sent to the webhook, the same on every attempt
+    payload JSONB NOT NULL,
+    status delivery_status_enum DEFAULT 'pending' NOT NULL,
+    attempts INTEGER DEFAULT 0 NOT NULL,
+    max_attempts INTEGER NOT NULL,
+    run_at TIMESTAMPTZ DEFAULT now() NOT NULL,
+    -- A delivery whose attempt didn't finish by then is picked up again
+    locked_until TIMESTAMPTZ,
+    -- Outcome of the last attempt
+    response_status INTEGER,
+    last_error TEXT,
+    delivered_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ DEFAULT now() NOT NULL
+);
+
+CREATE INDEX webhook_deliveries_pending_idx ON webhook_deliveries (run_at) WHERE status = 'pending';
+CREATE INDEX webhook_deliveries_webhook_id_idx ON webhook_deliveries (webhook_id, created_at);
@@ -0,0 +1,4 @@
+-- Add down migration script here
+
+DROP TABLE api_keys;
+DROP TABLE users;
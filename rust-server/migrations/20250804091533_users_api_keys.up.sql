@@ -0,0 +1,27 @@
+-- Add migration script here
+
+CREATE TABLE users (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    name VARCHAR(255) NOT NULL UNIQUE,
+    created_at TIMESTAMPTZ DEFAULT now() NOT NULL
+);
+
+CREATE TABLE api_keys (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name VARCHAR(255) NOT NULL,
+    -- Start of the key, to tell keys apart without storing them
+    prefix VARCHAR(16) NOT NULL,
+    -- SHA-256 of the whole key, in hex
+    key_hash VARCHAR(64) NOT NULL UNIQUE,
+    -- upload, compress, read and/or admin
+    scopes TEXT[] NOT NULL,
+    expires_at TIMESTAMPTZ,
+    last_used_at TIMESTAMPTZ,
+    revoked_at TIMESTAMPTZ,
+    -- Key that took over when this one was rotated
+    replaced_by UUID REFERENCES api_keys(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ DEFAULT now() NOT NULL
+);
+
+CREATE INDEX api_keys_user_id_idx ON api_keys (user_id);
@@ -0,0 +1,10 @@
+-- Add down migration script here
+
+DROP TABLE file_shares;
+DROP TYPE share_permission_enum;
+DROP TABLE group_members;
+DROP TABLE groups;
+
+ALTER TABLE tus_uploads DROP COLUMN owner_id;
+ALTER TABLE compressed_files DROP COLUMN owner_id;
+ALTER TABLE files DROP COLUMN owner_id;
@@ -0,0 +1,41 @@
+-- Add migration script here
+
+-- Rows made with the admin token, or before ownership existed, have no owner and are only reachable by admins
+ALTER TABLE files ADD COLUMN owner_id UUID REFERENCES users(id) ON DELETE SET NULL;
+ALTER TABLE compressed_files ADD COLUMN owner_id UUID REFERENCES users(id) ON DELETE SET NULL;
+ALTER TABLE tus_uploads ADD COLUMN owner_id UUID REFERENCES users(id) ON DELETE SET NULL;
+
+CREATE INDEX files_owner_id_idx ON files (owner_id);
+CREATE INDEX compressed_files_owner_id_idx ON compressed_files (owner_id);
+
+CREATE TABLE groups (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    name VARCHAR(255) NOT NULL UNIQUE,
+    created_at TIMESTAMPTZ DEFAULT now() NOT NULL
+);
+
+CREATE TABLE group_members (
+    group_id UUID NOT NULL REFERENCES groups(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    PRIMARY KEY (group_id, user_id)
+);
+
+CREATE INDEX group_members_user_id_idx ON group_members (user_id);
+
+CREATE TYPE share_permission_enum AS ENUM ('read', 'compress');
+
+-- A file shared with one user or one group, compress implies read
+CREATE TABLE file_shares (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    file_id UUID NOT NULL REFERENCES files(id) ON DELETE CASCADE,
+    user_id UUID REFERENCES users(id) ON DELETE CASCADE,
+    group_id UUID REFERENCES groups(id) ON DELETE CASCADE,
+    permission share_permission_enum NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT now() NOT NULL,
+    CHECK ((user_id IS NULL) <> (group_id IS NULL)),
+    UNIQUE (file_id, user_id),
+    UNIQUE (file_id, group_id)
+);
+
+CREATE INDEX file_shares_user_id_idx ON file_shares (user_id);
+CREATE INDEX file_shares_group_id_idx ON file_shares (group_id);
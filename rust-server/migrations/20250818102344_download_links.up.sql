@@ -0,0 +1,20 @@
+-- Add migration script here
+
+CREATE TABLE download_links (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    -- Links lead to either a file or a compressed file
+    file_id UUID REFERENCES files(id) ON DELETE CASCADE,
+    compressed_file_id UUID REFERENCES compressed_files(id) ON DELETE CASCADE,
+    expires_at TIMESTAMPTZ NOT NULL,
+    -- Unlimited when NULL
+    max_downloads INTEGER,
+    downloads INTEGER DEFAULT 0 NOT NULL,
+    -- Only this client address may download when set
+    allowed_ip TEXT,
+    created_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ DEFAULT now() NOT NULL,
+    CHECK ((file_id IS NULL) <> (compressed_file_id IS NULL))
+);
+
+CREATE INDEX download_links_file_id_idx ON download_links (file_id);
+CREATE INDEX download_links_compressed_file_id_idx ON download_links (compressed_file_id);
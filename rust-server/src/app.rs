@@ -1,28 +1,68 @@
 use super::handlers::{
-    compress_file_handler::CompressionHandler, upload_file_handler::UploadFileHandler,
+    api_key_handler::ApiKeyHandler, compress_file_handler::CompressionHandler,
+    content_handler::ContentHandler, deletion_handler::DeletionHandler,
+    download_link_handler::DownloadLinkHandler, event_handler::EventHandler,
+    group_handler::GroupHandler, share_handler::ShareHandler, tus_upload_handler::TusUploadHandler,
+    upload_file_handler::UploadFileHandler, user_handler::UserHandler,
+    webhook_handler::WebhookHandler,
 };
 use axum::{
-    extract::{Multipart, Path, Query, State},
-    http::StatusCode,
+    body::Body,
+    extract::{ws::WebSocketUpgrade, ConnectInfo, DefaultBodyLimit, Multipart, Path, Query, State},
+    http::{HeaderMap, StatusCode},
     middleware,
-    routing::{get, post},
-    Router,
+    routing::{delete, get, head, options, post, put},
+    Extension, Json, Router,
+};
+use rayon::ThreadPool;
+use rust_server::CancellationToken;
+use sqlx::{types::Uuid, PgPool};
+use tokio::{
+    net::TcpListener,
+    sync::{broadcast, Notify},
 };
-use sqlx::PgPool;
-use tokio::net::TcpListener;
-use tower_http::services::ServeDir;
 
 use crate::{
-    dtos::CompressionQuery,
-    helpers::{env::Env, logger::Logger},
+    dtos::{
+        ApiKeyRequest, ArchiveRequest, CompressionQuery, DecompressionQuery, DeleteQuery,
+        DownloadLinkRequest, DownloadQuery, GetFileQuery, GroupRequest, ListCompressedFilesQuery,
+        ListDeliveriesQuery, ListFilesQuery, RotateApiKeyQuery, ShareRequest, UploadQuery,
+        UserRequest, WebhookRequest,
+    },
+    helpers::{env::Env, jwt::JwtValidator, logger::Logger},
     middlewares::{auth_guard, log_requests},
+    models::{job::JobEvent, user::Principal},
+    workers::{compression_worker::CompressionWorker, webhook_worker::WebhookWorker},
+};
+use std::{
+    collections::{HashMap, HashSet},
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
 };
-use std::sync::Arc;
+
+// How often rows deleted before the restore window are looked for and purged
+const PURGE_INTERVAL: Duration = Duration::from_secs(10 * 60);
+// Job events kept for subscribers that fall behind, older ones are skipped
+const JOB_EVENTS_CAPACITY: usize = 1024;
 
 #[derive(Debug, Clone)]
 pub struct AppState {
     pub env: Arc<Env>,
     pub pool: Arc<PgPool>,
+    pub compression_pool: Arc<ThreadPool>,
+    // tus uploads currently receiving data
+    pub active_tus_uploads: Arc<Mutex<HashSet<Uuid>>>,
+    // Wakes an idle compression worker when a job is queued
+    pub job_notify: Arc<Notify>,
+    // Compressions running on this instance, by compressed file ID
+    pub running_compressions: Arc<Mutex<HashMap<Uuid, CancellationToken>>>,
+    // Job events of every instance, for the event streams
+    pub job_events: broadcast::Sender<JobEvent>,
+    // Wakes an idle webhook worker when a delivery is queued
+    pub webhook_notify: Arc<Notify>,
+    // Checks JWTs, unset when JWT_ALGORITHM isn't
+    pub jwt_validator: Option<Arc<JwtValidator>>,
 }
 
 // Renamed App struct to AppState for clarity
@@ -32,9 +72,24 @@ pub struct App {
 }
 
 impl App {
-    pub fn new(pool: Arc<PgPool>, env: Arc<Env>) -> Self {
+    pub fn new(
+        pool: Arc<PgPool>,
+        env: Arc<Env>,
+        compression_pool: Arc<ThreadPool>,
+        jwt_validator: Option<Arc<JwtValidator>>,
+    ) -> Self {
         Self {
-            state: Arc::new(AppState { pool, env }),
+            state: Arc::new(AppState {
+                pool,
+                env,
+                compression_pool,
+                active_tus_uploads: Arc::default(),
+                job_notify: Arc::default(),
+                running_compressions: Arc::default(),
+                job_events: broadcast::channel(JOB_EVENTS_CAPACITY).0,
+                webhook_notify: Arc::default(),
+                jwt_validator,
+            }),
         }
     }
 }
@@ -44,6 +99,17 @@ impl App {
         Router::new()
             .nest("/files", Self::upload_handler_routes())
             .nest("/compressed-files", Self::compression_handler_routes())
+            .nest("/webhooks", Self::webhook_handler_routes())
+            .nest("/users", Self::user_handler_routes())
+            .nest("/groups", Self::group_handler_routes())
+            .nest("/api-keys", Self::api_key_handler_routes())
+            // Every route needs an API key or token with the scope it requires
+            .layer(middleware::from_fn_with_state(
+                app_state.clone(),
+                auth_guard::auth_guard,
+            ))
+            // Download links carry their own signature, added after the guard so they skip it
+            .nest("/downloads", Self::download_link_routes())
             .layer(middleware::from_fn(log_requests::log_requests))
             .with_state(app_state)
     }
@@ -52,11 +118,26 @@ impl App {
     pub async fn run_server(self, ip_addr: &str, logger: impl Logger) {
         // Create a new Axum router
         let app = Self::route(self.state.clone());
-        // Serve the files from the uploads directory
-        let app = app.nest_service(
-            &format!("/{}", self.state.env.uploads_dir),
-            ServeDir::new(self.state.env.uploads_dir.as_str()),
-        );
+
+        // Pass on job events to the event streams
+        let state = self.state.clone();
+        tokio::spawn(async move { EventHandler::new(state).relay().await });
+
+        // Run the queued compressions, starting with those interrupted by the last shutdown
+        CompressionWorker::new(self.state.clone()).start().await;
+
+        // Send the queued webhook deliveries, including those left by the last run
+        WebhookWorker::new(self.state.clone()).start();
+
+        // Purge what was deleted before the restore window, in the background
+        let state = self.state.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(PURGE_INTERVAL);
+            loop {
+                interval.tick().await;
+                DeletionHandler::new(state.clone()).purge_expired().await;
+            }
+        });
 
         let listener = match TcpListener::bind(ip_addr).await {
             Ok(listener) => listener,
@@ -68,6 +149,9 @@ impl App {
 
         logger.log(&format!("Application is running http://{ip_addr}"));
 
+        // Download links can be restricted to the client's IP
+        let app = app.into_make_service_with_connect_info::<SocketAddr>();
+
         // Start the server
         if let Err(e) = axum::serve(listener, app).await {
             eprintln!("{e}")
@@ -78,24 +162,190 @@ impl App {
 impl App {
     fn upload_handler_routes() -> Router<Arc<AppState>> {
         Router::new()
+            .route(
+                "/",
+                get(
+                    |State(state): State<Arc<AppState>>,
+                     Extension(principal): Extension<Principal>,
+                     Query(query): Query<ListFilesQuery>| async move {
+                        let upload_file_handler = UploadFileHandler::new(state);
+                        upload_file_handler.list_files(principal, query).await
+                    },
+                ),
+            )
             .route(
                 "/upload",
                 post(
-                    |State(state): State<Arc<AppState>>, multipart: Multipart| async move {
+                    |State(state): State<Arc<AppState>>,
+                     Extension(principal): Extension<Principal>,
+                     Query(query): Query<UploadQuery>,
+                     multipart: Multipart| async move {
                         let upload_file_handler = UploadFileHandler::new(state);
-                        upload_file_handler.upload_files(multipart).await
+                        upload_file_handler
+                            .upload_files(principal, multipart, query)
+                            .await
                     },
-                ),
+                )
+                // Uploads are streamed to disk, MAX_UPLOAD_SIZE bounds each file as it is written
+                .layer(DefaultBodyLimit::disable()),
             )
             .route(
                 "/{id}",
                 get(
-                    |State(state): State<Arc<AppState>>, Path(id): Path<String>| async move {
+                    |State(state): State<Arc<AppState>>,
+                     Extension(principal): Extension<Principal>,
+                     Path(id): Path<String>,
+                     Query(query): Query<GetFileQuery>| async move {
                         let upload_file_handler = UploadFileHandler::new(state);
-                        upload_file_handler.get_file(id).await
+                        upload_file_handler.get_file(principal, id, query).await
+                    },
+                )
+                .delete(
+                    |State(state): State<Arc<AppState>>,
+                     Extension(principal): Extension<Principal>,
+                     Path(id): Path<String>,
+                     Query(query): Query<DeleteQuery>| async move {
+                        DeletionHandler::new(state)
+                            .delete_file(principal, id, query)
+                            .await
+                    },
+                ),
+            )
+            .route(
+                "/{id}/restore",
+                post(
+                    |State(state): State<Arc<AppState>>,
+                     Extension(principal): Extension<Principal>,
+                     Path(id): Path<String>| async move {
+                        DeletionHandler::new(state)
+                            .restore_file(principal, id)
+                            .await
+                    },
+                ),
+            )
+            .route(
+                "/{id}/content",
+                get(
+                    |State(state): State<Arc<AppState>>,
+                     Extension(principal): Extension<Principal>,
+                     Path(id): Path<String>,
+                     headers: HeaderMap| async move {
+                        ContentHandler::new(state)
+                            .file_content(principal, id, headers)
+                            .await
                     },
                 ),
             )
+            .route(
+                "/{id}/compressed",
+                get(
+                    |State(state): State<Arc<AppState>>,
+                     Extension(principal): Extension<Principal>,
+                     Path(id): Path<String>,
+                     Query(query): Query<ListCompressedFilesQuery>| async move {
+                        let compression_handler = CompressionHandler::new(state);
+                        compression_handler
+                            .list_for_file(principal, id, query)
+                            .await
+                    },
+                ),
+            )
+            .route(
+                "/{id}/shares",
+                get(
+                    |State(state): State<Arc<AppState>>,
+                     Extension(principal): Extension<Principal>,
+                     Path(id): Path<String>| async move {
+                        ShareHandler::new(state).list(principal, id).await
+                    },
+                )
+                .post(
+                    |State(state): State<Arc<AppState>>,
+                     Extension(principal): Extension<Principal>,
+                     Path(id): Path<String>,
+                     Json(request): Json<ShareRequest>| async move {
+                        ShareHandler::new(state)
+                            .create(principal, id, request)
+                            .await
+                    },
+                ),
+            )
+            .route(
+                "/{id}/share",
+                post(
+                    |State(state): State<Arc<AppState>>,
+                     Extension(principal): Extension<Principal>,
+                     Path(id): Path<String>,
+                     Json(request): Json<DownloadLinkRequest>| async move {
+                        DownloadLinkHandler::new(state)
+                            .create_for_file(principal, id, request)
+                            .await
+                    },
+                ),
+            )
+            .route(
+                "/{id}/shares/{share_id}",
+                delete(
+                    |State(state): State<Arc<AppState>>,
+                     Extension(principal): Extension<Principal>,
+                     Path((id, share_id)): Path<(String, String)>| async move {
+                        ShareHandler::new(state)
+                            .delete(principal, id, share_id)
+                            .await
+                    },
+                ),
+            )
+            .route(
+                "/tus",
+                options(|State(state): State<Arc<AppState>>| async move {
+                    TusUploadHandler::new(state).options()
+                })
+                .post(
+                    |State(state): State<Arc<AppState>>,
+                     Extension(principal): Extension<Principal>,
+                     headers: HeaderMap| async move {
+                        TusUploadHandler::new(state)
+                            .create(principal, headers)
+                            .await
+                    },
+                ),
+            )
+            .route(
+                "/tus/{id}",
+                head(
+                    |State(state): State<Arc<AppState>>,
+                     Extension(principal): Extension<Principal>,
+                     Path(id): Path<String>,
+                     headers: HeaderMap| async move {
+                        TusUploadHandler::new(state)
+                            .head(principal, id, headers)
+                            .await
+                    },
+                )
+                .patch(
+                    |State(state): State<Arc<AppState>>,
+                     Extension(principal): Extension<Principal>,
+                     Path(id): Path<String>,
+                     headers: HeaderMap,
+                     body: Body| async move {
+                        TusUploadHandler::new(state)
+                            .patch(principal, id, headers, body)
+                            .await
+                    },
+                )
+                .delete(
+                    |State(state): State<Arc<AppState>>,
+                     Extension(principal): Extension<Principal>,
+                     Path(id): Path<String>,
+                     headers: HeaderMap| async move {
+                        TusUploadHandler::new(state)
+                            .terminate(principal, id, headers)
+                            .await
+                    },
+                )
+                // Chunks are streamed to disk, bounded by Upload-Length, itself capped by MAX_UPLOAD_SIZE
+                .layer(DefaultBodyLimit::disable()),
+            )
             .fallback(|| async { (StatusCode::NOT_FOUND, "Route not found".to_string()) })
     }
 }
@@ -103,24 +353,390 @@ impl App {
 impl App {
     fn compression_handler_routes() -> Router<Arc<AppState>> {
         Router::new()
+            .route(
+                "/",
+                get(
+                    |State(state): State<Arc<AppState>>,
+                     Extension(principal): Extension<Principal>,
+                     Query(query): Query<ListCompressedFilesQuery>| async move {
+                        let compression_handler = Arc::new(CompressionHandler::new(state.clone()));
+                        compression_handler.list(principal, query).await
+                    },
+                ),
+            )
+            .route(
+                "/events",
+                get(
+                    |State(state): State<Arc<AppState>>,
+                     Extension(principal): Extension<Principal>| async move {
+                        EventHandler::new(state).all_events(principal)
+                    },
+                ),
+            )
+            .route(
+                "/ws",
+                get(
+                    |State(state): State<Arc<AppState>>,
+                     Extension(principal): Extension<Principal>,
+                     upgrade: WebSocketUpgrade| async move {
+                        EventHandler::new(state).all_socket(principal, upgrade)
+                    },
+                ),
+            )
             .route(
                 "/{file_id}/compress",
                 post(
                     |State(state): State<Arc<AppState>>,
+                     Extension(principal): Extension<Principal>,
                      Path(file_id): Path<String>,
                      Query(query): Query<CompressionQuery>| async move {
                         let compression_handler = Arc::new(CompressionHandler::new(state.clone()));
-                        compression_handler.initiate(file_id, query).await
+                        compression_handler
+                            .initiate(principal, file_id, query)
+                            .await
+                    },
+                ),
+            )
+            .route(
+                "/archive",
+                post(
+                    |State(state): State<Arc<AppState>>,
+                     Extension(principal): Extension<Principal>,
+                     Json(request): Json<ArchiveRequest>| async move {
+                        let compression_handler = Arc::new(CompressionHandler::new(state.clone()));
+                        compression_handler.archive(principal, request).await
+                    },
+                ),
+            )
+            .route(
+                "/{id}",
+                delete(
+                    |State(state): State<Arc<AppState>>,
+                     Extension(principal): Extension<Principal>,
+                     Path(id): Path<String>,
+                     Query(query): Query<DeleteQuery>| async move {
+                        DeletionHandler::new(state)
+                            .delete_compressed_file(principal, id, query)
+                            .await
+                    },
+                ),
+            )
+            .route(
+                "/{id}/restore",
+                post(
+                    |State(state): State<Arc<AppState>>,
+                     Extension(principal): Extension<Principal>,
+                     Path(id): Path<String>| async move {
+                        DeletionHandler::new(state)
+                            .restore_compressed_file(principal, id)
+                            .await
+                    },
+                ),
+            )
+            .route(
+                "/{id}/content",
+                get(
+                    |State(state): State<Arc<AppState>>,
+                     Extension(principal): Extension<Principal>,
+                     Path(id): Path<String>,
+                     headers: HeaderMap| async move {
+                        ContentHandler::new(state)
+                            .compressed_file_content(principal, id, headers)
+                            .await
+                    },
+                ),
+            )
+            .route(
+                "/{id}/share",
+                post(
+                    |State(state): State<Arc<AppState>>,
+                     Extension(principal): Extension<Principal>,
+                     Path(id): Path<String>,
+                     Json(request): Json<DownloadLinkRequest>| async move {
+                        DownloadLinkHandler::new(state)
+                            .create_for_compressed_file(principal, id, request)
+                            .await
                     },
                 ),
             )
             .route(
                 "/{id}/status",
-                get(|State(state): State<Arc<AppState>>, Path(id)| async move {
-                    let compression_handler = Arc::new(CompressionHandler::new(state.clone()));
-                    compression_handler.get_status(id).await
-                }),
+                get(
+                    |State(state): State<Arc<AppState>>,
+                     Extension(principal): Extension<Principal>,
+                     Path(id)| async move {
+                        let compression_handler = Arc::new(CompressionHandler::new(state.clone()));
+                        compression_handler.get_status(principal, id).await
+                    },
+                ),
+            )
+            .route(
+                "/{id}/events",
+                get(
+                    |State(state): State<Arc<AppState>>,
+                     Extension(principal): Extension<Principal>,
+                     Path(id): Path<String>| async move {
+                        EventHandler::new(state)
+                            .compressed_file_events(principal, id)
+                            .await
+                    },
+                ),
+            )
+            .route(
+                "/{id}/ws",
+                get(
+                    |State(state): State<Arc<AppState>>,
+                     Extension(principal): Extension<Principal>,
+                     Path(id): Path<String>,
+                     upgrade: WebSocketUpgrade| async move {
+                        EventHandler::new(state)
+                            .compressed_file_socket(principal, id, upgrade)
+                            .await
+                    },
+                ),
+            )
+            .route(
+                "/{id}/cancel",
+                post(
+                    |State(state): State<Arc<AppState>>,
+                     Extension(principal): Extension<Principal>,
+                     Path(id): Path<String>| async move {
+                        let compression_handler = Arc::new(CompressionHandler::new(state.clone()));
+                        compression_handler.cancel(principal, id).await
+                    },
+                ),
+            )
+            .route(
+                "/{id}/retry",
+                post(
+                    |State(state): State<Arc<AppState>>,
+                     Extension(principal): Extension<Principal>,
+                     Path(id): Path<String>| async move {
+                        let compression_handler = Arc::new(CompressionHandler::new(state.clone()));
+                        compression_handler.retry(principal, id).await
+                    },
+                ),
+            )
+            .route(
+                "/{id}/decompress",
+                post(
+                    |State(state): State<Arc<AppState>>,
+                     Extension(principal): Extension<Principal>,
+                     Path(id): Path<String>,
+                     Query(query): Query<DecompressionQuery>| async move {
+                        let compression_handler = Arc::new(CompressionHandler::new(state.clone()));
+                        compression_handler.decompress(principal, id, query).await
+                    },
+                ),
+            )
+    }
+}
+
+impl App {
+    fn webhook_handler_routes() -> Router<Arc<AppState>> {
+        Router::new()
+            .route(
+                "/",
+                get(|State(state): State<Arc<AppState>>| async move {
+                    WebhookHandler::new(state).list().await
+                })
+                .post(
+                    |State(state): State<Arc<AppState>>, Json(request): Json<WebhookRequest>| async move {
+                        WebhookHandler::new(state).create(request).await
+                    },
+                ),
             )
-            .layer(middleware::from_fn(auth_guard::auth_guard))
+            .route(
+                "/{id}",
+                get(
+                    |State(state): State<Arc<AppState>>, Path(id): Path<String>| async move {
+                        WebhookHandler::new(state).get(id).await
+                    },
+                )
+                .delete(
+                    |State(state): State<Arc<AppState>>, Path(id): Path<String>| async move {
+                        WebhookHandler::new(state).delete(id).await
+                    },
+                ),
+            )
+            .route(
+                "/{id}/deliveries",
+                get(
+                    |State(state): State<Arc<AppState>>,
+                     Path(id): Path<String>,
+                     Query(query): Query<ListDeliveriesQuery>| async move {
+                        WebhookHandler::new(state).list_deliveries(id, query).await
+                    },
+                ),
+            )
+            .route(
+                "/deliveries/{id}/redeliver",
+                post(
+                    |State(state): State<Arc<AppState>>, Path(id): Path<String>| async move {
+                        WebhookHandler::new(state).redeliver(id).await
+                    },
+                ),
+            )
+    }
+}
+
+impl App {
+    fn user_handler_routes() -> Router<Arc<AppState>> {
+        Router::new()
+            .route(
+                "/",
+                get(|State(state): State<Arc<AppState>>| async move {
+                    UserHandler::new(state).list().await
+                })
+                .post(
+                    |State(state): State<Arc<AppState>>, Json(request): Json<UserRequest>| async move {
+                        UserHandler::new(state).create(request).await
+                    },
+                ),
+            )
+            .route(
+                "/{id}",
+                get(
+                    |State(state): State<Arc<AppState>>, Path(id): Path<String>| async move {
+                        UserHandler::new(state).get(id).await
+                    },
+                )
+                .delete(
+                    |State(state): State<Arc<AppState>>, Path(id): Path<String>| async move {
+                        UserHandler::new(state).delete(id).await
+                    },
+                ),
+            )
+            .route(
+                "/{id}/api-keys",
+                get(
+                    |State(state): State<Arc<AppState>>,
+                     Extension(principal): Extension<Principal>,
+                     Path(id): Path<String>| async move {
+                        ApiKeyHandler::new(state).list(principal, Some(id)).await
+                    },
+                )
+                .post(
+                    |State(state): State<Arc<AppState>>,
+                     Extension(principal): Extension<Principal>,
+                     Path(id): Path<String>,
+                     Json(request): Json<ApiKeyRequest>| async move {
+                        ApiKeyHandler::new(state)
+                            .create(principal, Some(id), request)
+                            .await
+                    },
+                ),
+            )
+    }
+}
+
+impl App {
+    fn group_handler_routes() -> Router<Arc<AppState>> {
+        Router::new()
+            .route(
+                "/",
+                get(|State(state): State<Arc<AppState>>| async move {
+                    GroupHandler::new(state).list().await
+                })
+                .post(
+                    |State(state): State<Arc<AppState>>, Json(request): Json<GroupRequest>| async move {
+                        GroupHandler::new(state).create(request).await
+                    },
+                ),
+            )
+            .route(
+                "/{id}",
+                get(
+                    |State(state): State<Arc<AppState>>, Path(id): Path<String>| async move {
+                        GroupHandler::new(state).get(id).await
+                    },
+                )
+                .delete(
+                    |State(state): State<Arc<AppState>>, Path(id): Path<String>| async move {
+                        GroupHandler::new(state).delete(id).await
+                    },
+                ),
+            )
+            .route(
+                "/{id}/members/{user_id}",
+                put(
+                    |State(state): State<Arc<AppState>>,
+                     Path((id, user_id)): Path<(String, String)>| async move {
+                        GroupHandler::new(state).add_member(id, user_id).await
+                    },
+                )
+                .delete(
+                    |State(state): State<Arc<AppState>>,
+                     Path((id, user_id)): Path<(String, String)>| async move {
+                        GroupHandler::new(state).remove_member(id, user_id).await
+                    },
+                ),
+            )
+    }
+}
+
+impl App {
+    // The caller's own keys
+    fn api_key_handler_routes() -> Router<Arc<AppState>> {
+        Router::new()
+            .route(
+                "/",
+                get(
+                    |State(state): State<Arc<AppState>>,
+                     Extension(principal): Extension<Principal>| async move {
+                        ApiKeyHandler::new(state).list(principal, None).await
+                    },
+                )
+                .post(
+                    |State(state): State<Arc<AppState>>,
+                     Extension(principal): Extension<Principal>,
+                     Json(request): Json<ApiKeyRequest>| async move {
+                        ApiKeyHandler::new(state)
+                            .create(principal, None, request)
+                            .await
+                    },
+                ),
+            )
+            .route(
+                "/{id}",
+                delete(
+                    |State(state): State<Arc<AppState>>,
+                     Extension(principal): Extension<Principal>,
+                     Path(id): Path<String>| async move {
+                        ApiKeyHandler::new(state).revoke(principal, id).await
+                    },
+                ),
+            )
+            .route(
+                "/{id}/rotate",
+                post(
+                    |State(state): State<Arc<AppState>>,
+                     Extension(principal): Extension<Principal>,
+                     Path(id): Path<String>,
+                     Query(query): Query<RotateApiKeyQuery>| async move {
+                        ApiKeyHandler::new(state).rotate(principal, id, query).await
+                    },
+                ),
+            )
+    }
+}
+
+impl App {
+    // Reached without credentials, see DownloadLinkHandler::download
+    fn download_link_routes() -> Router<Arc<AppState>> {
+        Router::new().route(
+            "/{id}",
+            get(
+                |State(state): State<Arc<AppState>>,
+                 ConnectInfo(address): ConnectInfo<SocketAddr>,
+                 Path(id): Path<String>,
+                 Query(query): Query<DownloadQuery>,
+                 headers: HeaderMap| async move {
+                    DownloadLinkHandler::new(state)
+                        .download(id, query, address.ip(), headers)
+                        .await
+                },
+            ),
+        )
     }
 }
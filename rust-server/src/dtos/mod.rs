@@ -1,20 +1,245 @@
 use serde::Deserialize;
+use sqlx::types::Uuid;
 
-// Define a struct to receive the compression level from the client
+use crate::models::{
+    file::{DecompressionMode, FileStatus},
+    page::{SortField, SortOrder},
+    share::Permission,
+};
+
+// Define a struct to receive the compression level and algorithm from the client
 #[derive(Deserialize)]
 pub struct CompressionQuery {
-    pub level: u32,
+    // Defaults to the algorithm's default level, ignored with `alg=auto`
+    pub level: Option<u32>,
+    // Defaults to gzip when omitted, `auto` picks one by sampling the file
+    pub alg: Option<String>,
+    // Only used with `alg=auto`: ratio, speed or balanced (default)
+    pub goal: Option<String>,
 }
 
-#[derive(Deserialize)]
 pub struct CreateCompressedFile {
+    // Unset on archives
+    pub file_id: Option<Uuid>,
     pub file_ref: String,
     pub level: u32,
     pub alg: String,
+    pub goal: Option<String>,
+    pub selection_reason: Option<String>,
+    pub input_sha256: Option<String>,
+    pub owner_id: Option<Uuid>,
 }
 
-#[derive(Deserialize)]
 pub struct CreateFile {
     pub file_ref: String,
     pub size: u64,
+    pub sha256: Option<String>,
+    pub blob_sha256: Option<String>,
+    pub parent_id: Option<Uuid>,
+    pub archive_path: Option<String>,
+    pub owner_id: Option<Uuid>,
+}
+
+#[derive(Deserialize)]
+pub struct UploadQuery {
+    // Unpacks uploaded zip and tar archives into one file per member, other files are stored as-is
+    #[serde(default)]
+    pub extract: bool,
+}
+
+#[derive(Deserialize)]
+pub struct DecompressionQuery {
+    // Defaults to validate, which leaves the uploaded file untouched
+    #[serde(default)]
+    pub mode: DecompressionMode,
+}
+
+pub struct CreateDecompression {
+    pub compressed_file_id: Uuid,
+    pub mode: DecompressionMode,
+    pub status: FileStatus,
+    pub alg: String,
+    pub size: Option<u64>,
+    pub error: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ArchiveRequest {
+    // IDs of the uploaded files to bundle, in archive order
+    pub files: Vec<String>,
+    // tar.gz (default), tar.zst or zip
+    pub format: Option<String>,
+    // Defaults to the format's default level
+    pub level: Option<u32>,
+}
+
+pub struct CreateArchiveMember {
+    pub file_id: Uuid,
+    pub name: String,
+}
+
+pub struct CreateTusUpload {
+    pub file_name: String,
+    pub upload_length: u64,
+    pub metadata: Option<String>,
+    pub owner_id: Option<Uuid>,
+}
+
+#[derive(Deserialize)]
+pub struct ListFilesQuery {
+    // `next_cursor` of the previous page
+    pub cursor: Option<String>,
+    pub limit: Option<i64>,
+    // created_at (default) or size
+    #[serde(default)]
+    pub sort: SortField,
+    // asc or desc (default)
+    #[serde(default)]
+    pub order: SortOrder,
+    // Size range in bytes, both ends included
+    pub min_size: Option<i64>,
+    pub max_size: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct GetFileQuery {
+    // Also lists the file's compressed files under `compressed`
+    #[serde(default)]
+    pub compressed: bool,
+}
+
+#[derive(Deserialize)]
+pub struct ListCompressedFilesQuery {
+    // `next_cursor` of the previous page
+    pub cursor: Option<String>,
+    pub limit: Option<i64>,
+    // created_at (default) or size, the output size
+    #[serde(default)]
+    pub sort: SortField,
+    // asc or desc (default)
+    #[serde(default)]
+    pub order: SortOrder,
+    pub status: Option<String>,
+    // Only the compressed files of this file
+    pub file_id: Option<String>,
+    // Algorithm or archive format name, e.g. gzip or tar.zst
+    pub alg: Option<String>,
+    pub level: Option<i32>,
+    // Output size range in bytes, both ends included
+    pub min_size: Option<i64>,
+    pub max_size: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct DeleteQuery {
+    // Removes the rows and their content right away instead of after the restore window
+    #[serde(default)]
+    pub purge: bool,
+}
+
+#[derive(Deserialize)]
+pub struct WebhookRequest {
+    // http or https URL the events are posted to
+    pub url: String,
+    // e.g. file.uploaded, compression.passed or compression.failed
+    pub events: Vec<String>,
+    // Key to sign the deliveries with, one is generated when omitted
+    pub secret: Option<String>,
+}
+
+pub struct CreateWebhook {
+    pub url: String,
+    pub secret: String,
+    pub events: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ListDeliveriesQuery {
+    // `next_cursor` of the previous page
+    pub cursor: Option<String>,
+    pub limit: Option<i64>,
+    // By creation time, asc or desc (default)
+    #[serde(default)]
+    pub order: SortOrder,
+    // pending, delivered or failed
+    pub status: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct UserRequest {
+    pub name: String,
+}
+
+#[derive(Deserialize)]
+pub struct ApiKeyRequest {
+    // Label to tell the user's keys apart
+    pub name: String,
+    // upload, compress, read and/or admin
+    pub scopes: Vec<String>,
+    // The key never expires when omitted
+    pub expires_in_secs: Option<u64>,
+}
+
+pub struct CreateApiKey {
+    pub user_id: Uuid,
+    pub name: String,
+    pub prefix: String,
+    pub key_hash: String,
+    pub scopes: Vec<String>,
+    pub expires_in_secs: Option<u64>,
+}
+
+#[derive(Deserialize)]
+pub struct RotateApiKeyQuery {
+    // How long the old key keeps working, so clients can switch over, it stops right away by default
+    #[serde(default)]
+    pub grace_secs: u64,
+}
+
+#[derive(Deserialize)]
+pub struct GroupRequest {
+    pub name: String,
+}
+
+#[derive(Deserialize)]
+pub struct ShareRequest {
+    // Share with either a user or a group
+    pub user_id: Option<String>,
+    pub group_id: Option<String>,
+    // read or compress, which includes read
+    pub permission: String,
+}
+
+pub struct CreateFileShare {
+    pub file_id: Uuid,
+    pub user_id: Option<Uuid>,
+    pub group_id: Option<Uuid>,
+    pub permission: Permission,
+}
+
+#[derive(Deserialize)]
+pub struct DownloadLinkRequest {
+    // The link stops working after this long, a day by default
+    pub expires_in_secs: Option<u64>,
+    // Any number of downloads when omitted
+    pub max_downloads: Option<u32>,
+    // Restricts the link to the client with this IP address
+    pub ip: Option<String>,
+}
+
+pub struct CreateDownloadLink {
+    // Either the file or the compressed file is set
+    pub file_id: Option<Uuid>,
+    pub compressed_file_id: Option<Uuid>,
+    pub expires_in_secs: u64,
+    pub max_downloads: Option<u32>,
+    pub allowed_ip: Option<String>,
+    pub created_by: Option<Uuid>,
+}
+
+#[derive(Deserialize)]
+pub struct DownloadQuery {
+    // Unix timestamp the link expires at, covered by the signature
+    pub expires: i64,
+    pub signature: String,
 }
@@ -0,0 +1,255 @@
+use std::sync::Arc;
+
+use axum::{http::StatusCode, response::IntoResponse};
+use sqlx::types::Uuid;
+
+use crate::{
+    app::AppState,
+    dtos::{ApiKeyRequest, CreateApiKey, RotateApiKeyQuery},
+    helpers::{
+        api_key::ApiKeyHelper,
+        logger::{DefaultLogger, Logger},
+    },
+    models::user::{ApiKey, Principal, Scope},
+    services::{api_key_service::ApiKeyService, user_service::UserService},
+};
+
+/// Creates, lists, rotates and revokes API keys.
+/// Callers manage their own keys, admins those of every user.
+pub struct ApiKeyHandler {
+    api_key_service: ApiKeyService,
+    user_service: UserService,
+    logger: Arc<dyn Logger>,
+}
+
+impl ApiKeyHandler {
+    pub fn new(state: Arc<AppState>) -> Self {
+        Self {
+            api_key_service: ApiKeyService::new(state.pool.clone()),
+            user_service: UserService::new(state.pool.clone()),
+
+            // Initialize the logger
+            logger: Arc::new(DefaultLogger::new::<ApiKeyHandler>()),
+        }
+    }
+}
+
+impl ApiKeyHandler {
+    /// Creates a key for the user, the response is the only one including the key itself.
+    /// A key can't be given a scope its creator doesn't have.
+    pub async fn create(
+        &self,
+        principal: Principal,
+        user_id: Option<String>,
+        request: ApiKeyRequest,
+    ) -> impl IntoResponse {
+        let user_id = match self.user_id(&principal, user_id) {
+            Ok(user_id) => user_id,
+            Err(e) => return e,
+        };
+        if request.name.trim().is_empty() {
+            return (
+                StatusCode::BAD_REQUEST,
+                "API key name can't be empty".to_string(),
+            );
+        }
+        if request.scopes.is_empty() {
+            return (
+                StatusCode::BAD_REQUEST,
+                "At least one scope is required".to_string(),
+            );
+        }
+        let mut scopes = Vec::with_capacity(request.scopes.len());
+        for scope in &request.scopes {
+            let scope: Scope = match scope.parse() {
+                Ok(scope) => scope,
+                Err(e) => return (StatusCode::BAD_REQUEST, e),
+            };
+            if !principal.has_scope(scope) {
+                return (
+                    StatusCode::FORBIDDEN,
+                    format!("Can't grant the {} scope", scope.name()),
+                );
+            }
+            if !scopes.contains(&scope.name().to_string()) {
+                scopes.push(scope.name().to_string());
+            }
+        }
+        if let Err(e) = self.user_service.find_one(user_id).await {
+            return (StatusCode::NOT_FOUND, format!("User not found: {e}"));
+        }
+
+        let key = ApiKeyHelper::generate();
+        match self
+            .api_key_service
+            .create(CreateApiKey {
+                user_id,
+                name: request.name.trim().to_string(),
+                prefix: ApiKeyHelper::prefix(&key),
+                key_hash: ApiKeyHelper::hash(&key),
+                scopes,
+                expires_in_secs: request.expires_in_secs,
+            })
+            .await
+        {
+            Ok(api_key) => {
+                self.logger.debug(&format!(
+                    "API key(id: {}) created for user(id: {})",
+                    api_key.id, user_id
+                ));
+                (StatusCode::CREATED, Self::with_key(&api_key, key))
+            }
+            Err(e) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to create API key: {e}"),
+            ),
+        }
+    }
+
+    /// Keys of the user, revoked and expired ones included
+    pub async fn list(&self, principal: Principal, user_id: Option<String>) -> impl IntoResponse {
+        let user_id = match self.user_id(&principal, user_id) {
+            Ok(user_id) => user_id,
+            Err(e) => return e,
+        };
+
+        match self.api_key_service.find_for_user(user_id).await {
+            Ok(api_keys) => (StatusCode::OK, serde_json::json!(api_keys).to_string()),
+            Err(e) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to list API keys: {e}"),
+            ),
+        }
+    }
+
+    /// Replaces the key with a new one, the old key keeps working for `grace_secs`
+    pub async fn rotate(
+        &self,
+        principal: Principal,
+        id: String,
+        query: RotateApiKeyQuery,
+    ) -> impl IntoResponse {
+        let id_uuid = match self.find_own(&principal, &id).await {
+            Ok(id_uuid) => id_uuid,
+            Err(e) => return e,
+        };
+
+        let key = ApiKeyHelper::generate();
+        match self
+            .api_key_service
+            .rotate(
+                id_uuid,
+                &ApiKeyHelper::prefix(&key),
+                &ApiKeyHelper::hash(&key),
+                query.grace_secs,
+            )
+            .await
+        {
+            Ok(Some(api_key)) => {
+                self.logger.debug(&format!(
+                    "API key(id: {}) rotated to API key(id: {})",
+                    id_uuid, api_key.id
+                ));
+                (StatusCode::CREATED, Self::with_key(&api_key, key))
+            }
+            Ok(None) => (
+                StatusCode::CONFLICT,
+                format!("API key was already rotated, revoked or has expired: {id_uuid}"),
+            ),
+            Err(e) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to rotate API key: {e}"),
+            ),
+        }
+    }
+
+    /// Stops the key from working right away
+    pub async fn revoke(&self, principal: Principal, id: String) -> impl IntoResponse {
+        let id_uuid = match self.find_own(&principal, &id).await {
+            Ok(id_uuid) => id_uuid,
+            Err(e) => return e,
+        };
+
+        match self.api_key_service.revoke(id_uuid).await {
+            Ok(true) => (StatusCode::NO_CONTENT, String::new()),
+            Ok(false) => (
+                StatusCode::CONFLICT,
+                format!("API key was already revoked: {id_uuid}"),
+            ),
+            Err(e) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to revoke API key: {e}"),
+            ),
+        }
+    }
+}
+
+impl ApiKeyHandler {
+    // The user whose keys are managed: the one in the path for admins, the caller otherwise
+    fn user_id(
+        &self,
+        principal: &Principal,
+        user_id: Option<String>,
+    ) -> Result<Uuid, (StatusCode, String)> {
+        match user_id {
+            Some(id) => {
+                let id_uuid: Uuid = id.parse().map_err(|_| {
+                    (
+                        StatusCode::BAD_REQUEST,
+                        format!("Invalid ID format: {}", id),
+                    )
+                })?;
+                match principal.is_admin() || principal.user_id == Some(id_uuid) {
+                    true => Ok(id_uuid),
+                    false => Err((
+                        StatusCode::FORBIDDEN,
+                        "Only admins can manage the keys of other users".to_string(),
+                    )),
+                }
+            }
+            None => principal.user_id.ok_or_else(|| {
+                (
+                    StatusCode::BAD_REQUEST,
+                    "The admin token has no keys, use /users/{id}/api-keys".to_string(),
+                )
+            }),
+        }
+    }
+
+    // Checks the key exists and the caller may manage it
+    async fn find_own(
+        &self,
+        principal: &Principal,
+        id: &str,
+    ) -> Result<Uuid, (StatusCode, String)> {
+        let id_uuid: Uuid = id.parse().map_err(|_| {
+            (
+                StatusCode::BAD_REQUEST,
+                format!("Invalid ID format: {}", id),
+            )
+        })?;
+        let api_key = self
+            .api_key_service
+            .find_one(id_uuid)
+            .await
+            .map_err(|e| (StatusCode::NOT_FOUND, format!("API key not found: {e}")))?;
+
+        let owned = principal
+            .user_id
+            .is_some_and(|user_id| user_id.to_string() == api_key.user_id);
+        match principal.is_admin() || owned {
+            true => Ok(id_uuid),
+            // Other users' keys aren't revealed to exist
+            false => Err((
+                StatusCode::NOT_FOUND,
+                format!("API key not found: {id_uuid}"),
+            )),
+        }
+    }
+
+    fn with_key(api_key: &ApiKey, key: String) -> String {
+        let mut body = serde_json::json!(api_key);
+        body["key"] = key.into();
+        body.to_string()
+    }
+}
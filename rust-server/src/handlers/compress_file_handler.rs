@@ -1,25 +1,57 @@
-use std::sync::Arc;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
 
-use axum::{http::StatusCode, response::IntoResponse};
-use rust_server::compress_file;
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use rust_server::{
+    auto::{self, Goal},
+    decompress_file, validate_file, Algorithm, ArchiveFormat, CancellationToken,
+};
 use sqlx::types::Uuid;
+use tokio::sync::Notify;
 
 use crate::app::AppState;
-use crate::dtos::CompressionQuery;
-use crate::helpers::env::Env;
-use crate::services::{compressed_file_service::CompressedFileService, file_service::FileService};
+use crate::dtos::{
+    ArchiveRequest, CompressionQuery, CreateArchiveMember, CreateDecompression, DecompressionQuery,
+    ListCompressedFilesQuery,
+};
+use crate::helpers::{access::AccessFilter, env::Env, pagination::Pagination};
+use crate::services::{
+    archive_service::ArchiveService, compressed_file_service::CompressedFileService,
+    decompression_service::DecompressionService, event_service::EventService,
+    file_service::FileService, job_service::JobService, webhook_service::WebhookService,
+};
 use crate::{
     dtos::CreateCompressedFile,
     helpers::file::FileHelper,
     helpers::logger::{DefaultLogger, Logger},
-    models::file::FileStatus,
+    models::{
+        file::{Archive, DecompressionMode, FileStatus},
+        job::{JobEvent, JobKind},
+        page::SortField,
+        share::Access,
+        user::Principal,
+        webhook::WebhookEvent,
+    },
 };
 
 pub struct CompressionHandler {
     env: Arc<Env>,
+    job_notify: Arc<Notify>,
+    webhook_notify: Arc<Notify>,
+    running_compressions: Arc<Mutex<HashMap<Uuid, CancellationToken>>>,
     logger: Arc<dyn Logger>,
     file_service: FileService,
     compressed_file_service: CompressedFileService,
+    decompression_service: DecompressionService,
+    archive_service: ArchiveService,
+    job_service: JobService,
+    event_service: EventService,
+    webhook_service: WebhookService,
 }
 
 impl CompressionHandler {
@@ -27,8 +59,16 @@ impl CompressionHandler {
         Self {
             // Initialize the services
             env: state.env.clone(),
+            job_notify: state.job_notify.clone(),
+            webhook_notify: state.webhook_notify.clone(),
+            running_compressions: state.running_compressions.clone(),
             file_service: FileService::new(state.pool.clone()),
             compressed_file_service: CompressedFileService::new(state.pool.clone()),
+            decompression_service: DecompressionService::new(state.pool.clone()),
+            archive_service: ArchiveService::new(state.pool.clone()),
+            job_service: JobService::new(state.pool.clone()),
+            event_service: EventService::new(state.pool.clone()),
+            webhook_service: WebhookService::new(state.pool.clone()),
 
             // Initialize the logger
             logger: Arc::new(DefaultLogger::new::<CompressionHandler>()),
@@ -38,7 +78,12 @@ impl CompressionHandler {
 
 impl CompressionHandler {
     //// Endpoint to trigger file compression on demand
-    pub async fn initiate(&self, id: String, query: CompressionQuery) -> impl IntoResponse {
+    pub async fn initiate(
+        &self,
+        principal: Principal,
+        id: String,
+        query: CompressionQuery,
+    ) -> impl IntoResponse {
         let id_uuid: Uuid = match id.parse() {
             Ok(uuid) => uuid,
             Err(_) => {
@@ -49,78 +94,93 @@ impl CompressionHandler {
             }
         };
 
-        let file = match self.file_service.find_one(id_uuid).await {
+        // `None` means the algorithm is picked by sampling the file for the goal
+        let algorithm: Option<Algorithm> = match query.alg.as_deref() {
+            Some(alg) if alg.eq_ignore_ascii_case(auto::AUTO) => None,
+            alg => match alg.map(str::parse::<Algorithm>).transpose() {
+                Ok(algorithm) => Some(algorithm.unwrap_or_default()),
+                Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()),
+            },
+        };
+        if algorithm.is_some() && query.goal.is_some() {
+            return (
+                StatusCode::BAD_REQUEST,
+                format!("goal is only supported with alg={}", auto::AUTO),
+            );
+        }
+        let goal: Goal = match query.goal.as_deref().map(str::parse).transpose() {
+            Ok(goal) => goal.unwrap_or_default(),
+            Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()),
+        };
+
+        let access = AccessFilter::new(&principal, Access::Compress);
+        let file = match self.file_service.find_one(id_uuid, &access).await {
             Ok(value) => value,
             Err(e) => return (StatusCode::NOT_FOUND, format!("File record not found: {e}")),
         };
 
-        let input_path =
-            match FileHelper::get_uploaded_file_path(&file.file_ref, &self.env.uploads_dir) {
-                Some(value) => value,
-                None => {
-                    return (
-                        StatusCode::NOT_FOUND,
-                        format!("File not found: {}", &file.file_ref),
-                    )
+        if FileHelper::get_file_path(&file, &self.env.uploads_dir).is_none() {
+            return (
+                StatusCode::NOT_FOUND,
+                format!("File not found: {}", &file.file_ref),
+            );
+        }
+
+        // Automatic selection samples the file, that's left to the worker along with the compression
+        let (alg, level, goal) = match algorithm {
+            Some(algorithm) => {
+                match algorithm.check_level(query.level.unwrap_or(algorithm.default_level())) {
+                    Ok(level) => (algorithm.name(), level, None),
+                    Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()),
                 }
-            };
+            }
+            None => (auto::AUTO, 0, Some(goal.to_string())),
+        };
 
-        let output_path =
-            match FileHelper::get_compressed_file_path(&file.file_ref, &self.env.compressed_dir) {
-                Some(value) => value,
-                None => {
+        // Identical content was already compressed the same way, or is being compressed right now.
+        // Compressions of other users' files aren't handed out, the caller couldn't cancel or retry them.
+        if let (Some(sha256), None) = (&file.blob_sha256, &goal) {
+            match self
+                .compressed_file_service
+                .find_reusable(sha256, alg, level, &access)
+                .await
+            {
+                Ok(Some(existing)) => {
+                    self.logger.debug(&format!(
+                        "Reusing compressed file(id: {}) for file(id: {})",
+                        existing.id, file.id
+                    ));
+                    return (StatusCode::OK, serde_json::json!(existing).to_string());
+                }
+                Ok(None) => {}
+                Err(e) => {
                     return (
                         StatusCode::INTERNAL_SERVER_ERROR,
-                        format!("Failed to create output path: {}", &file.file_ref),
+                        format!("Failed to look up compressed files: {e}"),
                     )
                 }
-            };
+            }
+        }
 
-        let (compression_level, compressed_file) = self
+        let compressed_file = self
             .compressed_file_service
             .create(CreateCompressedFile {
+                file_id: Some(id_uuid),
                 file_ref: file.file_ref,
-                level: query.level,
-                alg: "gzip".to_string(),
+                level,
+                alg: alg.to_string(),
+                goal,
+                selection_reason: None,
+                input_sha256: file.blob_sha256,
+                owner_id: principal.user_id,
             })
             .await;
 
         match compressed_file {
-            Ok(row) => {
-                let logger = self.logger.clone();
-
-                let id_uuid = Uuid::parse_str(&row.id).unwrap();
-                let service = self.compressed_file_service.clone();
-
-                tokio::task::spawn(async move {
-                    logger.debug(&format!("Starting compression task(id: {})...", id_uuid));
-                    match compress_file(&input_path, &output_path, compression_level).await {
-                        Ok(_) => {
-                            if let Err(e) = service.update_status(id_uuid, FileStatus::Passed).await
-                            {
-                                logger.error(&format!(
-                                    "Failed to update file status to Passed: {}",
-                                    e
-                                ));
-                            }
-                        }
-                        Err(e) => {
-                            if let Err(db_err) =
-                                service.update_status(id_uuid, FileStatus::Failed).await
-                            {
-                                logger.error(&format!(
-                                    "Failed to update file status to Failed: {}",
-                                    db_err
-                                ));
-                            }
-                            logger.error(&format!("Compression task(id: {}) failed: {e}", id_uuid));
-                        }
-                    }
-                    logger.debug(&format!("Compression task(id: {}) completed.", id_uuid));
-                });
-
-                (StatusCode::OK, serde_json::json!(row).to_string())
-            }
+            Ok(row) => match self.enqueue(&row.id, JobKind::Compress).await {
+                Ok(()) => (StatusCode::OK, serde_json::json!(row).to_string()),
+                Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e),
+            },
             Err(e) => (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 format!("Failed to create compressed file: {e}"),
@@ -128,7 +188,208 @@ impl CompressionHandler {
         }
     }
 
-    pub async fn get_status(&self, id: String) -> impl IntoResponse {
+    /// Bundles several uploaded files into one archive, built by the compression workers
+    pub async fn archive(
+        &self,
+        principal: Principal,
+        request: ArchiveRequest,
+    ) -> impl IntoResponse {
+        let format: ArchiveFormat = match request.format.as_deref().map(str::parse).transpose() {
+            Ok(format) => format.unwrap_or_default(),
+            Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()),
+        };
+        let level = match format
+            .algorithm()
+            .check_level(request.level.unwrap_or(format.default_level()))
+        {
+            Ok(level) => level,
+            Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()),
+        };
+
+        let mut ids: Vec<Uuid> = Vec::with_capacity(request.files.len());
+        for id in &request.files {
+            match id.parse() {
+                // Each file is only added once
+                Ok(uuid) if ids.contains(&uuid) => {}
+                Ok(uuid) => ids.push(uuid),
+                Err(_) => {
+                    return (
+                        StatusCode::BAD_REQUEST,
+                        format!("Invalid ID format: {}", id),
+                    )
+                }
+            }
+        }
+        if ids.is_empty() {
+            return (
+                StatusCode::BAD_REQUEST,
+                "At least one file is required".to_string(),
+            );
+        }
+
+        let access = AccessFilter::new(&principal, Access::Compress);
+        let mut members = Vec::with_capacity(ids.len());
+        for id in ids {
+            let file = match self.file_service.find_one(id, &access).await {
+                Ok(value) => value,
+                Err(e) => {
+                    return (
+                        StatusCode::NOT_FOUND,
+                        format!("File record not found: {id}: {e}"),
+                    )
+                }
+            };
+            let (Some(_), Some(name)) = (
+                FileHelper::get_file_path(&file, &self.env.uploads_dir),
+                FileHelper::original_file_name(&file.file_ref),
+            ) else {
+                return (
+                    StatusCode::NOT_FOUND,
+                    format!("File not found: {}", &file.file_ref),
+                );
+            };
+
+            members.push(CreateArchiveMember {
+                file_id: id,
+                name: name.to_string(),
+            });
+        }
+
+        // Only the name archives are downloaded with, the output is named after the row id
+        let file_ref = format!("/archives/archive.{}", format.extension());
+        let compressed_file = match self
+            .compressed_file_service
+            .create(CreateCompressedFile {
+                file_id: None,
+                file_ref,
+                level,
+                alg: format.to_string(),
+                goal: None,
+                selection_reason: None,
+                input_sha256: None,
+                owner_id: principal.user_id,
+            })
+            .await
+        {
+            Ok(row) => row,
+            Err(e) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Failed to create compressed file: {e}"),
+                )
+            }
+        };
+
+        let id_uuid = Uuid::parse_str(&compressed_file.id).unwrap();
+        let members = match self.archive_service.add_members(id_uuid, members).await {
+            Ok(members) => members,
+            Err(e) => {
+                let _ = self
+                    .compressed_file_service
+                    .update_status(id_uuid, FileStatus::Failed)
+                    .await;
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Failed to record archive members: {e}"),
+                );
+            }
+        };
+
+        if let Err(e) = self.enqueue(&compressed_file.id, JobKind::Archive).await {
+            return (StatusCode::INTERNAL_SERVER_ERROR, e);
+        }
+
+        let archive = Archive {
+            compressed_file,
+            members,
+        };
+        (StatusCode::OK, serde_json::json!(archive).to_string())
+    }
+
+    /// Compressed files the caller can read, every one for admins
+    pub async fn list(
+        &self,
+        principal: Principal,
+        query: ListCompressedFilesQuery,
+    ) -> impl IntoResponse {
+        let pagination = match Pagination::new(
+            query.sort,
+            query.order,
+            query.cursor.as_deref(),
+            query.limit,
+        ) {
+            Ok(pagination) => pagination,
+            Err(e) => return (StatusCode::BAD_REQUEST, e),
+        };
+        let status = match query.status.as_deref().map(str::parse).transpose() {
+            Ok(status) => status,
+            Err(e) => return (StatusCode::BAD_REQUEST, e),
+        };
+        let file_id: Option<Uuid> = match query.file_id.as_deref().map(str::parse).transpose() {
+            Ok(file_id) => file_id,
+            Err(_) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    format!("Invalid ID format: {}", query.file_id.unwrap_or_default()),
+                )
+            }
+        };
+
+        let files = match self
+            .compressed_file_service
+            .list(
+                &query,
+                file_id,
+                status,
+                &pagination,
+                &AccessFilter::new(&principal, Access::Read),
+            )
+            .await
+        {
+            Ok(files) => files,
+            Err(e) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Failed to list compressed files: {e}"),
+                )
+            }
+        };
+
+        let page = pagination.page(files, |file| match pagination.sort {
+            SortField::CreatedAt => (file.created_at.timestamp_micros(), &file.id),
+            SortField::Size => (file.output_size.unwrap_or_default(), &file.id),
+        });
+        (StatusCode::OK, serde_json::json!(page).to_string())
+    }
+
+    /// Every compressed variant of a file, as a page like `list`
+    pub async fn list_for_file(
+        &self,
+        principal: Principal,
+        file_id: String,
+        mut query: ListCompressedFilesQuery,
+    ) -> Response {
+        let id_uuid: Uuid = match file_id.parse() {
+            Ok(uuid) => uuid,
+            Err(_) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    format!("Invalid ID format: {}", file_id),
+                )
+                    .into_response();
+            }
+        };
+
+        let access = AccessFilter::new(&principal, Access::Read);
+        if let Err(e) = self.file_service.find_one(id_uuid, &access).await {
+            return (StatusCode::NOT_FOUND, format!("File not found: {e}")).into_response();
+        }
+
+        query.file_id = Some(file_id);
+        self.list(principal, query).await.into_response()
+    }
+
+    pub async fn get_status(&self, principal: Principal, id: String) -> impl IntoResponse {
         let id_uuid: Uuid = match id.parse() {
             Ok(uuid) => uuid,
             Err(_) => {
@@ -139,7 +400,12 @@ impl CompressionHandler {
             }
         };
 
-        let file = match self.compressed_file_service.find_one(id_uuid).await {
+        let access = AccessFilter::new(&principal, Access::Read);
+        let file = match self
+            .compressed_file_service
+            .find_one(id_uuid, &access)
+            .await
+        {
             Ok(value) => value,
             Err(e) => {
                 return (
@@ -149,6 +415,402 @@ impl CompressionHandler {
             }
         };
 
+        // Archives also list the files they bundle
+        if file.alg.parse::<ArchiveFormat>().is_ok() {
+            return match self.archive_service.find_members(id_uuid).await {
+                Ok(members) => (
+                    StatusCode::OK,
+                    serde_json::json!(Archive {
+                        compressed_file: file,
+                        members,
+                    })
+                    .to_string(),
+                ),
+                Err(e) => (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Failed to load archive members: {e}"),
+                ),
+            };
+        }
+
         (StatusCode::OK, serde_json::json!(file).to_string())
     }
+
+    /// Stops a queued or running compression, its partial output is removed
+    pub async fn cancel(&self, principal: Principal, id: String) -> impl IntoResponse {
+        let id_uuid: Uuid = match id.parse() {
+            Ok(uuid) => uuid,
+            Err(_) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    format!("Invalid ID format: {}", id),
+                );
+            }
+        };
+
+        let access = AccessFilter::new(&principal, Access::Compress);
+        match self.compressed_file_service.cancel(id_uuid, &access).await {
+            Ok(true) => {
+                self.publish_status(id_uuid, FileStatus::Cancelled, None)
+                    .await
+            }
+            Ok(false) => {
+                return self
+                    .not_in_state(id_uuid, "queued or compressing", &access)
+                    .await
+            }
+            Err(e) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Failed to cancel compression: {e}"),
+                )
+            }
+        }
+
+        // A worker of this instance stops right away, others once they fail to renew the job
+        if let Some(cancellation) = self.running_compressions.lock().unwrap().get(&id_uuid) {
+            cancellation.cancel();
+        }
+
+        match self
+            .compressed_file_service
+            .find_one(id_uuid, &access)
+            .await
+        {
+            Ok(compressed_file) => (
+                StatusCode::OK,
+                serde_json::json!(compressed_file).to_string(),
+            ),
+            Err(e) => (
+                StatusCode::NOT_FOUND,
+                format!("Compressed file not found: {e}"),
+            ),
+        }
+    }
+
+    /// Runs a failed or cancelled compression again, with the same algorithm and level
+    pub async fn retry(&self, principal: Principal, id: String) -> impl IntoResponse {
+        let id_uuid: Uuid = match id.parse() {
+            Ok(uuid) => uuid,
+            Err(_) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    format!("Invalid ID format: {}", id),
+                );
+            }
+        };
+
+        let access = AccessFilter::new(&principal, Access::Compress);
+        match self.compressed_file_service.requeue(id_uuid, &access).await {
+            Ok(true) => {}
+            Ok(false) => {
+                return self
+                    .not_in_state(id_uuid, "failed or cancelled", &access)
+                    .await
+            }
+            Err(e) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Failed to retry compression: {e}"),
+                )
+            }
+        }
+
+        let compressed_file = match self
+            .compressed_file_service
+            .find_one(id_uuid, &access)
+            .await
+        {
+            Ok(compressed_file) => compressed_file,
+            Err(e) => {
+                return (
+                    StatusCode::NOT_FOUND,
+                    format!("Compressed file not found: {e}"),
+                )
+            }
+        };
+        // Archives are the only compressed files without a source file
+        let kind = match compressed_file.file_id {
+            Some(_) => JobKind::Compress,
+            None => JobKind::Archive,
+        };
+        match self.enqueue(&compressed_file.id, kind).await {
+            Ok(()) => (
+                StatusCode::OK,
+                serde_json::json!(compressed_file).to_string(),
+            ),
+            Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e),
+        }
+    }
+
+    /// Restores the original upload from a compressed file, or only checks that it decodes
+    pub async fn decompress(
+        &self,
+        principal: Principal,
+        id: String,
+        query: DecompressionQuery,
+    ) -> impl IntoResponse {
+        let id_uuid: Uuid = match id.parse() {
+            Ok(uuid) => uuid,
+            Err(_) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    format!("Invalid ID format: {}", id),
+                );
+            }
+        };
+
+        let access = AccessFilter::new(&principal, Access::Compress);
+        let compressed_file = match self
+            .compressed_file_service
+            .find_one(id_uuid, &access)
+            .await
+        {
+            Ok(value) => value,
+            Err(e) => {
+                return (
+                    StatusCode::NOT_FOUND,
+                    format!("Compressed file not found: {e}"),
+                )
+            }
+        };
+
+        if compressed_file.status != FileStatus::Passed {
+            return (
+                StatusCode::CONFLICT,
+                format!("Compressed file is not ready: {}", compressed_file.id),
+            );
+        }
+
+        if compressed_file.alg.parse::<ArchiveFormat>().is_ok() {
+            return (
+                StatusCode::BAD_REQUEST,
+                format!("Archives can't be decompressed: {}", compressed_file.id),
+            );
+        }
+
+        let algorithm: Algorithm = match compressed_file.alg.parse() {
+            Ok(algorithm) => algorithm,
+            Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+        };
+
+        let input_path = match FileHelper::get_compressed_file_path(
+            &compressed_file.id,
+            &self.env.compressed_dir,
+            algorithm,
+        ) {
+            Some(value) => value,
+            None => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Failed to resolve compressed path: {}", &compressed_file.id),
+                )
+            }
+        };
+
+        let result = match query.mode {
+            DecompressionMode::Validate => validate_file(&input_path, Some(algorithm)).await,
+            DecompressionMode::Restore => {
+                // Blob-backed inputs are restored into their blob, which must keep its exact content
+                let output_path = match &compressed_file.input_sha256 {
+                    Some(sha256) => FileHelper::get_blob_path(sha256, &self.env.uploads_dir),
+                    None => FileHelper::get_uploaded_file_path(
+                        &compressed_file.file_ref,
+                        &self.env.uploads_dir,
+                    ),
+                };
+                match output_path {
+                    Some(output_path) => {
+                        Self::restore(
+                            &input_path,
+                            &output_path,
+                            algorithm,
+                            compressed_file.input_sha256.as_deref(),
+                        )
+                        .await
+                    }
+                    None => {
+                        return (
+                            StatusCode::INTERNAL_SERVER_ERROR,
+                            format!(
+                                "Failed to resolve upload path: {}",
+                                &compressed_file.file_ref
+                            ),
+                        )
+                    }
+                }
+            }
+        };
+
+        let (status, size, error) = match result {
+            Ok((_, size)) => (FileStatus::Passed, Some(size), None),
+            Err(e) => {
+                self.logger.error(&format!(
+                    "Decompression of {} failed: {e}",
+                    compressed_file.id
+                ));
+                (FileStatus::Failed, None, Some(e.to_string()))
+            }
+        };
+
+        let decompression = self
+            .decompression_service
+            .create(CreateDecompression {
+                compressed_file_id: id_uuid,
+                mode: query.mode,
+                status,
+                alg: algorithm.to_string(),
+                size,
+                error,
+            })
+            .await;
+
+        match decompression {
+            Ok(row) if row.status == FileStatus::Passed => {
+                (StatusCode::OK, serde_json::json!(row).to_string())
+            }
+            Ok(row) => (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                serde_json::json!(row).to_string(),
+            ),
+            Err(e) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to record decompression: {e}"),
+            ),
+        }
+    }
+
+    // Hands the compression to the workers, a row that can't be queued would never leave `compressing`
+    async fn enqueue(&self, id: &str, kind: JobKind) -> Result<(), String> {
+        let id_uuid = Uuid::parse_str(id).map_err(|e| format!("{e}"))?;
+        match self
+            .job_service
+            .enqueue(id_uuid, kind, self.env.job_max_attempts)
+            .await
+        {
+            Ok(_) => {
+                self.job_notify.notify_one();
+                self.publish_status(id_uuid, FileStatus::Queued, None).await;
+                Ok(())
+            }
+            Err(e) => {
+                let error = format!("Failed to queue compression: {e}");
+                match self.compressed_file_service.fail(id_uuid, &error).await {
+                    Ok(_) => {
+                        self.publish_status(id_uuid, FileStatus::Failed, Some(&error))
+                            .await;
+                        self.notify_failed(id_uuid).await;
+                    }
+                    Err(db_err) => self.logger.error(&format!(
+                        "Failed to update file status to Failed: {}",
+                        db_err
+                    )),
+                }
+                Err(error)
+            }
+        }
+    }
+
+    async fn publish_status(&self, id: Uuid, status: FileStatus, error: Option<&str>) {
+        let event = JobEvent::Status {
+            compressed_file_id: id.to_string(),
+            status,
+            error: error.map(str::to_string),
+        };
+        if let Err(e) = self.event_service.publish(&event).await {
+            self.logger.error(&format!(
+                "Failed to publish compressed file(id: {}) status: {e}",
+                id
+            ));
+        }
+    }
+
+    // Queues the `compression.failed` webhooks for a compression that never reached the workers,
+    // which send them for the ones they run
+    async fn notify_failed(&self, id: Uuid) {
+        let compressed_file = match self
+            .compressed_file_service
+            .find_any(id, &AccessFilter::Everything)
+            .await
+        {
+            Ok(Some(compressed_file)) => compressed_file,
+            Ok(None) => return,
+            Err(e) => {
+                self.logger
+                    .error(&format!("Failed to load compressed file(id: {}): {e}", id));
+                return;
+            }
+        };
+        match self
+            .webhook_service
+            .enqueue(
+                WebhookEvent::CompressionFailed,
+                &serde_json::json!(compressed_file),
+                self.env.webhook_max_attempts,
+            )
+            .await
+        {
+            Ok(0) => {}
+            Ok(_) => self.webhook_notify.notify_one(),
+            Err(e) => self.logger.error(&format!(
+                "Failed to queue webhook deliveries for compressed file(id: {}): {e}",
+                id
+            )),
+        }
+    }
+
+    // Tells a compressed file that doesn't exist apart from one in the wrong state for the action
+    async fn not_in_state(
+        &self,
+        id: Uuid,
+        expected: &str,
+        access: &AccessFilter,
+    ) -> (StatusCode, String) {
+        match self.compressed_file_service.find_one(id, access).await {
+            Ok(compressed_file) => (
+                StatusCode::CONFLICT,
+                format!(
+                    "Compressed file is {:?}, it must be {expected}",
+                    compressed_file.status
+                ),
+            ),
+            Err(e) => (
+                StatusCode::NOT_FOUND,
+                format!("Compressed file not found: {e}"),
+            ),
+        }
+    }
+
+    // Decompress next to the destination first so a failure never clobbers an existing upload
+    async fn restore(
+        input_path: &str,
+        output_path: &str,
+        algorithm: Algorithm,
+        expected_sha256: Option<&str>,
+    ) -> std::io::Result<(Algorithm, u64)> {
+        // Unique, concurrent restores of the same blob each write their own file
+        let partial_path = format!("{output_path}.{}.partial", Uuid::new_v4());
+        let result = async {
+            let result = decompress_file(input_path, &partial_path, Some(algorithm)).await?;
+            if let Some(expected_sha256) = expected_sha256 {
+                let sha256 = FileHelper::hash_file(&partial_path).await?;
+                if sha256 != expected_sha256 {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        format!(
+                            "Restored content has SHA-256 {sha256}, expected {expected_sha256}"
+                        ),
+                    ));
+                }
+            }
+            tokio::fs::rename(&partial_path, output_path).await?;
+            Ok(result)
+        }
+        .await;
+
+        if result.is_err() {
+            let _ = tokio::fs::remove_file(&partial_path).await;
+        }
+        result
+    }
 }
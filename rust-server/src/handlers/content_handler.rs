@@ -0,0 +1,865 @@
+use std::{
+    io::{self, SeekFrom},
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    body::Body,
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use rust_server::{compress_stream_with, Algorithm, ArchiveFormat, CHUNK_SIZE};
+use sqlx::types::Uuid;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio_util::io::{ReaderStream, SyncIoBridge};
+
+use crate::{
+    app::AppState,
+    helpers::{
+        access::AccessFilter,
+        env::Env,
+        file::FileHelper,
+        logger::{DefaultLogger, Logger},
+    },
+    models::{
+        file::{CompressedFile, FileStatus},
+        share::Access,
+        user::Principal,
+    },
+    services::{compressed_file_service::CompressedFileService, file_service::FileService},
+};
+
+// Content codings offered to clients, preferred in this order when they accept several equally
+const ENCODINGS: [Algorithm; 3] = [Algorithm::Zstd, Algorithm::Brotli, Algorithm::Gzip];
+// Smaller files aren't worth compressing on the fly
+const MIN_ENCODE_SIZE: u64 = 1024;
+
+/// What a download is served as
+struct Content {
+    path: String,
+    // Offered to the client in `Content-Disposition`
+    file_name: String,
+    content_type: String,
+    // Strong validator when the content's hash is known
+    sha256: Option<String>,
+    // Set when a compressed variant is sent in place of the file, with `Content-Encoding`
+    encoding: Option<Algorithm>,
+    // The representation depends on `Accept-Encoding`, which caches must know about
+    negotiated: bool,
+}
+
+/// Serves uploads and compressed outputs over plain HTTP: conditional requests, single byte ranges
+/// and the original file name, so browsers and download managers can resume and cache them
+pub struct ContentHandler {
+    env: Arc<Env>,
+    file_service: FileService,
+    compressed_file_service: CompressedFileService,
+    logger: Arc<dyn Logger>,
+}
+
+impl ContentHandler {
+    pub fn new(state: Arc<AppState>) -> Self {
+        Self {
+            env: state.env.clone(),
+            file_service: FileService::new(state.pool.clone()),
+            compressed_file_service: CompressedFileService::new(state.pool.clone()),
+
+            // Initialize the logger
+            logger: Arc::new(DefaultLogger::new::<ContentHandler>()),
+        }
+    }
+}
+
+impl ContentHandler {
+    /// Serves the file, or one of its compressed variants with `Content-Encoding` when the client accepts it.
+    /// Without a matching variant the file can be compressed while it is sent, see `ENCODE_ON_THE_FLY`.
+    pub async fn file_content(
+        &self,
+        principal: Principal,
+        id: String,
+        headers: HeaderMap,
+    ) -> Response {
+        let id_uuid: Uuid = match id.parse() {
+            Ok(uuid) => uuid,
+            Err(_) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    format!("Invalid ID format: {}", id),
+                )
+                    .into_response();
+            }
+        };
+
+        let access = AccessFilter::new(&principal, Access::Read);
+        self.serve_file(id_uuid, &access, headers).await
+    }
+
+    pub async fn compressed_file_content(
+        &self,
+        principal: Principal,
+        id: String,
+        headers: HeaderMap,
+    ) -> Response {
+        let id_uuid: Uuid = match id.parse() {
+            Ok(uuid) => uuid,
+            Err(_) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    format!("Invalid ID format: {}", id),
+                )
+                    .into_response();
+            }
+        };
+
+        let access = AccessFilter::new(&principal, Access::Read);
+        self.serve_compressed_file(id_uuid, &access, headers).await
+    }
+
+    /// `file_content` for a file the caller was already checked against, e.g. through a download link
+    pub async fn serve_file(
+        &self,
+        id_uuid: Uuid,
+        access: &AccessFilter,
+        headers: HeaderMap,
+    ) -> Response {
+        let file = match self.file_service.find_one(id_uuid, access).await {
+            Ok(file) => file,
+            Err(e) => {
+                return (StatusCode::NOT_FOUND, format!("File not found: {e}")).into_response()
+            }
+        };
+
+        let (Some(path), Some(file_name)) = (
+            FileHelper::get_file_path(&file, &self.env.uploads_dir),
+            FileHelper::original_file_name(&file.file_ref),
+        ) else {
+            return (
+                StatusCode::NOT_FOUND,
+                format!("File not found: {}", &file.file_ref),
+            )
+                .into_response();
+        };
+
+        let mut content = Content {
+            path,
+            file_name: file_name.to_string(),
+            content_type: mime_guess::from_path(file_name)
+                .first_or_octet_stream()
+                .to_string(),
+            sha256: file.sha256,
+            encoding: None,
+            negotiated: true,
+        };
+
+        let accepted = Self::accepted_encodings(&headers);
+        let Some(&(preferred, _)) = accepted.first() else {
+            return self.serve(content, &headers).await;
+        };
+
+        match self
+            .compressed_file_service
+            .find_for_file(id_uuid, access)
+            .await
+        {
+            Ok(variants) => {
+                if let Some((variant, path, algorithm)) =
+                    self.best_variant(variants, &accepted).await
+                {
+                    content.path = path;
+                    content.sha256 = variant.sha256;
+                    content.encoding = Some(algorithm);
+                    return self.serve(content, &headers).await;
+                }
+            }
+            // The file itself can still be sent
+            Err(e) => self.logger.error(&format!(
+                "Failed to load compressed files of {id_uuid}: {e}"
+            )),
+        }
+
+        // Ranges of an output produced anew each time mean nothing, those are served from the file
+        if self.env.encode_on_the_fly && !headers.contains_key(header::RANGE) {
+            return self.encode(content, preferred, &headers).await;
+        }
+        self.serve(content, &headers).await
+    }
+
+    /// `compressed_file_content` for a compressed file the caller was already checked against
+    pub async fn serve_compressed_file(
+        &self,
+        id_uuid: Uuid,
+        access: &AccessFilter,
+        headers: HeaderMap,
+    ) -> Response {
+        let compressed_file = match self.compressed_file_service.find_one(id_uuid, access).await {
+            Ok(value) => value,
+            Err(e) => {
+                return (
+                    StatusCode::NOT_FOUND,
+                    format!("Compressed file not found: {e}"),
+                )
+                    .into_response()
+            }
+        };
+
+        if compressed_file.status != FileStatus::Passed {
+            return (
+                StatusCode::CONFLICT,
+                format!("Compressed file is not ready: {}", compressed_file.id),
+            )
+                .into_response();
+        }
+
+        // Archives are named after their file ref, compressed files after the file they were made from
+        let file_name = FileHelper::original_file_name(&compressed_file.file_ref);
+        let (file_name, content_type) = match (
+            compressed_file.alg.parse::<ArchiveFormat>(),
+            compressed_file.alg.parse::<Algorithm>(),
+        ) {
+            (Ok(format), _) => (file_name.map(str::to_string), format.mime_type()),
+            (_, Ok(algorithm)) => (
+                file_name.map(|name| format!("{name}.{}", algorithm.extension())),
+                algorithm.mime_type(),
+            ),
+            (_, Err(e)) => {
+                return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()
+            }
+        };
+
+        let (Some(path), Some(file_name)) = (
+            FileHelper::get_output_path(&compressed_file, &self.env.compressed_dir),
+            file_name,
+        ) else {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!(
+                    "Failed to resolve compressed path: {}",
+                    &compressed_file.file_ref
+                ),
+            )
+                .into_response();
+        };
+
+        let content = Content {
+            path,
+            file_name,
+            content_type: content_type.to_string(),
+            sha256: compressed_file.sha256,
+            encoding: None,
+            negotiated: false,
+        };
+        self.serve(content, &headers).await
+    }
+}
+
+impl ContentHandler {
+    async fn serve(&self, content: Content, headers: &HeaderMap) -> Response {
+        let mut file = match tokio::fs::File::open(&content.path).await {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return (
+                    StatusCode::NOT_FOUND,
+                    format!("Content not found: {}", content.file_name),
+                )
+                    .into_response()
+            }
+            Err(e) => return self.io_error(&content, e),
+        };
+        let metadata = match file.metadata().await {
+            Ok(metadata) => metadata,
+            Err(e) => return self.io_error(&content, e),
+        };
+        let size = metadata.len();
+        let modified = metadata.modified().ok();
+
+        // Files without a hash fall back to a weak validator made of their size and modification time
+        let etag = match &content.sha256 {
+            Some(sha256) => format!("\"{sha256}\""),
+            None => {
+                let modified = modified
+                    .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                    .map_or(0, |modified| modified.as_secs());
+                format!("W/\"{size:x}-{modified:x}\"")
+            }
+        };
+
+        let mut response_headers = HeaderMap::new();
+        if let Ok(value) = HeaderValue::from_str(&etag) {
+            response_headers.insert(header::ETAG, value);
+        }
+        if let Some(modified) = modified {
+            if let Ok(value) = HeaderValue::from_str(&httpdate::fmt_http_date(modified)) {
+                response_headers.insert(header::LAST_MODIFIED, value);
+            }
+        }
+
+        Self::insert_encoding(&mut response_headers, &content);
+
+        if Self::not_modified(headers, &etag, modified) {
+            return (StatusCode::NOT_MODIFIED, response_headers).into_response();
+        }
+
+        response_headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+        let range = match Self::range(headers, size, &etag, modified) {
+            Ok(range) => range,
+            Err(()) => {
+                if let Ok(value) = HeaderValue::from_str(&format!("bytes */{size}")) {
+                    response_headers.insert(header::CONTENT_RANGE, value);
+                }
+                return (StatusCode::RANGE_NOT_SATISFIABLE, response_headers).into_response();
+            }
+        };
+
+        let (status, start, length) = match range {
+            Some((start, end)) => {
+                if let Ok(value) = HeaderValue::from_str(&format!("bytes {start}-{end}/{size}")) {
+                    response_headers.insert(header::CONTENT_RANGE, value);
+                }
+                (StatusCode::PARTIAL_CONTENT, start, end - start + 1)
+            }
+            None => (StatusCode::OK, 0, size),
+        };
+
+        if start > 0 {
+            if let Err(e) = file.seek(SeekFrom::Start(start)).await {
+                return self.io_error(&content, e);
+            }
+        }
+
+        response_headers.insert(header::CONTENT_LENGTH, HeaderValue::from(length));
+        Self::insert_content_headers(&mut response_headers, &content);
+
+        let body = Body::from_stream(ReaderStream::new(file.take(length)));
+        (status, response_headers, body).into_response()
+    }
+
+    /// Streams the file through the library's encoder. The length isn't known up front,
+    /// so the response is chunked and doesn't support ranges.
+    async fn encode(
+        &self,
+        mut content: Content,
+        algorithm: Algorithm,
+        headers: &HeaderMap,
+    ) -> Response {
+        let metadata = match tokio::fs::metadata(&content.path).await {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return (
+                    StatusCode::NOT_FOUND,
+                    format!("Content not found: {}", content.file_name),
+                )
+                    .into_response()
+            }
+            Err(e) => return self.io_error(&content, e),
+        };
+        if metadata.len() < MIN_ENCODE_SIZE {
+            return self.serve(content, headers).await;
+        }
+        let modified = metadata.modified().ok();
+        content.encoding = Some(algorithm);
+
+        // Equivalent but not byte-for-byte identical from one response to the next, hence weak
+        let token = algorithm.content_encoding().unwrap_or(algorithm.name());
+        let etag = match &content.sha256 {
+            Some(sha256) => format!("W/\"{sha256}-{token}\""),
+            None => {
+                let modified = modified
+                    .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                    .map_or(0, |modified| modified.as_secs());
+                format!("W/\"{:x}-{modified:x}-{token}\"", metadata.len())
+            }
+        };
+
+        let mut response_headers = HeaderMap::new();
+        if let Ok(value) = HeaderValue::from_str(&etag) {
+            response_headers.insert(header::ETAG, value);
+        }
+        if let Some(modified) = modified {
+            if let Ok(value) = HeaderValue::from_str(&httpdate::fmt_http_date(modified)) {
+                response_headers.insert(header::LAST_MODIFIED, value);
+            }
+        }
+        Self::insert_encoding(&mut response_headers, &content);
+
+        if Self::not_modified(headers, &etag, modified) {
+            return (StatusCode::NOT_MODIFIED, response_headers).into_response();
+        }
+        Self::insert_content_headers(&mut response_headers, &content);
+
+        // Fast levels, the client is waiting for the output
+        let compressor = match algorithm {
+            Algorithm::Zstd => algorithm.compressor(3),
+            Algorithm::Brotli => algorithm.compressor(4),
+            _ => algorithm.compressor(algorithm.default_level()),
+        };
+        let (writer, reader) = tokio::io::duplex(CHUNK_SIZE);
+        let path = content.path.clone();
+        let logger = self.logger.clone();
+        tokio::task::spawn_blocking(move || {
+            // Dropping the writer ends the body, a failure midway leaves the client with a truncated stream
+            let writer = SyncIoBridge::new(writer);
+            let result = std::fs::File::open(&path)
+                .and_then(|input| compress_stream_with(input, writer, compressor.as_ref()));
+            if let Err(e) = result {
+                logger.error(&format!("Failed to encode {path}: {e}"));
+            }
+        });
+
+        let body = Body::from_stream(ReaderStream::new(reader));
+        (StatusCode::OK, response_headers, body).into_response()
+    }
+
+    /// Compressed variant to send and its path: the most wanted coding, then the smallest output
+    async fn best_variant(
+        &self,
+        variants: Vec<CompressedFile>,
+        accepted: &[(Algorithm, f32)],
+    ) -> Option<(CompressedFile, String, Algorithm)> {
+        let mut candidates: Vec<(CompressedFile, Algorithm, f32)> = variants
+            .into_iter()
+            .filter(|variant| variant.status == FileStatus::Passed)
+            .filter_map(|variant| {
+                let algorithm: Algorithm = variant.alg.parse().ok()?;
+                let (_, q) = accepted
+                    .iter()
+                    .find(|(accepted, _)| *accepted == algorithm)?;
+                Some((variant, algorithm, *q))
+            })
+            .collect();
+        candidates.sort_by(|a, b| {
+            b.2.total_cmp(&a.2)
+                .then_with(|| a.0.output_size.cmp(&b.0.output_size))
+        });
+
+        for (variant, algorithm, _) in candidates {
+            let Some(path) = FileHelper::get_output_path(&variant, &self.env.compressed_dir) else {
+                continue;
+            };
+            // Outputs removed from disk behind the database's back are passed over
+            if tokio::fs::try_exists(&path).await.unwrap_or(false) {
+                return Some((variant, path, algorithm));
+            }
+        }
+        None
+    }
+
+    /// Codings the client accepts that the library produces, most wanted first
+    fn accepted_encodings(headers: &HeaderMap) -> Vec<(Algorithm, f32)> {
+        let Some(accept_encoding) = headers
+            .get(header::ACCEPT_ENCODING)
+            .and_then(|accept_encoding| accept_encoding.to_str().ok())
+        else {
+            return vec![];
+        };
+
+        let mut wildcard = None;
+        let mut listed = vec![];
+        for item in accept_encoding.split(',') {
+            let mut parts = item.split(';');
+            let token = parts.next().unwrap_or_default().trim();
+            let q = parts
+                .find_map(|param| {
+                    let (name, value) = param.split_once('=')?;
+                    name.trim().eq_ignore_ascii_case("q").then(|| value.trim())
+                })
+                .map_or(Some(1.0), |q| q.parse::<f32>().ok());
+            let Some(q) = q else {
+                continue;
+            };
+
+            if token == "*" {
+                wildcard = Some(q);
+            } else if let Some(algorithm) = Algorithm::from_content_encoding(token) {
+                listed.push((algorithm, q));
+            }
+        }
+
+        let mut accepted: Vec<(Algorithm, f32)> = ENCODINGS
+            .into_iter()
+            .filter_map(|algorithm| {
+                let q = listed
+                    .iter()
+                    .find(|(listed, _)| *listed == algorithm)
+                    .map(|(_, q)| *q)
+                    .or(wildcard)?;
+                (q > 0.0).then_some((algorithm, q))
+            })
+            .collect();
+        // Stable, so equally wanted codings keep the order of `ENCODINGS`
+        accepted.sort_by(|a, b| b.1.total_cmp(&a.1));
+        accepted
+    }
+
+    fn insert_encoding(response_headers: &mut HeaderMap, content: &Content) {
+        if content.negotiated {
+            response_headers.insert(header::VARY, HeaderValue::from_static("accept-encoding"));
+        }
+        if let Some(token) = content
+            .encoding
+            .and_then(|algorithm| algorithm.content_encoding())
+        {
+            response_headers.insert(header::CONTENT_ENCODING, HeaderValue::from_static(token));
+        }
+    }
+
+    fn insert_content_headers(response_headers: &mut HeaderMap, content: &Content) {
+        if let Ok(value) = HeaderValue::from_str(&content.content_type) {
+            response_headers.insert(header::CONTENT_TYPE, value);
+        }
+        if let Ok(value) = HeaderValue::from_str(&Self::content_disposition(&content.file_name)) {
+            response_headers.insert(header::CONTENT_DISPOSITION, value);
+        }
+    }
+
+    // `If-None-Match` takes precedence, `If-Modified-Since` only has a one second resolution
+    fn not_modified(headers: &HeaderMap, etag: &str, modified: Option<SystemTime>) -> bool {
+        if let Some(if_none_match) = headers.get(header::IF_NONE_MATCH) {
+            let Ok(if_none_match) = if_none_match.to_str() else {
+                return false;
+            };
+            return if_none_match.split(',').map(str::trim).any(|tag| {
+                tag == "*" || tag.trim_start_matches("W/") == etag.trim_start_matches("W/")
+            });
+        }
+
+        let since = headers
+            .get(header::IF_MODIFIED_SINCE)
+            .and_then(|since| since.to_str().ok())
+            .and_then(|since| httpdate::parse_http_date(since).ok());
+        match (since, modified) {
+            (Some(since), Some(modified)) => Self::whole_seconds(modified) <= since,
+            _ => false,
+        }
+    }
+
+    /// The single byte range requested, as inclusive offsets. `Ok(None)` serves the whole content:
+    /// no range, a stale `If-Range`, or a request this handler doesn't split (other units, several ranges).
+    fn range(
+        headers: &HeaderMap,
+        size: u64,
+        etag: &str,
+        modified: Option<SystemTime>,
+    ) -> Result<Option<(u64, u64)>, ()> {
+        let Some(range) = headers
+            .get(header::RANGE)
+            .and_then(|range| range.to_str().ok())
+        else {
+            return Ok(None);
+        };
+
+        // Ranges only apply to the representation the client already has part of, compared strongly
+        if let Some(if_range) = headers
+            .get(header::IF_RANGE)
+            .and_then(|if_range| if_range.to_str().ok())
+        {
+            let current = match httpdate::parse_http_date(if_range) {
+                Ok(date) => modified.is_some_and(|modified| Self::whole_seconds(modified) == date),
+                Err(_) => !etag.starts_with("W/") && if_range == etag,
+            };
+            if !current {
+                return Ok(None);
+            }
+        }
+
+        let Some(spec) = range.trim().strip_prefix("bytes=") else {
+            return Ok(None);
+        };
+        if spec.contains(',') {
+            return Ok(None);
+        }
+        let Some((start, end)) = spec.trim().split_once('-') else {
+            return Ok(None);
+        };
+
+        let (start, end) = match (start.parse::<u64>(), end.parse::<u64>()) {
+            // `bytes=-500`: the last 500 bytes
+            (Err(_), Ok(suffix)) if start.is_empty() => {
+                if suffix == 0 || size == 0 {
+                    return Err(());
+                }
+                (size.saturating_sub(suffix), size - 1)
+            }
+            // `bytes=500-`: everything from byte 500
+            (Ok(start), Err(_)) if end.is_empty() => (start, size.saturating_sub(1)),
+            (Ok(start), Ok(end)) if start <= end => (start, end.min(size.saturating_sub(1))),
+            _ => return Ok(None),
+        };
+        if start >= size {
+            return Err(());
+        }
+
+        Ok(Some((start, end)))
+    }
+
+    // Quoted ASCII name for older clients, the exact UTF-8 name in `filename*`
+    fn content_disposition(file_name: &str) -> String {
+        let fallback: String = file_name
+            .chars()
+            .map(|c| match c {
+                ' '..='~' if c != '"' && c != '\\' => c,
+                _ => '_',
+            })
+            .collect();
+        format!(
+            "attachment; filename=\"{fallback}\"; filename*=UTF-8''{}",
+            utf8_percent_encode(file_name, NON_ALPHANUMERIC)
+        )
+    }
+
+    // HTTP dates drop the sub-second part of modification times
+    fn whole_seconds(time: SystemTime) -> SystemTime {
+        match time.duration_since(UNIX_EPOCH) {
+            Ok(duration) => UNIX_EPOCH + std::time::Duration::from_secs(duration.as_secs()),
+            Err(_) => time,
+        }
+    }
+
+    fn io_error(&self, content: &Content, e: io::Error) -> Response {
+        self.logger
+            .error(&format!("Failed to read {}: {e}", content.path));
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to read content: {}", content.file_name),
+        )
+            .into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    const ETAG: &str = "\"abc\"";
+
+    fn headers(pairs: &[(header::HeaderName, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(name, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    // 2025-05-19 10:20:00 UTC and a bit, HTTP dates only keep the seconds
+    fn modified() -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(1_747_650_000_250)
+    }
+
+    fn range(pairs: &[(header::HeaderName, &str)]) -> Result<Option<(u64, u64)>, ()> {
+        ContentHandler::range(&headers(pairs), 1000, ETAG, Some(modified()))
+    }
+
+    #[test]
+    fn test_range_forms() {
+        assert_eq!(range(&[]), Ok(None));
+        assert_eq!(range(&[(header::RANGE, "bytes=0-99")]), Ok(Some((0, 99))));
+        assert_eq!(
+            range(&[(header::RANGE, "bytes=500-")]),
+            Ok(Some((500, 999)))
+        );
+        assert_eq!(
+            range(&[(header::RANGE, "bytes=-200")]),
+            Ok(Some((800, 999)))
+        );
+        // Past the end is cut to the content, a suffix longer than it is all of it
+        assert_eq!(
+            range(&[(header::RANGE, "bytes=900-5000")]),
+            Ok(Some((900, 999)))
+        );
+        assert_eq!(range(&[(header::RANGE, "bytes=-5000")]), Ok(Some((0, 999))));
+    }
+
+    #[test]
+    fn test_range_unsatisfiable() {
+        assert_eq!(range(&[(header::RANGE, "bytes=1000-")]), Err(()));
+        assert_eq!(range(&[(header::RANGE, "bytes=1000-1200")]), Err(()));
+        assert_eq!(range(&[(header::RANGE, "bytes=-0")]), Err(()));
+        assert_eq!(
+            ContentHandler::range(&headers(&[(header::RANGE, "bytes=-10")]), 0, ETAG, None),
+            Err(())
+        );
+    }
+
+    #[test]
+    fn test_range_served_whole() {
+        // Several ranges, other units and malformed specs get the whole content
+        assert_eq!(range(&[(header::RANGE, "bytes=0-9,20-29")]), Ok(None));
+        assert_eq!(range(&[(header::RANGE, "items=0-9")]), Ok(None));
+        assert_eq!(range(&[(header::RANGE, "bytes=9-0")]), Ok(None));
+        assert_eq!(range(&[(header::RANGE, "bytes=abc")]), Ok(None));
+    }
+
+    #[test]
+    fn test_range_if_range() {
+        let current_date = httpdate::fmt_http_date(modified());
+        let stale_date = httpdate::fmt_http_date(modified() - Duration::from_secs(60));
+
+        assert_eq!(
+            range(&[(header::RANGE, "bytes=0-9"), (header::IF_RANGE, ETAG)]),
+            Ok(Some((0, 9)))
+        );
+        assert_eq!(
+            range(&[
+                (header::RANGE, "bytes=0-9"),
+                (header::IF_RANGE, "\"other\"")
+            ]),
+            Ok(None)
+        );
+        assert_eq!(
+            range(&[
+                (header::RANGE, "bytes=0-9"),
+                (header::IF_RANGE, &current_date)
+            ]),
+            Ok(Some((0, 9)))
+        );
+        assert_eq!(
+            range(&[
+                (header::RANGE, "bytes=0-9"),
+                (header::IF_RANGE, &stale_date)
+            ]),
+            Ok(None)
+        );
+        // Weak validators never match If-Range
+        assert_eq!(
+            ContentHandler::range(
+                &headers(&[
+                    (header::RANGE, "bytes=0-9"),
+                    (header::IF_RANGE, "W/\"abc\"")
+                ]),
+                1000,
+                "W/\"abc\"",
+                None,
+            ),
+            Ok(None)
+        );
+    }
+
+    #[test]
+    fn test_not_modified_etag() {
+        let not_modified = |value: &str| {
+            ContentHandler::not_modified(
+                &headers(&[(header::IF_NONE_MATCH, value)]),
+                ETAG,
+                Some(modified()),
+            )
+        };
+
+        assert!(not_modified(ETAG));
+        assert!(not_modified("\"other\", \"abc\""));
+        assert!(not_modified("*"));
+        // Compared weakly
+        assert!(not_modified("W/\"abc\""));
+        assert!(!not_modified("\"other\""));
+    }
+
+    #[test]
+    fn test_not_modified_date() {
+        let not_modified = |pairs: &[(header::HeaderName, &str)]| {
+            ContentHandler::not_modified(&headers(pairs), ETAG, Some(modified()))
+        };
+        let current_date = httpdate::fmt_http_date(modified());
+        let earlier_date = httpdate::fmt_http_date(modified() - Duration::from_secs(1));
+
+        assert!(!not_modified(&[]));
+        // The sub-second part of the modification time doesn't make it newer than its HTTP date
+        assert!(not_modified(&[(header::IF_MODIFIED_SINCE, &current_date)]));
+        assert!(!not_modified(&[(header::IF_MODIFIED_SINCE, &earlier_date)]));
+        assert!(!not_modified(&[(header::IF_MODIFIED_SINCE, "yesterday")]));
+        // If-None-Match wins over If-Modified-Since
+        assert!(!not_modified(&[
+            (header::IF_NONE_MATCH, "\"other\""),
+            (header::IF_MODIFIED_SINCE, &current_date),
+        ]));
+        assert!(!ContentHandler::not_modified(
+            &headers(&[(header::IF_MODIFIED_SINCE, &current_date)]),
+            ETAG,
+            None,
+        ));
+    }
+
+    #[test]
+    fn test_content_disposition() {
+        assert_eq!(
+            ContentHandler::content_disposition("report.pdf"),
+            "attachment; filename=\"report.pdf\"; filename*=UTF-8''report%2Epdf"
+        );
+        // Quotes, backslashes and anything outside printable ASCII are replaced in the fallback
+        assert_eq!(
+            ContentHandler::content_disposition("a \"b\"\\é.txt"),
+            "attachment; filename=\"a _b___.txt\"; filename*=UTF-8''a%20%22b%22%5C%C3%A9%2Etxt"
+        );
+    }
+
+    fn accepted(accept_encoding: &str) -> Vec<(Algorithm, f32)> {
+        ContentHandler::accepted_encodings(&headers(&[(header::ACCEPT_ENCODING, accept_encoding)]))
+    }
+
+    #[test]
+    fn test_accepted_encodings_quality() {
+        assert_eq!(
+            ContentHandler::accepted_encodings(&HeaderMap::new()),
+            vec![]
+        );
+        assert_eq!(
+            accepted("gzip;q=0.5, br;q=0.8, identity"),
+            vec![(Algorithm::Brotli, 0.8), (Algorithm::Gzip, 0.5)]
+        );
+        // q=0 rules a coding out, unknown codings are passed over
+        assert_eq!(
+            accepted("gzip;q=0, zstd, deflate"),
+            vec![(Algorithm::Zstd, 1.0)]
+        );
+        assert_eq!(accepted("GZIP;Q=0.3"), vec![(Algorithm::Gzip, 0.3)]);
+    }
+
+    #[test]
+    fn test_accepted_encodings_wildcard() {
+        assert_eq!(
+            accepted("*"),
+            vec![
+                (Algorithm::Zstd, 1.0),
+                (Algorithm::Brotli, 1.0),
+                (Algorithm::Gzip, 1.0)
+            ]
+        );
+        // Listed codings keep their own quality, the wildcard covers the rest
+        assert_eq!(
+            accepted("br;q=0, *;q=0.5"),
+            vec![(Algorithm::Zstd, 0.5), (Algorithm::Gzip, 0.5)]
+        );
+        assert_eq!(accepted("gzip, *;q=0"), vec![(Algorithm::Gzip, 1.0)]);
+    }
+
+    #[test]
+    fn test_accepted_encodings_malformed_quality() {
+        // An item whose quality can't be read is dropped, the others still count
+        assert_eq!(
+            accepted("zstd;q=high, gzip;q=0.7"),
+            vec![(Algorithm::Gzip, 0.7)]
+        );
+        assert_eq!(accepted("br;q="), vec![]);
+    }
+
+    #[test]
+    fn test_accepted_encodings_ties() {
+        // Equally wanted codings are preferred in the order of `ENCODINGS`, whatever the header's order
+        assert_eq!(
+            accepted("gzip, br, zstd"),
+            vec![
+                (Algorithm::Zstd, 1.0),
+                (Algorithm::Brotli, 1.0),
+                (Algorithm::Gzip, 1.0)
+            ]
+        );
+        assert_eq!(
+            accepted("gzip;q=0.9, br;q=0.9, zstd;q=0.4"),
+            vec![
+                (Algorithm::Brotli, 0.9),
+                (Algorithm::Gzip, 0.9),
+                (Algorithm::Zstd, 0.4)
+            ]
+        );
+    }
+}
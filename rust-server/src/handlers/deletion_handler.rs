@@ -0,0 +1,407 @@
+use std::sync::Arc;
+
+use axum::{http::StatusCode, response::IntoResponse};
+use sqlx::types::Uuid;
+
+use crate::{
+    app::AppState,
+    dtos::DeleteQuery,
+    helpers::{
+        access::AccessFilter,
+        env::Env,
+        file::FileHelper,
+        logger::{DefaultLogger, Logger},
+    },
+    models::{
+        file::{CompressedFile, File as FileRecord, FileStatus},
+        share::Access,
+        user::Principal,
+    },
+    services::{
+        blob_service::BlobService, compressed_file_service::CompressedFileService,
+        file_service::FileService,
+    },
+};
+
+/// Deletes files and compressed files. A deletion only hides the rows at first, they can be restored
+/// until `deleted_retention_secs` have passed and are then purged along with their content on disk.
+/// Only owners and admins delete and restore, compressed files also by the owner of the file they were made from.
+pub struct DeletionHandler {
+    env: Arc<Env>,
+    file_service: FileService,
+    compressed_file_service: CompressedFileService,
+    blob_service: BlobService,
+    logger: Arc<dyn Logger>,
+}
+
+impl DeletionHandler {
+    pub fn new(state: Arc<AppState>) -> Self {
+        Self {
+            env: state.env.clone(),
+            file_service: FileService::new(state.pool.clone()),
+            compressed_file_service: CompressedFileService::new(state.pool.clone()),
+            blob_service: BlobService::new(state.pool.clone()),
+
+            // Initialize the logger
+            logger: Arc::new(DefaultLogger::new::<DeletionHandler>()),
+        }
+    }
+}
+
+impl DeletionHandler {
+    /// Deletes a file along with its compressed files, `purge=true` skips the restore window
+    pub async fn delete_file(
+        &self,
+        principal: Principal,
+        id: String,
+        query: DeleteQuery,
+    ) -> impl IntoResponse {
+        let id_uuid: Uuid = match id.parse() {
+            Ok(uuid) => uuid,
+            Err(_) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    format!("Invalid ID format: {}", id),
+                );
+            }
+        };
+
+        // Deleting first also stops new compressions of the file while it is being purged
+        let access = AccessFilter::new(&principal, Access::Own);
+        let deleted = match self.file_service.soft_delete(id_uuid, &access).await {
+            Ok(deleted) => deleted,
+            Err(e) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Failed to delete file: {e}"),
+                )
+            }
+        };
+        if !query.purge {
+            return match deleted {
+                true => (StatusCode::NO_CONTENT, String::new()),
+                false => (StatusCode::NOT_FOUND, format!("File not found: {id}")),
+            };
+        }
+
+        let file = match self.file_service.find_any(id_uuid, &access).await {
+            Ok(Some(file)) => file,
+            Ok(None) => return (StatusCode::NOT_FOUND, format!("File not found: {id}")),
+            Err(e) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Failed to delete file: {e}"),
+                )
+            }
+        };
+        match self.purge_file(&file).await {
+            Ok(()) => (StatusCode::NO_CONTENT, String::new()),
+            Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e),
+        }
+    }
+
+    /// Deletes a compressed file or an archive, `purge=true` skips the restore window
+    pub async fn delete_compressed_file(
+        &self,
+        principal: Principal,
+        id: String,
+        query: DeleteQuery,
+    ) -> impl IntoResponse {
+        let id_uuid: Uuid = match id.parse() {
+            Ok(uuid) => uuid,
+            Err(_) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    format!("Invalid ID format: {}", id),
+                );
+            }
+        };
+
+        let access = AccessFilter::new(&principal, Access::Own);
+        if !query.purge {
+            return match self
+                .compressed_file_service
+                .soft_delete(id_uuid, &access)
+                .await
+            {
+                Ok(true) => (StatusCode::NO_CONTENT, String::new()),
+                Ok(false) => (
+                    StatusCode::NOT_FOUND,
+                    format!("Compressed file not found: {id}"),
+                ),
+                Err(e) => (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Failed to delete compressed file: {e}"),
+                ),
+            };
+        }
+
+        let compressed_file = match self
+            .compressed_file_service
+            .find_any(id_uuid, &access)
+            .await
+        {
+            Ok(Some(compressed_file)) => compressed_file,
+            Ok(None) => {
+                return (
+                    StatusCode::NOT_FOUND,
+                    format!("Compressed file not found: {id}"),
+                )
+            }
+            Err(e) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Failed to delete compressed file: {e}"),
+                )
+            }
+        };
+        match self.purge_compressed_file(&compressed_file).await {
+            Ok(()) => (StatusCode::NO_CONTENT, String::new()),
+            Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e),
+        }
+    }
+
+    /// Brings back a file deleted within the restore window, with the compressed files deleted along with it
+    pub async fn restore_file(&self, principal: Principal, id: String) -> impl IntoResponse {
+        let id_uuid: Uuid = match id.parse() {
+            Ok(uuid) => uuid,
+            Err(_) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    format!("Invalid ID format: {}", id),
+                );
+            }
+        };
+
+        let access = AccessFilter::new(&principal, Access::Own);
+        match self
+            .file_service
+            .restore(id_uuid, self.env.deleted_retention_secs, &access)
+            .await
+        {
+            Ok(true) => {}
+            Ok(false) => {
+                return (
+                    StatusCode::NOT_FOUND,
+                    format!("No restorable file found: {id}"),
+                )
+            }
+            Err(e) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Failed to restore file: {e}"),
+                )
+            }
+        }
+
+        match self.file_service.find_one(id_uuid, &access).await {
+            Ok(file) => (StatusCode::OK, serde_json::json!(file).to_string()),
+            Err(e) => (StatusCode::NOT_FOUND, format!("File not found: {e}")),
+        }
+    }
+
+    /// Brings back a compressed file deleted within the restore window, its file must not be deleted
+    pub async fn restore_compressed_file(
+        &self,
+        principal: Principal,
+        id: String,
+    ) -> impl IntoResponse {
+        let id_uuid: Uuid = match id.parse() {
+            Ok(uuid) => uuid,
+            Err(_) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    format!("Invalid ID format: {}", id),
+                );
+            }
+        };
+
+        let access = AccessFilter::new(&principal, Access::Own);
+        match self
+            .compressed_file_service
+            .restore(id_uuid, self.env.deleted_retention_secs, &access)
+            .await
+        {
+            Ok(true) => {}
+            Ok(false) => {
+                return (
+                    StatusCode::NOT_FOUND,
+                    format!("No restorable compressed file found: {id}"),
+                )
+            }
+            Err(e) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Failed to restore compressed file: {e}"),
+                )
+            }
+        }
+
+        match self
+            .compressed_file_service
+            .find_one(id_uuid, &access)
+            .await
+        {
+            Ok(compressed_file) => (
+                StatusCode::OK,
+                serde_json::json!(compressed_file).to_string(),
+            ),
+            Err(e) => (
+                StatusCode::NOT_FOUND,
+                format!("Compressed file not found: {e}"),
+            ),
+        }
+    }
+
+    /// Purges everything deleted before the restore window, run periodically in the background
+    pub async fn purge_expired(&self) {
+        let retention_secs = self.env.deleted_retention_secs;
+
+        // Files first, their compressions still reused by files of the same content are handed over
+        match self.file_service.find_expired(retention_secs).await {
+            Ok(ids) => {
+                for id in ids {
+                    if let Ok(Some(file)) = self
+                        .file_service
+                        .find_any(id, &AccessFilter::Everything)
+                        .await
+                    {
+                        if let Err(e) = self.purge_file(&file).await {
+                            self.logger.error(&e);
+                        }
+                    }
+                }
+            }
+            Err(e) => self
+                .logger
+                .error(&format!("Failed to find expired files: {e}")),
+        }
+
+        match self
+            .compressed_file_service
+            .find_expired(retention_secs)
+            .await
+        {
+            Ok(ids) => {
+                for id in ids {
+                    if let Ok(Some(compressed_file)) = self
+                        .compressed_file_service
+                        .find_any(id, &AccessFilter::Everything)
+                        .await
+                    {
+                        if let Err(e) = self.purge_compressed_file(&compressed_file).await {
+                            self.logger.error(&e);
+                        }
+                    }
+                }
+            }
+            Err(e) => self
+                .logger
+                .error(&format!("Failed to find expired compressed files: {e}")),
+        }
+    }
+}
+
+impl DeletionHandler {
+    /// Removes a file, its compressed files and its content for good.
+    /// Blob-backed content is only removed with the last file referencing it.
+    async fn purge_file(&self, file: &FileRecord) -> Result<(), String> {
+        let id_uuid: Uuid = file.id.parse().map_err(|e| format!("{e}"))?;
+
+        // Other files of the same content reuse its compressions, they keep them
+        if let Some(sha256) = &file.blob_sha256 {
+            let transferred = self
+                .compressed_file_service
+                .transfer(id_uuid, sha256)
+                .await
+                .map_err(|e| format!("Failed to hand over compressed files of {}: {e}", file.id))?;
+            if transferred > 0 {
+                self.logger.debug(&format!(
+                    "Handed {transferred} compressed files of {} over to a file of the same content",
+                    file.id
+                ));
+            }
+        }
+        let compressed_files = self
+            .compressed_file_service
+            .find_by_file_id(id_uuid)
+            .await
+            .map_err(|e| format!("Failed to find compressed files of {}: {e}", file.id))?;
+        for compressed_file in &compressed_files {
+            self.purge_compressed_file(compressed_file).await?;
+        }
+
+        if !self
+            .file_service
+            .delete(id_uuid)
+            .await
+            .map_err(|e| format!("Failed to purge file {}: {e}", file.id))?
+        {
+            // Purged concurrently, the content went with it
+            return Ok(());
+        }
+
+        match &file.blob_sha256 {
+            Some(sha256) => {
+                let blob_path = FileHelper::get_blob_path(sha256, &self.env.uploads_dir)
+                    .ok_or_else(|| format!("Failed to get blob path: {sha256}"))?;
+                self.blob_service
+                    .release(sha256, &blob_path)
+                    .await
+                    .map_err(|e| format!("Failed to release blob {sha256}: {e}"))?;
+            }
+            None => {
+                if let Some(path) =
+                    FileHelper::get_uploaded_file_path(&file.file_ref, &self.env.uploads_dir)
+                {
+                    Self::remove_file(&path).await?;
+                }
+            }
+        }
+
+        self.logger
+            .debug(&format!("File purged: {}", file.file_ref));
+        Ok(())
+    }
+
+    /// Removes a compressed file and its output for good
+    async fn purge_compressed_file(&self, compressed_file: &CompressedFile) -> Result<(), String> {
+        let id_uuid: Uuid = compressed_file.id.parse().map_err(|e| format!("{e}"))?;
+
+        if !self
+            .compressed_file_service
+            .delete(id_uuid)
+            .await
+            .map_err(|e| {
+                format!(
+                    "Failed to purge compressed file {}: {e}",
+                    compressed_file.id
+                )
+            })?
+        {
+            return Ok(());
+        }
+
+        // A compression still running notices its row is gone when it ends, and removes its output then
+        if compressed_file.status == FileStatus::Compressing {
+            return Ok(());
+        }
+
+        if let Some(path) = FileHelper::get_output_path(compressed_file, &self.env.compressed_dir) {
+            Self::remove_file(&path).await?;
+        }
+
+        self.logger
+            .debug(&format!("Compressed file purged: {}", compressed_file.id));
+        Ok(())
+    }
+
+    // Content that is already gone is fine, e.g. a skipped compression never kept its output
+    async fn remove_file(path: &str) -> Result<(), String> {
+        match tokio::fs::remove_file(path).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(format!("Failed to remove {path}: {e}")),
+        }
+    }
+}
@@ -0,0 +1,312 @@
+use std::{net::IpAddr, sync::Arc};
+
+use axum::{
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use sqlx::types::Uuid;
+
+use crate::{
+    app::AppState,
+    dtos::{CreateDownloadLink, DownloadLinkRequest, DownloadQuery},
+    handlers::content_handler::ContentHandler,
+    helpers::{
+        access::AccessFilter,
+        download_link::DownloadLinkHelper,
+        env::Env,
+        logger::{DefaultLogger, Logger},
+    },
+    models::{
+        share::{Access, DownloadLink},
+        user::Principal,
+    },
+    services::{
+        compressed_file_service::CompressedFileService, download_link_service::DownloadLinkService,
+        file_service::FileService,
+    },
+};
+
+// How long a link works when the request doesn't say
+const DEFAULT_EXPIRY_SECS: u64 = 24 * 60 * 60;
+// Links are meant to be handed out for a while, not for good
+const MAX_EXPIRY_SECS: u64 = 365 * 24 * 60 * 60;
+
+/// Hands out signed, expiring links to files and compressed files, so they can be downloaded
+/// without credentials. Only owners and admins create links, whoever holds one can download.
+pub struct DownloadLinkHandler {
+    env: Arc<Env>,
+    file_service: FileService,
+    compressed_file_service: CompressedFileService,
+    download_link_service: DownloadLinkService,
+    content_handler: ContentHandler,
+    logger: Arc<dyn Logger>,
+}
+
+impl DownloadLinkHandler {
+    pub fn new(state: Arc<AppState>) -> Self {
+        Self {
+            env: state.env.clone(),
+            file_service: FileService::new(state.pool.clone()),
+            compressed_file_service: CompressedFileService::new(state.pool.clone()),
+            download_link_service: DownloadLinkService::new(state.pool.clone()),
+            content_handler: ContentHandler::new(state.clone()),
+
+            // Initialize the logger
+            logger: Arc::new(DefaultLogger::new::<DownloadLinkHandler>()),
+        }
+    }
+}
+
+impl DownloadLinkHandler {
+    pub async fn create_for_file(
+        &self,
+        principal: Principal,
+        file_id: String,
+        request: DownloadLinkRequest,
+    ) -> impl IntoResponse {
+        let file_id: Uuid = match file_id.parse() {
+            Ok(uuid) => uuid,
+            Err(_) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    format!("Invalid ID format: {}", file_id),
+                );
+            }
+        };
+
+        let access = AccessFilter::new(&principal, Access::Own);
+        if let Err(e) = self.file_service.find_one(file_id, &access).await {
+            return (StatusCode::NOT_FOUND, format!("File not found: {e}"));
+        }
+
+        self.create(principal, Some(file_id), None, request).await
+    }
+
+    pub async fn create_for_compressed_file(
+        &self,
+        principal: Principal,
+        id: String,
+        request: DownloadLinkRequest,
+    ) -> impl IntoResponse {
+        let id_uuid: Uuid = match id.parse() {
+            Ok(uuid) => uuid,
+            Err(_) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    format!("Invalid ID format: {}", id),
+                );
+            }
+        };
+
+        let access = AccessFilter::new(&principal, Access::Own);
+        if let Err(e) = self
+            .compressed_file_service
+            .find_one(id_uuid, &access)
+            .await
+        {
+            return (
+                StatusCode::NOT_FOUND,
+                format!("Compressed file not found: {e}"),
+            );
+        }
+
+        self.create(principal, None, Some(id_uuid), request).await
+    }
+
+    /// Serves the file behind the link once its signature, expiry, download count and IP restriction
+    /// are checked. Requests answered with content count as a download, range requests included, the download
+    /// is counted up front so concurrent requests can't go over the limit and given back when no content is
+    /// sent. The IP is the one of the connecting client, proxies in front of the server aren't looked through.
+    pub async fn download(
+        &self,
+        id: String,
+        query: DownloadQuery,
+        ip: IpAddr,
+        headers: HeaderMap,
+    ) -> Response {
+        let id_uuid: Uuid = match id.parse() {
+            Ok(uuid) => uuid,
+            Err(_) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    format!("Invalid ID format: {}", id),
+                )
+                    .into_response();
+            }
+        };
+        let Some(secret) = &self.env.download_link_secret else {
+            return (
+                StatusCode::NOT_FOUND,
+                format!("Download link not found: {id_uuid}"),
+            )
+                .into_response();
+        };
+
+        if !DownloadLinkHelper::verify(
+            secret,
+            &id_uuid.to_string(),
+            query.expires,
+            &query.signature,
+        ) {
+            return (
+                StatusCode::FORBIDDEN,
+                "Invalid download link signature".to_string(),
+            )
+                .into_response();
+        }
+        if DownloadLinkHelper::is_expired(query.expires, chrono::Utc::now().timestamp()) {
+            return (StatusCode::GONE, "Download link has expired".to_string()).into_response();
+        }
+
+        let link = match self.download_link_service.find_one(id_uuid).await {
+            Ok(link) => link,
+            Err(e) => {
+                return (
+                    StatusCode::NOT_FOUND,
+                    format!("Download link not found: {e}"),
+                )
+                    .into_response()
+            }
+        };
+        if let Some(allowed_ip) = &link.allowed_ip {
+            if *allowed_ip != ip.to_canonical().to_string() {
+                return (
+                    StatusCode::FORBIDDEN,
+                    "Download link is restricted to another IP address".to_string(),
+                )
+                    .into_response();
+            }
+        }
+
+        let link = match self.download_link_service.claim(id_uuid).await {
+            Ok(Some(link)) => link,
+            Ok(None) => {
+                return (
+                    StatusCode::GONE,
+                    "Download link has expired or has no downloads left".to_string(),
+                )
+                    .into_response()
+            }
+            Err(e) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Failed to count download: {e}"),
+                )
+                    .into_response()
+            }
+        };
+        self.logger.debug(&format!(
+            "Download link(id: {}) used from {}, {} download(s)",
+            link.id, ip, link.downloads
+        ));
+
+        // Access was checked when the link was created, the link itself is the permission now
+        let access = AccessFilter::Everything;
+        let response = match (
+            link.file_id.map(|id| id.parse::<Uuid>()),
+            link.compressed_file_id.map(|id| id.parse::<Uuid>()),
+        ) {
+            (Some(Ok(file_id)), _) => {
+                self.content_handler
+                    .serve_file(file_id, &access, headers)
+                    .await
+            }
+            (_, Some(Ok(compressed_file_id))) => {
+                self.content_handler
+                    .serve_compressed_file(compressed_file_id, &access, headers)
+                    .await
+            }
+            _ => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to resolve download link: {}", link.id),
+            )
+                .into_response(),
+        };
+
+        // Not modified, unsatisfiable ranges and errors don't use up the link
+        if !matches!(
+            response.status(),
+            StatusCode::OK | StatusCode::PARTIAL_CONTENT
+        ) {
+            if let Err(e) = self.download_link_service.refund(id_uuid).await {
+                self.logger.error(&format!(
+                    "Failed to give back download of link(id: {}): {e}",
+                    link.id
+                ));
+            }
+        }
+        response
+    }
+}
+
+impl DownloadLinkHandler {
+    async fn create(
+        &self,
+        principal: Principal,
+        file_id: Option<Uuid>,
+        compressed_file_id: Option<Uuid>,
+        request: DownloadLinkRequest,
+    ) -> (StatusCode, String) {
+        let Some(secret) = &self.env.download_link_secret else {
+            return (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "Download links are disabled, DOWNLOAD_LINK_SECRET is not set".to_string(),
+            );
+        };
+
+        let expires_in_secs = request.expires_in_secs.unwrap_or(DEFAULT_EXPIRY_SECS);
+        if expires_in_secs == 0 || expires_in_secs > MAX_EXPIRY_SECS {
+            return (
+                StatusCode::BAD_REQUEST,
+                format!("expires_in_secs must be between 1 and {MAX_EXPIRY_SECS}"),
+            );
+        }
+        if request.max_downloads == Some(0) {
+            return (
+                StatusCode::BAD_REQUEST,
+                "max_downloads must be at least 1".to_string(),
+            );
+        }
+        let allowed_ip = match request.ip.as_deref().map(str::parse::<IpAddr>) {
+            Some(Ok(ip)) => Some(ip.to_canonical().to_string()),
+            Some(Err(_)) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    format!("Invalid IP address: {}", request.ip.unwrap_or_default()),
+                )
+            }
+            None => None,
+        };
+
+        match self
+            .download_link_service
+            .create(CreateDownloadLink {
+                file_id,
+                compressed_file_id,
+                expires_in_secs,
+                max_downloads: request.max_downloads,
+                allowed_ip,
+                created_by: principal.user_id,
+            })
+            .await
+        {
+            Ok(link) => {
+                self.logger.debug(&format!(
+                    "Download link(id: {}) created, expires at {}",
+                    link.id, link.expires_at
+                ));
+                (StatusCode::CREATED, self.with_url(secret, &link))
+            }
+            Err(e) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to create download link: {e}"),
+            ),
+        }
+    }
+
+    fn with_url(&self, secret: &str, link: &DownloadLink) -> String {
+        let mut body = serde_json::json!(link);
+        body["url"] = DownloadLinkHelper::url(&self.env.public_url, secret, link).into();
+        body.to_string()
+    }
+}
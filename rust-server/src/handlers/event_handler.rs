@@ -0,0 +1,279 @@
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    future,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use axum::{
+    extract::ws::{close_code, CloseFrame, Message, WebSocket, WebSocketUpgrade},
+    http::StatusCode,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+};
+use futures::{
+    stream::{self, BoxStream},
+    SinkExt, StreamExt,
+};
+use sqlx::types::Uuid;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::{
+    app::AppState,
+    helpers::{
+        access::AccessFilter,
+        logger::{DefaultLogger, Logger},
+    },
+    models::{file::FileStatus, job::JobEvent, share::Access, user::Principal},
+    services::{compressed_file_service::CompressedFileService, event_service::EventService},
+};
+
+// Wait before listening again after losing the connection to Postgres
+const RELAY_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Streams job events, as Server-Sent Events or over a WebSocket.
+/// A compressed file's stream starts with its current state and ends with its final status.
+/// Callers only get the events of compressed files they can read.
+pub struct EventHandler {
+    job_events: broadcast::Sender<JobEvent>,
+    compressed_file_service: CompressedFileService,
+    event_service: EventService,
+    logger: Arc<dyn Logger>,
+}
+
+impl EventHandler {
+    pub fn new(state: Arc<AppState>) -> Self {
+        Self {
+            job_events: state.job_events.clone(),
+            compressed_file_service: CompressedFileService::new(state.pool.clone()),
+            event_service: EventService::new(state.pool.clone()),
+
+            // Initialize the logger
+            logger: Arc::new(DefaultLogger::new::<EventHandler>()),
+        }
+    }
+}
+
+impl EventHandler {
+    /// Events of one compressed file as Server-Sent Events
+    pub async fn compressed_file_events(&self, principal: Principal, id: String) -> Response {
+        match self.events_of(&principal, id).await {
+            Ok(events) => Self::sse(events),
+            Err(e) => e.into_response(),
+        }
+    }
+
+    /// Events of one compressed file over a WebSocket, one JSON message per event
+    pub async fn compressed_file_socket(
+        &self,
+        principal: Principal,
+        id: String,
+        upgrade: WebSocketUpgrade,
+    ) -> Response {
+        match self.events_of(&principal, id).await {
+            Ok(events) => self.socket(upgrade, events),
+            Err(e) => e.into_response(),
+        }
+    }
+
+    /// Events of every job, for dashboards
+    pub fn all_events(&self, principal: Principal) -> Response {
+        Self::sse(self.readable(&principal, self.job_events.subscribe()))
+    }
+
+    /// Events of every job over a WebSocket
+    pub fn all_socket(&self, principal: Principal, upgrade: WebSocketUpgrade) -> Response {
+        self.socket(
+            upgrade,
+            self.readable(&principal, self.job_events.subscribe()),
+        )
+    }
+
+    /// Passes the events the workers of every instance publish to this instance's streams, runs in the background
+    pub async fn relay(&self) {
+        loop {
+            if let Err(e) = self.event_service.relay(&self.job_events).await {
+                self.logger
+                    .error(&format!("Failed to listen for job events: {e}"));
+            }
+            tokio::time::sleep(RELAY_RETRY_DELAY).await;
+        }
+    }
+}
+
+impl EventHandler {
+    // Current state of the compressed file followed by its live events, up to its final status
+    async fn events_of(
+        &self,
+        principal: &Principal,
+        id: String,
+    ) -> Result<BoxStream<'static, JobEvent>, (StatusCode, String)> {
+        let id_uuid: Uuid = match id.parse() {
+            Ok(uuid) => uuid,
+            Err(_) => {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    format!("Invalid ID format: {}", id),
+                ))
+            }
+        };
+
+        // Subscribe first so nothing happening while the row is read gets lost
+        let receiver = self.job_events.subscribe();
+        let access = AccessFilter::new(principal, Access::Read);
+        let compressed_file = match self
+            .compressed_file_service
+            .find_one(id_uuid, &access)
+            .await
+        {
+            Ok(value) => value,
+            Err(e) => {
+                return Err((
+                    StatusCode::NOT_FOUND,
+                    format!("Compressed file not found: {e}"),
+                ))
+            }
+        };
+
+        let mut current = vec![JobEvent::Status {
+            compressed_file_id: compressed_file.id.clone(),
+            status: compressed_file.status,
+            error: compressed_file.error.clone(),
+        }];
+        if compressed_file.status.is_final() {
+            return Ok(stream::iter(current).boxed());
+        }
+        if compressed_file.status == FileStatus::Compressing {
+            current.push(JobEvent::Progress {
+                compressed_file_id: compressed_file.id.clone(),
+                bytes_processed: compressed_file.bytes_processed as u64,
+                total_bytes: compressed_file.total_bytes.map(|total| total as u64),
+                percent: Some(compressed_file.percent),
+            });
+        }
+
+        let live = Self::live(receiver)
+            .filter(move |event| future::ready(event.compressed_file_id() == compressed_file.id));
+        // The final event is followed by an end marker, so the stream ends without waiting for the next event
+        let events = stream::iter(current)
+            .chain(live)
+            .flat_map(|event| {
+                let end = event.is_final().then_some(None);
+                stream::iter(std::iter::once(Some(event)).chain(end))
+            })
+            .take_while(|event| future::ready(event.is_some()))
+            .filter_map(future::ready);
+
+        Ok(events.boxed())
+    }
+
+    // Live events of the compressed files the caller can read, each one is only looked up once per stream
+    fn readable(
+        &self,
+        principal: &Principal,
+        receiver: broadcast::Receiver<JobEvent>,
+    ) -> BoxStream<'static, JobEvent> {
+        let access = AccessFilter::new(principal, Access::Read);
+        if access == AccessFilter::Everything {
+            return Self::live(receiver).boxed();
+        }
+
+        let compressed_file_service = self.compressed_file_service.clone();
+        let readable = Arc::new(Mutex::new(HashMap::<String, bool>::new()));
+        Self::live(receiver)
+            .filter_map(move |event| {
+                let compressed_file_service = compressed_file_service.clone();
+                let readable = readable.clone();
+                async move {
+                    let id = event.compressed_file_id().to_string();
+                    let known = readable.lock().unwrap().get(&id).copied();
+                    let is_readable = match known {
+                        Some(is_readable) => is_readable,
+                        None => {
+                            let is_readable = match id.parse() {
+                                Ok(id_uuid) => compressed_file_service
+                                    .find_one(id_uuid, &access)
+                                    .await
+                                    .is_ok(),
+                                Err(_) => false,
+                            };
+                            readable.lock().unwrap().insert(id, is_readable);
+                            is_readable
+                        }
+                    };
+                    is_readable.then_some(event)
+                }
+            })
+            .boxed()
+    }
+
+    // Events as they are published, subscribers too slow to keep up skip what they missed
+    fn live(receiver: broadcast::Receiver<JobEvent>) -> impl futures::Stream<Item = JobEvent> {
+        stream::unfold(receiver, |mut receiver| async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(event) => return Some((event, receiver)),
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        })
+    }
+
+    fn sse(events: BoxStream<'static, JobEvent>) -> Response {
+        let events = events.map(|event| {
+            Ok::<_, Infallible>(
+                Event::default()
+                    .event(event.name())
+                    .data(serde_json::json!(event).to_string()),
+            )
+        });
+        Sse::new(events)
+            .keep_alive(KeepAlive::default())
+            .into_response()
+    }
+
+    // Pushes the events over the WebSocket once the response switching protocols has gone out
+    fn socket(&self, upgrade: WebSocketUpgrade, events: BoxStream<'static, JobEvent>) -> Response {
+        let logger = self.logger.clone();
+        upgrade.on_upgrade(move |socket| async move {
+            if let Err(e) = Self::push(socket, events).await {
+                logger.debug(&format!("WebSocket closed: {e}"));
+            }
+        })
+    }
+
+    // Sends every event as a JSON text message, then closes the connection.
+    // Stops early when the client closes it or goes away, pings are answered by axum and other messages ignored.
+    async fn push(
+        socket: WebSocket,
+        mut events: BoxStream<'static, JobEvent>,
+    ) -> Result<(), axum::Error> {
+        let (mut sender, mut receiver) = socket.split();
+        loop {
+            tokio::select! {
+                event = events.next() => match event {
+                    Some(event) => {
+                        let message = serde_json::json!(event).to_string();
+                        sender.send(Message::Text(message.into())).await?
+                    }
+                    None => break,
+                },
+                message = receiver.next() => match message {
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return Ok(()),
+                    Some(Ok(_)) => {}
+                },
+            }
+        }
+
+        sender
+            .send(Message::Close(Some(CloseFrame {
+                code: close_code::NORMAL,
+                reason: "".into(),
+            })))
+            .await
+    }
+}
@@ -0,0 +1,168 @@
+use std::sync::Arc;
+
+use axum::{http::StatusCode, response::IntoResponse};
+use sqlx::types::Uuid;
+
+use crate::{
+    app::AppState,
+    dtos::GroupRequest,
+    helpers::logger::{DefaultLogger, Logger},
+    services::group_service::GroupService,
+};
+
+/// Manages the groups files can be shared with, admins only
+pub struct GroupHandler {
+    group_service: GroupService,
+    logger: Arc<dyn Logger>,
+}
+
+impl GroupHandler {
+    pub fn new(state: Arc<AppState>) -> Self {
+        Self {
+            group_service: GroupService::new(state.pool.clone()),
+
+            // Initialize the logger
+            logger: Arc::new(DefaultLogger::new::<GroupHandler>()),
+        }
+    }
+}
+
+impl GroupHandler {
+    pub async fn create(&self, request: GroupRequest) -> impl IntoResponse {
+        let name = request.name.trim();
+        if name.is_empty() {
+            return (
+                StatusCode::BAD_REQUEST,
+                "Group name can't be empty".to_string(),
+            );
+        }
+
+        match self.group_service.create(name).await {
+            Ok(group) => {
+                self.logger
+                    .debug(&format!("Group(id: {}) created", group.id));
+                (StatusCode::CREATED, serde_json::json!(group).to_string())
+            }
+            Err(e)
+                if e.as_database_error()
+                    .is_some_and(|e| e.is_unique_violation()) =>
+            {
+                (
+                    StatusCode::CONFLICT,
+                    format!("Group already exists: {name}"),
+                )
+            }
+            Err(e) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to create group: {e}"),
+            ),
+        }
+    }
+
+    pub async fn list(&self) -> impl IntoResponse {
+        match self.group_service.find_all().await {
+            Ok(groups) => (StatusCode::OK, serde_json::json!(groups).to_string()),
+            Err(e) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to list groups: {e}"),
+            ),
+        }
+    }
+
+    pub async fn get(&self, id: String) -> impl IntoResponse {
+        let id_uuid: Uuid = match id.parse() {
+            Ok(uuid) => uuid,
+            Err(_) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    format!("Invalid ID format: {}", id),
+                );
+            }
+        };
+
+        match self.group_service.find_one(id_uuid).await {
+            Ok(group) => (StatusCode::OK, serde_json::json!(group).to_string()),
+            Err(e) => (StatusCode::NOT_FOUND, format!("Group not found: {e}")),
+        }
+    }
+
+    /// Removes the group, the files shared with it are no longer shared with its members
+    pub async fn delete(&self, id: String) -> impl IntoResponse {
+        let id_uuid: Uuid = match id.parse() {
+            Ok(uuid) => uuid,
+            Err(_) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    format!("Invalid ID format: {}", id),
+                );
+            }
+        };
+
+        match self.group_service.delete(id_uuid).await {
+            Ok(result) if result.rows_affected() > 0 => (StatusCode::NO_CONTENT, String::new()),
+            Ok(_) => (StatusCode::NOT_FOUND, format!("Group not found: {id_uuid}")),
+            Err(e) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to delete group: {e}"),
+            ),
+        }
+    }
+
+    /// Adds the user to the group, they get access to every file shared with it
+    pub async fn add_member(&self, id: String, user_id: String) -> impl IntoResponse {
+        let (id_uuid, user_id) = match Self::parse_ids(&id, &user_id) {
+            Ok(ids) => ids,
+            Err(e) => return e,
+        };
+
+        match self.group_service.add_member(id_uuid, user_id).await {
+            Ok(_) => match self.group_service.find_one(id_uuid).await {
+                Ok(group) => (StatusCode::OK, serde_json::json!(group).to_string()),
+                Err(e) => (StatusCode::NOT_FOUND, format!("Group not found: {e}")),
+            },
+            Err(e)
+                if e.as_database_error()
+                    .is_some_and(|e| e.is_foreign_key_violation()) =>
+            {
+                (StatusCode::NOT_FOUND, "Group or user not found".to_string())
+            }
+            Err(e) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to add group member: {e}"),
+            ),
+        }
+    }
+
+    pub async fn remove_member(&self, id: String, user_id: String) -> impl IntoResponse {
+        let (id_uuid, user_id) = match Self::parse_ids(&id, &user_id) {
+            Ok(ids) => ids,
+            Err(e) => return e,
+        };
+
+        match self.group_service.remove_member(id_uuid, user_id).await {
+            Ok(result) if result.rows_affected() > 0 => (StatusCode::NO_CONTENT, String::new()),
+            Ok(_) => (
+                StatusCode::NOT_FOUND,
+                format!("User(id: {user_id}) is not a member of group(id: {id_uuid})"),
+            ),
+            Err(e) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to remove group member: {e}"),
+            ),
+        }
+    }
+}
+
+impl GroupHandler {
+    fn parse_ids(id: &str, user_id: &str) -> Result<(Uuid, Uuid), (StatusCode, String)> {
+        let parse = |id: &str| {
+            id.parse::<Uuid>().map_err(|_| {
+                (
+                    StatusCode::BAD_REQUEST,
+                    format!("Invalid ID format: {}", id),
+                )
+            })
+        };
+        Ok((parse(id)?, parse(user_id)?))
+    }
+}
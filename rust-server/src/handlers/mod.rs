@@ -1,2 +1,12 @@
 pub mod upload_file_handler;
-pub mod compress_file_handler;
\ No newline at end of file
+pub mod compress_file_handler;
+pub mod tus_upload_handler;
+pub mod deletion_handler;
+pub mod content_handler;
+pub mod event_handler;
+pub mod webhook_handler;
+pub mod api_key_handler;
+pub mod user_handler;
+pub mod group_handler;
+pub mod share_handler;
+pub mod download_link_handler;
\ No newline at end of file
@@ -0,0 +1,179 @@
+use std::sync::Arc;
+
+use axum::{http::StatusCode, response::IntoResponse};
+use sqlx::types::Uuid;
+
+use crate::{
+    app::AppState,
+    dtos::{CreateFileShare, ShareRequest},
+    helpers::{
+        access::AccessFilter,
+        logger::{DefaultLogger, Logger},
+    },
+    models::{
+        share::{Access, Permission},
+        user::Principal,
+    },
+    services::{file_service::FileService, share_service::ShareService},
+};
+
+/// Shares files with other users or groups, only the file's owner and admins manage its shares
+pub struct ShareHandler {
+    file_service: FileService,
+    share_service: ShareService,
+    logger: Arc<dyn Logger>,
+}
+
+impl ShareHandler {
+    pub fn new(state: Arc<AppState>) -> Self {
+        Self {
+            file_service: FileService::new(state.pool.clone()),
+            share_service: ShareService::new(state.pool.clone()),
+
+            // Initialize the logger
+            logger: Arc::new(DefaultLogger::new::<ShareHandler>()),
+        }
+    }
+}
+
+impl ShareHandler {
+    /// Shares the file with a user or a group, sharing it again with them changes the permission
+    pub async fn create(
+        &self,
+        principal: Principal,
+        file_id: String,
+        request: ShareRequest,
+    ) -> impl IntoResponse {
+        let file_id = match self.find_own(&principal, &file_id).await {
+            Ok(file_id) => file_id,
+            Err(e) => return e,
+        };
+        let permission: Permission = match request.permission.parse() {
+            Ok(permission) => permission,
+            Err(e) => return (StatusCode::BAD_REQUEST, e),
+        };
+        let (user_id, group_id) = match (request.user_id, request.group_id) {
+            (Some(id), None) => match id.parse() {
+                Ok(uuid) => (Some(uuid), None),
+                Err(_) => {
+                    return (
+                        StatusCode::BAD_REQUEST,
+                        format!("Invalid ID format: {}", id),
+                    )
+                }
+            },
+            (None, Some(id)) => match id.parse() {
+                Ok(uuid) => (None, Some(uuid)),
+                Err(_) => {
+                    return (
+                        StatusCode::BAD_REQUEST,
+                        format!("Invalid ID format: {}", id),
+                    )
+                }
+            },
+            _ => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    "Either user_id or group_id is required".to_string(),
+                )
+            }
+        };
+
+        match self
+            .share_service
+            .create(CreateFileShare {
+                file_id,
+                user_id,
+                group_id,
+                permission,
+            })
+            .await
+        {
+            Ok(share) => {
+                self.logger.debug(&format!(
+                    "File(id: {}) shared, share(id: {})",
+                    file_id, share.id
+                ));
+                (StatusCode::CREATED, serde_json::json!(share).to_string())
+            }
+            Err(e)
+                if e.as_database_error()
+                    .is_some_and(|e| e.is_foreign_key_violation()) =>
+            {
+                (StatusCode::NOT_FOUND, "User or group not found".to_string())
+            }
+            Err(e) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to share file: {e}"),
+            ),
+        }
+    }
+
+    pub async fn list(&self, principal: Principal, file_id: String) -> impl IntoResponse {
+        let file_id = match self.find_own(&principal, &file_id).await {
+            Ok(file_id) => file_id,
+            Err(e) => return e,
+        };
+
+        match self.share_service.find_for_file(file_id).await {
+            Ok(shares) => (StatusCode::OK, serde_json::json!(shares).to_string()),
+            Err(e) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to list shares: {e}"),
+            ),
+        }
+    }
+
+    /// Stops sharing the file with the share's user or group
+    pub async fn delete(
+        &self,
+        principal: Principal,
+        file_id: String,
+        id: String,
+    ) -> impl IntoResponse {
+        let file_id = match self.find_own(&principal, &file_id).await {
+            Ok(file_id) => file_id,
+            Err(e) => return e,
+        };
+        let id_uuid: Uuid = match id.parse() {
+            Ok(uuid) => uuid,
+            Err(_) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    format!("Invalid ID format: {}", id),
+                );
+            }
+        };
+
+        match self.share_service.delete(file_id, id_uuid).await {
+            Ok(result) if result.rows_affected() > 0 => (StatusCode::NO_CONTENT, String::new()),
+            Ok(_) => (StatusCode::NOT_FOUND, format!("Share not found: {id_uuid}")),
+            Err(e) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to delete share: {e}"),
+            ),
+        }
+    }
+}
+
+impl ShareHandler {
+    // Checks the file exists and the caller may share it, files shared with the caller can't be shared further
+    async fn find_own(
+        &self,
+        principal: &Principal,
+        id: &str,
+    ) -> Result<Uuid, (StatusCode, String)> {
+        let id_uuid: Uuid = id.parse().map_err(|_| {
+            (
+                StatusCode::BAD_REQUEST,
+                format!("Invalid ID format: {}", id),
+            )
+        })?;
+
+        self.file_service
+            .find_one(id_uuid, &AccessFilter::new(principal, Access::Own))
+            .await
+            .map(|_| id_uuid)
+            .map_err(|e| (StatusCode::NOT_FOUND, format!("File not found: {e}")))
+    }
+}
@@ -0,0 +1,713 @@
+use std::{
+    collections::HashSet,
+    io::{self, SeekFrom},
+    sync::{Arc, Mutex},
+};
+
+use axum::{
+    body::Body,
+    http::{header, HeaderMap, HeaderName, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use futures::StreamExt;
+use sqlx::types::Uuid;
+use tokio::{
+    io::{AsyncSeekExt, AsyncWriteExt},
+    sync::Notify,
+};
+
+use crate::{
+    app::AppState,
+    dtos::{CreateFile, CreateTusUpload},
+    handlers::upload_file_handler::UploadFileHandler,
+    helpers::{
+        env::Env,
+        file::FileHelper,
+        logger::{DefaultLogger, Logger},
+    },
+    models::{
+        file::{File as FileRecord, TusUpload},
+        user::Principal,
+        webhook::WebhookEvent,
+    },
+    services::{
+        blob_service::BlobService, file_service::FileService, tus_upload_service::TusUploadService,
+        webhook_service::WebhookService,
+    },
+};
+
+// The only protocol version spoken, and the extensions implemented on top of the core protocol
+const TUS_VERSION: &str = "1.0.0";
+const TUS_EXTENSIONS: &str = "creation,termination";
+const OFFSET_CONTENT_TYPE: &str = "application/offset+octet-stream";
+
+const TUS_RESUMABLE: HeaderName = HeaderName::from_static("tus-resumable");
+const TUS_VERSION_HEADER: HeaderName = HeaderName::from_static("tus-version");
+const TUS_EXTENSION: HeaderName = HeaderName::from_static("tus-extension");
+const TUS_MAX_SIZE: HeaderName = HeaderName::from_static("tus-max-size");
+const UPLOAD_LENGTH: HeaderName = HeaderName::from_static("upload-length");
+const UPLOAD_OFFSET: HeaderName = HeaderName::from_static("upload-offset");
+const UPLOAD_METADATA: HeaderName = HeaderName::from_static("upload-metadata");
+const UPLOAD_DEFER_LENGTH: HeaderName = HeaderName::from_static("upload-defer-length");
+// Not part of tus: tells the client which `files` row a completed upload became
+const UPLOAD_FILE_ID: HeaderName = HeaderName::from_static("upload-file-id");
+
+/// tus 1.0 resumable uploads: a file is sent over any number of PATCH requests,
+/// and registered as a regular upload once its last byte has arrived
+pub struct TusUploadHandler {
+    env: Arc<Env>,
+    file_service: FileService,
+    blob_service: BlobService,
+    tus_upload_service: TusUploadService,
+    // Uploads currently receiving a PATCH, a second one in parallel would corrupt the file
+    active_uploads: Arc<Mutex<HashSet<Uuid>>>,
+    webhook_service: WebhookService,
+    webhook_notify: Arc<Notify>,
+    logger: Arc<dyn Logger>,
+}
+
+impl TusUploadHandler {
+    pub fn new(state: Arc<AppState>) -> Self {
+        Self {
+            env: state.env.clone(),
+            file_service: FileService::new(state.pool.clone()),
+            blob_service: BlobService::new(state.pool.clone()),
+            tus_upload_service: TusUploadService::new(state.pool.clone()),
+            active_uploads: state.active_tus_uploads.clone(),
+            webhook_service: WebhookService::new(state.pool.clone()),
+            webhook_notify: state.webhook_notify.clone(),
+
+            // Initialize the logger
+            logger: Arc::new(DefaultLogger::new::<TusUploadHandler>()),
+        }
+    }
+}
+
+impl TusUploadHandler {
+    /// Advertises the protocol version, extensions and the largest upload accepted
+    pub fn options(&self) -> Response {
+        Self::respond(
+            StatusCode::NO_CONTENT,
+            vec![
+                (TUS_VERSION_HEADER, TUS_VERSION.to_string()),
+                (TUS_EXTENSION, TUS_EXTENSIONS.to_string()),
+                (TUS_MAX_SIZE, self.env.max_upload_size.to_string()),
+            ],
+            String::new(),
+        )
+    }
+
+    /// Creation extension: registers an upload of `Upload-Length` bytes, which belongs to the caller
+    pub async fn create(&self, principal: Principal, headers: HeaderMap) -> Response {
+        if let Some(response) = Self::check_version(&headers) {
+            return response;
+        }
+
+        let upload_length = match Self::header_u64(&headers, UPLOAD_LENGTH) {
+            Some(length) => length,
+            None if headers.contains_key(UPLOAD_DEFER_LENGTH) => {
+                return Self::error(
+                    StatusCode::BAD_REQUEST,
+                    "Upload-Defer-Length is not supported",
+                )
+            }
+            None => {
+                return Self::error(StatusCode::BAD_REQUEST, "Missing or invalid Upload-Length")
+            }
+        };
+        if upload_length > self.env.max_upload_size {
+            return Self::error(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                &format!("Upload-Length must not exceed {}", self.env.max_upload_size),
+            );
+        }
+
+        let metadata = headers
+            .get(UPLOAD_METADATA)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        let original_name = match metadata.as_deref().map(Self::metadata_file_name) {
+            Some(Ok(name)) => name,
+            Some(Err(e)) => return Self::error(StatusCode::BAD_REQUEST, &e),
+            None => None,
+        };
+        let file_name = match UploadFileHandler::upload_file_name(
+            original_name.as_deref().unwrap_or("unnamed"),
+        ) {
+            Ok(file_name) => file_name,
+            Err(e) => return Self::error(StatusCode::INTERNAL_SERVER_ERROR, &e),
+        };
+
+        let upload = match self
+            .tus_upload_service
+            .create(CreateTusUpload {
+                file_name,
+                upload_length,
+                metadata,
+                owner_id: principal.user_id,
+            })
+            .await
+        {
+            Ok(upload) => upload,
+            Err(e) => {
+                return Self::error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    &format!("Failed to create upload: {e}"),
+                )
+            }
+        };
+        let id_uuid = Uuid::parse_str(&upload.id).unwrap();
+
+        let partial_path = match self.partial_path(id_uuid) {
+            Some(path) => path,
+            None => {
+                return Self::error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to get save path")
+            }
+        };
+        if let Err(e) = tokio::fs::File::create(&partial_path).await {
+            let _ = self.tus_upload_service.delete(id_uuid).await;
+            return Self::error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                &format!("Failed to create upload: {e}"),
+            );
+        }
+
+        let mut extra_headers = vec![(header::LOCATION, format!("/files/tus/{}", upload.id))];
+        // An empty file is complete as soon as it exists
+        if upload_length == 0 {
+            match self.finalize(&upload, &partial_path).await {
+                Ok(file) => extra_headers.push((UPLOAD_FILE_ID, file.id)),
+                Err(e) => return Self::error(StatusCode::INTERNAL_SERVER_ERROR, &e),
+            }
+        }
+
+        Self::respond(StatusCode::CREATED, extra_headers, String::new())
+    }
+
+    /// Reports how many bytes of the upload the server has
+    pub async fn head(&self, principal: Principal, id: String, headers: HeaderMap) -> Response {
+        if let Some(response) = Self::check_version(&headers) {
+            return response;
+        }
+
+        let upload = match self.find_upload(&principal, &id).await {
+            Ok((_, upload)) => upload,
+            Err(response) => return response,
+        };
+
+        let mut extra_headers = vec![
+            (UPLOAD_OFFSET, upload.upload_offset.to_string()),
+            (UPLOAD_LENGTH, upload.upload_length.to_string()),
+            (header::CACHE_CONTROL, "no-store".to_string()),
+        ];
+        if let Some(metadata) = upload.metadata {
+            extra_headers.push((UPLOAD_METADATA, metadata));
+        }
+        if let Some(file_id) = upload.file_id {
+            extra_headers.push((UPLOAD_FILE_ID, file_id));
+        }
+
+        Self::respond(StatusCode::OK, extra_headers, String::new())
+    }
+
+    /// Appends the request body at `Upload-Offset`, which must be where the previous PATCH stopped
+    pub async fn patch(
+        &self,
+        principal: Principal,
+        id: String,
+        headers: HeaderMap,
+        body: Body,
+    ) -> Response {
+        if let Some(response) = Self::check_version(&headers) {
+            return response;
+        }
+
+        let content_type = headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok());
+        if content_type != Some(OFFSET_CONTENT_TYPE) {
+            return Self::error(
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                &format!("Content-Type must be {OFFSET_CONTENT_TYPE}"),
+            );
+        }
+        let offset = match Self::header_u64(&headers, UPLOAD_OFFSET) {
+            Some(offset) => offset,
+            None => {
+                return Self::error(StatusCode::BAD_REQUEST, "Missing or invalid Upload-Offset")
+            }
+        };
+
+        let (id_uuid, _) = match self.find_upload(&principal, &id).await {
+            Ok(found) => found,
+            Err(response) => return response,
+        };
+        let _lock = match ActiveUpload::acquire(&self.active_uploads, id_uuid) {
+            Some(lock) => lock,
+            None => return Self::error(StatusCode::LOCKED, "Upload is already being written to"),
+        };
+        // Read again now that it's locked, a PATCH that just finished may have moved the offset
+        let (_, upload) = match self.find_upload(&principal, &id).await {
+            Ok(found) => found,
+            Err(response) => return response,
+        };
+        if upload.file_id.is_some() || offset != upload.upload_offset as u64 {
+            return Self::error(
+                StatusCode::CONFLICT,
+                &format!("Upload-Offset must be {}", upload.upload_offset),
+            );
+        }
+
+        let partial_path = match self.partial_path(id_uuid) {
+            Some(path) => path,
+            None => {
+                return Self::error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to get save path")
+            }
+        };
+        let remaining = upload.upload_length as u64 - offset;
+        let (written, result) = Self::append(&partial_path, offset, remaining, body).await;
+
+        // Whatever arrived is kept, so an interrupted request can be resumed from there
+        let new_offset = offset + written;
+        if written > 0 {
+            match self
+                .tus_upload_service
+                .update_offset(id_uuid, offset, new_offset)
+                .await
+            {
+                Ok(true) => {}
+                // The lock only covers this instance, another one wrote the same range meanwhile
+                Ok(false) => {
+                    return Self::error(
+                        StatusCode::CONFLICT,
+                        "Upload was written to by another request",
+                    )
+                }
+                Err(e) => {
+                    return Self::error(
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        &format!("Failed to update upload offset: {e}"),
+                    )
+                }
+            }
+        }
+        if let Err((status, message)) = result {
+            self.logger.error(&format!(
+                "Upload(id: {}) stopped at {new_offset}: {message}",
+                id_uuid
+            ));
+            return Self::error(status, &message);
+        }
+
+        let mut extra_headers = vec![(UPLOAD_OFFSET, new_offset.to_string())];
+        if new_offset == upload.upload_length as u64 {
+            match self.finalize(&upload, &partial_path).await {
+                Ok(file) => extra_headers.push((UPLOAD_FILE_ID, file.id)),
+                Err(e) => return Self::error(StatusCode::INTERNAL_SERVER_ERROR, &e),
+            }
+        }
+
+        Self::respond(StatusCode::NO_CONTENT, extra_headers, String::new())
+    }
+
+    /// Termination extension: drops an upload and whatever was received of it
+    pub async fn terminate(
+        &self,
+        principal: Principal,
+        id: String,
+        headers: HeaderMap,
+    ) -> Response {
+        if let Some(response) = Self::check_version(&headers) {
+            return response;
+        }
+
+        let (id_uuid, _) = match self.find_upload(&principal, &id).await {
+            Ok(found) => found,
+            Err(response) => return response,
+        };
+        let _lock = match ActiveUpload::acquire(&self.active_uploads, id_uuid) {
+            Some(lock) => lock,
+            None => return Self::error(StatusCode::LOCKED, "Upload is already being written to"),
+        };
+
+        if let Some(partial_path) = self.partial_path(id_uuid) {
+            match tokio::fs::remove_file(&partial_path).await {
+                Ok(()) => {}
+                // Already moved into the blob store when the upload completed
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => {
+                    return Self::error(
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        &format!("Failed to remove upload: {e}"),
+                    )
+                }
+            }
+        }
+
+        if let Err(e) = self.tus_upload_service.delete(id_uuid).await {
+            return Self::error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                &format!("Failed to delete upload: {e}"),
+            );
+        }
+
+        Self::respond(StatusCode::NO_CONTENT, vec![], String::new())
+    }
+}
+
+impl TusUploadHandler {
+    // Writes the body at `offset`, refusing anything past `remaining` bytes.
+    // Returns the bytes written even when the stream fails halfway.
+    async fn append(
+        partial_path: &str,
+        offset: u64,
+        remaining: u64,
+        body: Body,
+    ) -> (u64, Result<(), (StatusCode, String)>) {
+        let internal = |e: io::Error| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string());
+
+        let mut file = match tokio::fs::OpenOptions::new()
+            .write(true)
+            .open(partial_path)
+            .await
+        {
+            Ok(file) => file,
+            Err(e) => return (0, Err(internal(e))),
+        };
+        // Drops bytes written past the recorded offset by a request that never got to record them
+        if let Err(e) = file.set_len(offset).await {
+            return (0, Err(internal(e)));
+        }
+        if let Err(e) = file.seek(SeekFrom::Start(offset)).await {
+            return (0, Err(internal(e)));
+        }
+
+        let mut written = 0;
+        let mut result = Ok(());
+        let mut stream = body.into_data_stream();
+        while let Some(chunk) = stream.next().await {
+            let chunk = match chunk {
+                Ok(chunk) => chunk,
+                Err(e) => {
+                    result = Err((StatusCode::BAD_REQUEST, format!("Upload interrupted: {e}")));
+                    break;
+                }
+            };
+            if written + chunk.len() as u64 > remaining {
+                // Nothing of this request is kept, the client has to resend a valid one
+                let _ = file.set_len(offset).await;
+                return (
+                    0,
+                    Err((
+                        StatusCode::PAYLOAD_TOO_LARGE,
+                        "Body exceeds Upload-Length".to_string(),
+                    )),
+                );
+            }
+            if let Err(e) = file.write_all(&chunk).await {
+                result = Err(internal(e));
+                break;
+            }
+            written += chunk.len() as u64;
+        }
+
+        if let Err(e) = file.sync_all().await {
+            return (0, Err(internal(e)));
+        }
+        (written, result)
+    }
+
+    // Moves the completed upload into place and registers it like a regular upload
+    async fn finalize(&self, upload: &TusUpload, partial_path: &str) -> Result<FileRecord, String> {
+        let id_uuid = Uuid::parse_str(&upload.id).map_err(|e| e.to_string())?;
+
+        // The content arrived over several requests, so it is hashed once complete
+        let sha256 = FileHelper::hash_file(partial_path)
+            .await
+            .map_err(|e| format!("Failed to hash upload: {e}"))?;
+        let file = UploadFileHandler::register_file(
+            &self.file_service,
+            &self.blob_service,
+            partial_path,
+            &self.env.uploads_dir,
+            CreateFile {
+                file_ref: format!("/uploads/{}", upload.file_name),
+                size: upload.upload_length as u64,
+                sha256: Some(sha256.clone()),
+                blob_sha256: Some(sha256),
+                parent_id: None,
+                archive_path: None,
+                owner_id: upload.owner_id.as_deref().and_then(|id| id.parse().ok()),
+            },
+        )
+        .await?;
+        let file_id = Uuid::parse_str(&file.id).map_err(|e| e.to_string())?;
+        self.tus_upload_service
+            .complete(id_uuid, file_id)
+            .await
+            .map_err(|e| format!("Failed to complete upload: {e}"))?;
+
+        self.logger.debug(&format!("File saved: {}", file.file_ref));
+
+        // Like any other upload, the webhooks subscribed to uploads are told about it
+        match self
+            .webhook_service
+            .enqueue(
+                WebhookEvent::FileUploaded,
+                &serde_json::json!(file),
+                self.env.webhook_max_attempts,
+            )
+            .await
+        {
+            Ok(0) => {}
+            Ok(_) => self.webhook_notify.notify_one(),
+            Err(e) => self.logger.error(&format!(
+                "Failed to queue webhook deliveries for {}: {e}",
+                file.file_ref
+            )),
+        }
+        Ok(file)
+    }
+
+    // Only the user who created an upload, or an admin, can see and continue it
+    async fn find_upload(
+        &self,
+        principal: &Principal,
+        id: &str,
+    ) -> Result<(Uuid, TusUpload), Response> {
+        let id_uuid: Uuid = id.parse().map_err(|_| {
+            Self::error(StatusCode::NOT_FOUND, &format!("Invalid ID format: {}", id))
+        })?;
+
+        match self.tus_upload_service.find_one(id_uuid).await {
+            Ok(upload)
+                if principal.is_admin()
+                    || principal
+                        .user_id
+                        .is_some_and(|user_id| upload.owner_id == Some(user_id.to_string())) =>
+            {
+                Ok((id_uuid, upload))
+            }
+            Ok(_) => Err(Self::error(
+                StatusCode::NOT_FOUND,
+                &format!("Upload not found: {id_uuid}"),
+            )),
+            Err(e) => Err(Self::error(
+                StatusCode::NOT_FOUND,
+                &format!("Upload not found: {e}"),
+            )),
+        }
+    }
+
+    // Partial uploads live next to the finished ones so completing them is a plain rename
+    fn partial_path(&self, id: Uuid) -> Option<String> {
+        FileHelper::get_uploaded_file_path(&format!(".{id}.tus"), &self.env.uploads_dir)
+    }
+
+    // `Upload-Metadata` is a list of `key base64(value)` pairs, only `filename` is used.
+    // Values may be left out, a `filename` without one is no name at all.
+    fn metadata_file_name(metadata: &str) -> Result<Option<String>, String> {
+        for pair in metadata.split(',') {
+            let (key, value) = pair.trim().split_once(' ').unwrap_or((pair.trim(), ""));
+            if key != "filename" {
+                continue;
+            }
+
+            let decoded = STANDARD
+                .decode(value.trim())
+                .map_err(|e| format!("Invalid Upload-Metadata: {e}"))?;
+            let name =
+                String::from_utf8(decoded).map_err(|e| format!("Invalid Upload-Metadata: {e}"))?;
+            return Ok(Some(name).filter(|name| !name.is_empty()));
+        }
+
+        Ok(None)
+    }
+
+    fn header_u64(headers: &HeaderMap, name: HeaderName) -> Option<u64> {
+        headers.get(name)?.to_str().ok()?.trim().parse().ok()
+    }
+
+    fn check_version(headers: &HeaderMap) -> Option<Response> {
+        let version = headers
+            .get(TUS_RESUMABLE)
+            .and_then(|value| value.to_str().ok());
+        if version == Some(TUS_VERSION) {
+            return None;
+        }
+
+        Some(Self::respond(
+            StatusCode::PRECONDITION_FAILED,
+            vec![(TUS_VERSION_HEADER, TUS_VERSION.to_string())],
+            format!("Unsupported Tus-Resumable version, expected {TUS_VERSION}"),
+        ))
+    }
+
+    fn error(status: StatusCode, message: &str) -> Response {
+        Self::respond(status, vec![], message.to_string())
+    }
+
+    // Every tus response carries `Tus-Resumable`
+    fn respond(
+        status: StatusCode,
+        extra_headers: Vec<(HeaderName, String)>,
+        body: String,
+    ) -> Response {
+        let mut headers = HeaderMap::new();
+        headers.insert(TUS_RESUMABLE, HeaderValue::from_static(TUS_VERSION));
+        for (name, value) in extra_headers {
+            if let Ok(value) = HeaderValue::from_str(&value) {
+                headers.insert(name, value);
+            }
+        }
+
+        (status, headers, body).into_response()
+    }
+}
+
+/// Marks an upload as being written to until dropped
+struct ActiveUpload {
+    active_uploads: Arc<Mutex<HashSet<Uuid>>>,
+    id: Uuid,
+}
+
+impl ActiveUpload {
+    fn acquire(active_uploads: &Arc<Mutex<HashSet<Uuid>>>, id: Uuid) -> Option<Self> {
+        let inserted = active_uploads
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(id);
+
+        inserted.then(|| Self {
+            active_uploads: active_uploads.clone(),
+            id,
+        })
+    }
+}
+
+impl Drop for ActiveUpload {
+    fn drop(&mut self) {
+        self.active_uploads
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(HeaderName, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(name, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn test_metadata_file_name() {
+        // "data.txt" and "text/plain"
+        let name = Some("data.txt".to_string());
+        assert_eq!(
+            TusUploadHandler::metadata_file_name("filename ZGF0YS50eHQ="),
+            Ok(name.clone())
+        );
+        assert_eq!(
+            TusUploadHandler::metadata_file_name("filetype dGV4dC9wbGFpbg==,filename ZGF0YS50eHQ="),
+            Ok(name.clone())
+        );
+        assert_eq!(
+            TusUploadHandler::metadata_file_name(
+                " filename ZGF0YS50eHQ= , filetype dGV4dC9wbGFpbg== "
+            ),
+            Ok(name)
+        );
+        assert_eq!(
+            TusUploadHandler::metadata_file_name("filetype dGV4dC9wbGFpbg=="),
+            Ok(None)
+        );
+        assert_eq!(TusUploadHandler::metadata_file_name(""), Ok(None));
+    }
+
+    #[test]
+    fn test_metadata_file_name_without_value() {
+        assert_eq!(
+            TusUploadHandler::metadata_file_name("is_confidential,filename ZGF0YS50eHQ="),
+            Ok(Some("data.txt".to_string()))
+        );
+        assert_eq!(TusUploadHandler::metadata_file_name("filename"), Ok(None));
+        assert_eq!(
+            TusUploadHandler::metadata_file_name("filename ,filetype dGV4dC9wbGFpbg=="),
+            Ok(None)
+        );
+        // Keys are matched whole
+        assert_eq!(
+            TusUploadHandler::metadata_file_name("filenames ZGF0YS50eHQ="),
+            Ok(None)
+        );
+    }
+
+    #[test]
+    fn test_metadata_file_name_invalid() {
+        assert!(TusUploadHandler::metadata_file_name("filename not*base64").is_err());
+        assert!(TusUploadHandler::metadata_file_name("filename ZGF0YS50eHQ").is_err());
+        // 0xff 0xfe isn't UTF-8
+        assert!(TusUploadHandler::metadata_file_name("filename //4=").is_err());
+        // Only the filename has to be valid
+        assert_eq!(
+            TusUploadHandler::metadata_file_name("filetype //4=,filename ZGF0YS50eHQ="),
+            Ok(Some("data.txt".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_header_u64() {
+        assert_eq!(
+            TusUploadHandler::header_u64(&headers(&[(UPLOAD_LENGTH, "100")]), UPLOAD_LENGTH),
+            Some(100)
+        );
+        assert_eq!(
+            TusUploadHandler::header_u64(&headers(&[(UPLOAD_LENGTH, " 7 ")]), UPLOAD_LENGTH),
+            Some(7)
+        );
+        assert_eq!(
+            TusUploadHandler::header_u64(
+                &headers(&[(UPLOAD_LENGTH, "18446744073709551615")]),
+                UPLOAD_LENGTH
+            ),
+            Some(u64::MAX)
+        );
+        for value in ["-1", "1.5", "abc", "", "18446744073709551616"] {
+            assert_eq!(
+                TusUploadHandler::header_u64(&headers(&[(UPLOAD_LENGTH, value)]), UPLOAD_LENGTH),
+                None,
+                "{value}"
+            );
+        }
+        assert_eq!(
+            TusUploadHandler::header_u64(&headers(&[(UPLOAD_OFFSET, "1")]), UPLOAD_LENGTH),
+            None
+        );
+    }
+
+    #[test]
+    fn test_check_version() {
+        assert!(
+            TusUploadHandler::check_version(&headers(&[(TUS_RESUMABLE, TUS_VERSION)])).is_none()
+        );
+
+        for headers in [
+            headers(&[]),
+            headers(&[(TUS_RESUMABLE, "0.2.2")]),
+            headers(&[(TUS_RESUMABLE, "1.0")]),
+        ] {
+            let response = TusUploadHandler::check_version(&headers).unwrap();
+            assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+            assert_eq!(response.headers()[TUS_VERSION_HEADER], TUS_VERSION);
+            assert_eq!(response.headers()[TUS_RESUMABLE], TUS_VERSION);
+        }
+    }
+}
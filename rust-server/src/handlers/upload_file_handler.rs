@@ -1,26 +1,68 @@
-use axum::{extract::Multipart, http::StatusCode, response::IntoResponse};
+use axum::{
+    extract::{multipart::Field, Multipart},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use rust_server::{
+    archive::{detect_archive, extract_archive, ExtractedEntry},
+    ExtractionLimits,
+};
+use sha2::{Digest, Sha256};
 use sqlx::types::Uuid;
 use std::{
-    fs::File,
-    io::{self, Write},
+    io,
     sync::Arc,
     time::{SystemTime, UNIX_EPOCH},
 };
+use tokio::{
+    io::{AsyncWriteExt, BufWriter},
+    sync::Notify,
+    task::JoinHandle,
+};
 
 use crate::{
     app::AppState,
-    dtos::CreateFile,
+    dtos::{CreateFile, GetFileQuery, ListFilesQuery, UploadQuery},
     helpers::{
+        access::AccessFilter,
         env::Env,
         file::FileHelper,
         logger::{DefaultLogger, Logger},
+        pagination::Pagination,
+    },
+    models::{
+        file::{File as FileRecord, FileWithCompressed},
+        page::SortField,
+        share::Access,
+        user::Principal,
+        webhook::WebhookEvent,
+    },
+    services::{
+        blob_service::BlobService, compressed_file_service::CompressedFileService,
+        file_service::FileService, webhook_service::WebhookService,
     },
-    services::file_service::FileService,
 };
 
+// Extensions stripped from an archive's name to prefix its members with
+const ARCHIVE_EXTENSIONS: [&str; 5] = [".tar.gz", ".tgz", ".tar.zst", ".tzst", ".zip"];
+
+// Files stored for a field, the archive first then its members, and why the archive couldn't be extracted
+type UploadResult = Result<(Vec<FileRecord>, Option<serde_json::Value>), (StatusCode, String)>;
+
+/// An upload written to a temporary file, with what was learned while streaming it
+struct SavedFile {
+    path: String,
+    size: u64,
+    sha256: String,
+}
+
 pub struct UploadFileHandler {
     env: Arc<Env>,
     file_service: FileService,
+    blob_service: BlobService,
+    compressed_file_service: CompressedFileService,
+    webhook_service: WebhookService,
+    webhook_notify: Arc<Notify>,
     logger: Arc<dyn Logger>,
 }
 
@@ -29,6 +71,10 @@ impl UploadFileHandler {
         Self {
             env: state.env.clone(),
             file_service: FileService::new(state.pool.clone()),
+            blob_service: BlobService::new(state.pool.clone()),
+            compressed_file_service: CompressedFileService::new(state.pool.clone()),
+            webhook_service: WebhookService::new(state.pool.clone()),
+            webhook_notify: state.webhook_notify.clone(),
 
             // Initialize the logger
             logger: Arc::new(DefaultLogger::new::<UploadFileHandler>()),
@@ -38,77 +84,145 @@ impl UploadFileHandler {
 
 impl UploadFileHandler {
     /// Handles file uploads from a multipart form request
-    pub async fn upload_files(&self, mut multipart: Multipart) -> impl IntoResponse {
+    /// With `extract=true`, archives are also unpacked into one file per member. A failed extraction answers 422
+    /// with the files stored anyway and the error of each archive that couldn't be extracted.
+    /// The files belong to the caller, archive members included
+    pub async fn upload_files(
+        &self,
+        principal: Principal,
+        mut multipart: Multipart,
+        query: UploadQuery,
+    ) -> impl IntoResponse {
+        let extract = query.extract;
+        let owner_id = principal.user_id;
         let mut uploaded_file_tasks = vec![];
         while let Ok(Some(field)) = multipart.next_field().await {
             let file_name = match Self::extract_filename(&field) {
                 Ok(value) => value,
-                Err(value) => return (StatusCode::BAD_REQUEST, value),
+                Err(value) => {
+                    self.discard(uploaded_file_tasks).await;
+                    return (StatusCode::BAD_REQUEST, value);
+                }
             };
 
-            match field.bytes().await {
-                Err(_) => {
+            // Fields are streamed one after the other, only the bookkeeping runs concurrently
+            self.logger.debug(&format!("Saving file: {}...", file_name));
+            let saved = match Self::save_field(
+                field,
+                &self.env.uploads_dir,
+                self.env.max_upload_size,
+            )
+            .await
+            {
+                Ok(saved) => saved,
+                Err(e) if e.kind() == io::ErrorKind::FileTooLarge => {
+                    self.discard(uploaded_file_tasks).await;
+                    return (StatusCode::PAYLOAD_TOO_LARGE, e.to_string());
+                }
+                Err(e) => {
+                    self.logger.error(&format!("Failed to save file: {}", e));
+                    self.discard(uploaded_file_tasks).await;
                     return (
                         StatusCode::BAD_REQUEST,
-                        "Failed to read file data!".to_string(),
-                    )
+                        format!("Failed to save file: {}", e),
+                    );
                 }
-                Ok(data) => {
-                    let env = self.env.clone();
-                    let file_service = self.file_service.clone();
-                    let logger = self.logger.clone();
-                    let task = tokio::spawn(async move {
-                        logger.debug(&format!("Saving file: {}...", file_name));
-                        if let Err(e) =
-                            Self::save_file(file_name.to_owned(), &data, &env.uploads_dir).await
-                        {
-                            logger.error(&format!("Failed to save file: {}", e));
-                            return Err((
-                                StatusCode::BAD_REQUEST,
-                                format!("Failed to save file: {}", e),
-                            ));
-                        }
+            };
 
-                        match file_service
-                            .create(CreateFile {
-                                file_ref: format!("/uploads/{file_name}"),
-                                size: data.len() as u64,
-                            })
-                            .await
-                        {
-                            Ok(file) => Ok(file),
-                            Err(e) => Err((
-                                StatusCode::INTERNAL_SERVER_ERROR,
-                                format!("Failed to create file: {e}"),
-                            )),
-                        }
-                    });
-                    uploaded_file_tasks.push(task);
+            let env = self.env.clone();
+            let file_service = self.file_service.clone();
+            let blob_service = self.blob_service.clone();
+            let logger = self.logger.clone();
+            let task = tokio::spawn(async move {
+                let file = match Self::register_file(
+                    &file_service,
+                    &blob_service,
+                    &saved.path,
+                    &env.uploads_dir,
+                    CreateFile {
+                        file_ref: format!("/uploads/{file_name}"),
+                        size: saved.size,
+                        sha256: Some(saved.sha256.clone()),
+                        blob_sha256: Some(saved.sha256),
+                        parent_id: None,
+                        archive_path: None,
+                        owner_id,
+                    },
+                )
+                .await
+                {
+                    Ok(file) => file,
+                    Err(e) => {
+                        let _ = tokio::fs::remove_file(&saved.path).await;
+                        return Err((StatusCode::INTERNAL_SERVER_ERROR, e));
+                    }
+                };
+
+                if !extract {
+                    return Ok((vec![file], None));
                 }
-            };
+
+                // A failed extraction still keeps the archive itself, the request reports the failure
+                match Self::extract_members(&file, &file_name, &env, &file_service, &blob_service)
+                    .await
+                {
+                    Ok(mut members) => {
+                        members.insert(0, file);
+                        Ok((members, None))
+                    }
+                    Err(e) => {
+                        logger.error(&format!("Failed to extract {}: {e}", file.file_ref));
+                        let extraction_error = serde_json::json!({
+                            "file_id": file.id,
+                            "file_ref": file.file_ref,
+                            "error": e,
+                        });
+                        Ok((vec![file], Some(extraction_error)))
+                    }
+                }
+            });
+            uploaded_file_tasks.push(task);
         }
 
+        let mut extraction_errors = vec![];
         let files = futures::future::join_all(uploaded_file_tasks)
             .await
             .into_iter()
             .filter_map(|file| match file {
                 Ok(file) => match file {
-                    Ok(file) => {
-                        self.logger.debug(&format!("File saved: {}", file.file_ref));
-                        Some(file)
+                    Ok((files, extraction_error)) => {
+                        for file in &files {
+                            self.logger.debug(&format!("File saved: {}", file.file_ref));
+                        }
+                        extraction_errors.extend(extraction_error);
+                        Some(files)
                     }
                     Err(_) => None,
                 },
                 Err(_) => None,
             })
+            .flatten()
             .collect::<Vec<_>>();
+        self.notify_webhooks(&files).await;
 
+        if !extraction_errors.is_empty() {
+            return (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                serde_json::json!({ "files": files, "extraction_errors": extraction_errors })
+                    .to_string(),
+            );
+        }
         (StatusCode::CREATED, serde_json::to_string(&files).unwrap())
     }
 }
 
 impl UploadFileHandler {
-    pub async fn get_file(&self, id: String) -> impl IntoResponse {
+    pub async fn get_file(
+        &self,
+        principal: Principal,
+        id: String,
+        query: GetFileQuery,
+    ) -> impl IntoResponse {
         let id_uuid: Uuid = match id.parse() {
             Ok(uuid) => uuid,
             Err(_) => {
@@ -119,40 +233,312 @@ impl UploadFileHandler {
             }
         };
 
-        let file = match self.file_service.find_one(id_uuid).await {
+        let access = AccessFilter::new(&principal, Access::Read);
+        let file = match self.file_service.find_one(id_uuid, &access).await {
             Ok(file) => file,
             Err(e) => return (StatusCode::NOT_FOUND, format!("File not found: {e}")),
         };
 
+        if query.compressed {
+            return match self
+                .compressed_file_service
+                .find_for_file(id_uuid, &access)
+                .await
+            {
+                Ok(compressed) => (
+                    StatusCode::OK,
+                    serde_json::json!(FileWithCompressed { file, compressed }).to_string(),
+                ),
+                Err(e) => (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Failed to load compressed files: {e}"),
+                ),
+            };
+        }
+
         (StatusCode::OK, serde_json::to_string(&file).unwrap())
     }
+
+    /// Files the caller owns or that are shared with them, every file for admins
+    pub async fn list_files(
+        &self,
+        principal: Principal,
+        query: ListFilesQuery,
+    ) -> impl IntoResponse {
+        let pagination = match Pagination::new(
+            query.sort,
+            query.order,
+            query.cursor.as_deref(),
+            query.limit,
+        ) {
+            Ok(pagination) => pagination,
+            Err(e) => return (StatusCode::BAD_REQUEST, e),
+        };
+
+        let access = AccessFilter::new(&principal, Access::Read);
+        let files = match self.file_service.list(&query, &pagination, &access).await {
+            Ok(files) => files,
+            Err(e) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Failed to list files: {e}"),
+                )
+            }
+        };
+
+        let page = pagination.page(files, |file| match pagination.sort {
+            SortField::CreatedAt => (file.created_at.timestamp_micros(), &file.id),
+            SortField::Size => (file.size, &file.id),
+        });
+        (StatusCode::OK, serde_json::json!(page).to_string())
+    }
 }
 
 impl UploadFileHandler {
-    /// Saves the uploaded file to the `uploads` directory
-    pub async fn save_file(file_name: String, data: &[u8], uploads_dir: &str) -> io::Result<()> {
-        let save_path = FileHelper::get_uploaded_file_path(&file_name, uploads_dir);
-        if let Some(save_path) = save_path {
-            let mut file = File::create(save_path)?; // Create a new file in the uploads directory
-            file.write_all(data) // Write the received data into the file
-        } else {
-            return Err(io::Error::new(
-                io::ErrorKind::Other,
-                "Failed to get save path",
-            ));
+    /// Streams a multipart field to a temporary file, which `register_file` then moves into the blob store.
+    /// The content is hashed and counted on the way, so it is never held in memory as a whole.
+    async fn save_field(
+        mut field: Field<'_>,
+        uploads_dir: &str,
+        max_size: u64,
+    ) -> io::Result<SavedFile> {
+        // Same filesystem as the blob store, so storing it is a plain rename.
+        // Named uniquely, concurrent uploads of the same name never write to the same file.
+        let partial_path = FileHelper::get_uploaded_file_path(
+            &format!(".{}.partial", Uuid::new_v4()),
+            uploads_dir,
+        )
+        .ok_or_else(|| io::Error::other("Failed to get save path"))?;
+
+        let mut saved = SavedFile {
+            path: partial_path.clone(),
+            size: 0,
+            sha256: String::new(),
+        };
+        let mut hasher = Sha256::new();
+        let result = async {
+            let mut file = BufWriter::new(
+                tokio::fs::OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .open(&partial_path)
+                    .await?,
+            );
+            while let Some(chunk) = field.chunk().await.map_err(io::Error::other)? {
+                if saved.size + chunk.len() as u64 > max_size {
+                    return Err(io::Error::new(
+                        io::ErrorKind::FileTooLarge,
+                        format!("File is larger than {max_size} bytes"),
+                    ));
+                }
+                hasher.update(&chunk);
+                saved.size += chunk.len() as u64;
+                file.write_all(&chunk).await?;
+            }
+            file.flush().await?;
+            file.into_inner().sync_all().await
         }
+        .await;
+
+        if let Err(e) = result {
+            let _ = tokio::fs::remove_file(&partial_path).await;
+            return Err(e);
+        }
+
+        saved.sha256 = format!("{:x}", hasher.finalize());
+        Ok(saved)
+    }
+
+    /// Unpacks an uploaded archive next to it and registers every member as a child file.
+    /// Files that aren't archives have no members, a gzipped JSON file is no tarball.
+    async fn extract_members(
+        archive: &FileRecord,
+        file_name: &str,
+        env: &Env,
+        file_service: &FileService,
+        blob_service: &BlobService,
+    ) -> Result<Vec<FileRecord>, String> {
+        let archive_id: Uuid = archive.id.parse().map_err(|e| format!("{e}"))?;
+        let archive_path = FileHelper::get_file_path(archive, &env.uploads_dir)
+            .ok_or("Failed to get archive path")?;
+        if detect_archive(&archive_path)
+            .await
+            .map_err(|e| e.to_string())?
+            .is_none()
+        {
+            return Ok(vec![]);
+        }
+
+        // Members are named `<archive name>_<member path>`, e.g. `1747650000_bundle_docs_a.txt`
+        let stem = ARCHIVE_EXTENSIONS
+            .iter()
+            .find_map(|extension| file_name.strip_suffix(extension))
+            .unwrap_or(file_name);
+        let entries = extract_archive(
+            &archive_path,
+            &env.uploads_dir,
+            &format!("{stem}_"),
+            ExtractionLimits::default(),
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+
+        let mut members = Vec::with_capacity(entries.len());
+        let mut entries = entries.into_iter();
+        while let Some(entry) = entries.next() {
+            let sha256 = entry.sha256_hex();
+            let ExtractedEntry {
+                name, path, size, ..
+            } = entry;
+            let member_name = std::path::Path::new(&path)
+                .file_name()
+                .and_then(|name| name.to_str())
+                .unwrap_or_default()
+                .to_string();
+
+            let member = Self::register_file(
+                file_service,
+                blob_service,
+                &path,
+                &env.uploads_dir,
+                CreateFile {
+                    file_ref: format!("/uploads/{member_name}"),
+                    size,
+                    sha256: Some(sha256.clone()),
+                    blob_sha256: Some(sha256),
+                    parent_id: Some(archive_id),
+                    archive_path: Some(name.clone()),
+                    owner_id: archive.owner_id.as_deref().and_then(|id| id.parse().ok()),
+                },
+            )
+            .await;
+            match member {
+                Ok(member) => members.push(member),
+                Err(e) => {
+                    // Don't leave the remaining extracted files lying around
+                    for entry in entries {
+                        let _ = tokio::fs::remove_file(&entry.path).await;
+                    }
+                    let _ = tokio::fs::remove_file(&path).await;
+                    // Nor the members stored so far, the archive is either extracted whole or not at all
+                    for member in &members {
+                        Self::unregister_file(file_service, blob_service, member, &env.uploads_dir)
+                            .await;
+                    }
+                    return Err(format!("Failed to store {name}: {e}"));
+                }
+            }
+        }
+
+        Ok(members)
+    }
+
+    /// Moves a fully written temporary file into the blob store and creates its `files` row.
+    /// The blob reference is taken first, so purging another file of the same content can't remove the blob
+    /// in between. The temporary file is left in place on failure.
+    pub async fn register_file(
+        file_service: &FileService,
+        blob_service: &BlobService,
+        temp_path: &str,
+        uploads_dir: &str,
+        file: CreateFile,
+    ) -> Result<FileRecord, String> {
+        let sha256 = file.blob_sha256.clone().ok_or("Missing blob checksum")?;
+        let blob_path =
+            FileHelper::get_blob_path(&sha256, uploads_dir).ok_or("Failed to get blob path")?;
+        blob_service
+            .acquire(&sha256, file.size)
+            .await
+            .map_err(|e| format!("Failed to record blob: {e}"))?;
+
+        let result = match FileHelper::store_blob(temp_path, &sha256, uploads_dir).await {
+            Ok(_) => file_service
+                .create(file)
+                .await
+                .map_err(|e| format!("Failed to create file: {e}")),
+            Err(e) => Err(format!("Failed to save file: {e}")),
+        };
+        if result.is_err() {
+            let _ = blob_service.release(&sha256, &blob_path).await;
+        }
+        result
+    }
+
+    // Undoes `register_file`, removing the row and dropping its blob reference
+    async fn unregister_file(
+        file_service: &FileService,
+        blob_service: &BlobService,
+        file: &FileRecord,
+        uploads_dir: &str,
+    ) {
+        if let Ok(id) = file.id.parse::<Uuid>() {
+            let _ = file_service.delete(id).await;
+        }
+        if let Some((sha256, blob_path)) = file.blob_sha256.as_deref().and_then(|sha256| {
+            FileHelper::get_blob_path(sha256, uploads_dir).map(|path| (sha256, path))
+        }) {
+            let _ = blob_service.release(sha256, &blob_path).await;
+        }
+    }
+
+    // Undoes the files stored for the earlier fields of a request that fails, it answers with none of them.
+    // The tasks are awaited rather than aborted, so none is stopped halfway through storing a file.
+    async fn discard(&self, tasks: Vec<JoinHandle<UploadResult>>) {
+        for task in tasks {
+            let Ok(Ok((files, _))) = task.await else {
+                continue;
+            };
+            // Members before their archive
+            for file in files.iter().rev() {
+                Self::unregister_file(
+                    &self.file_service,
+                    &self.blob_service,
+                    file,
+                    &self.env.uploads_dir,
+                )
+                .await;
+            }
+        }
+    }
+
+    // Tells the webhooks subscribed to uploads about every new file, archive members included
+    async fn notify_webhooks(&self, files: &[FileRecord]) {
+        let mut queued = 0;
+        for file in files {
+            match self
+                .webhook_service
+                .enqueue(
+                    WebhookEvent::FileUploaded,
+                    &serde_json::json!(file),
+                    self.env.webhook_max_attempts,
+                )
+                .await
+            {
+                Ok(count) => queued += count,
+                Err(e) => self.logger.error(&format!(
+                    "Failed to queue webhook deliveries for {}: {e}",
+                    file.file_ref
+                )),
+            }
+        }
+        if queued > 0 {
+            self.webhook_notify.notify_one();
+        }
+    }
+
+    fn extract_filename(field: &Field<'_>) -> Result<String, String> {
+        Self::upload_file_name(field.file_name().unwrap_or("unnamed"))
     }
 
-    fn extract_filename(field: &axum::extract::multipart::Field<'_>) -> Result<String, String> {
-        let mut file_name: String = String::from(field.file_name().unwrap_or("unnamed"))
-            .split(&[' ', '-', ':', '\''])
-            .collect();
+    /// Name an upload is stored under: the original name without separators, prefixed with the time
+    pub fn upload_file_name(original: &str) -> Result<String, String> {
+        let mut file_name: String = original.split(&[' ', '-', ':', '\'']).collect();
 
         let now = SystemTime::now();
 
         match now.duration_since(UNIX_EPOCH) {
             Ok(duration) => file_name.insert_str(0, &format!("{}_", duration.as_secs())),
-            Err(_) => return Err(format!("Time went backward!")),
+            Err(_) => return Err("Time went backward!".to_string()),
         };
 
         Ok(file_name)
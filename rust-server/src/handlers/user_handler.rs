@@ -0,0 +1,106 @@
+use std::sync::Arc;
+
+use axum::{http::StatusCode, response::IntoResponse};
+use sqlx::types::Uuid;
+
+use crate::{
+    app::AppState,
+    dtos::UserRequest,
+    helpers::logger::{DefaultLogger, Logger},
+    services::user_service::UserService,
+};
+
+/// Manages the users API keys belong to, admins only
+pub struct UserHandler {
+    user_service: UserService,
+    logger: Arc<dyn Logger>,
+}
+
+impl UserHandler {
+    pub fn new(state: Arc<AppState>) -> Self {
+        Self {
+            user_service: UserService::new(state.pool.clone()),
+
+            // Initialize the logger
+            logger: Arc::new(DefaultLogger::new::<UserHandler>()),
+        }
+    }
+}
+
+impl UserHandler {
+    pub async fn create(&self, request: UserRequest) -> impl IntoResponse {
+        let name = request.name.trim();
+        if name.is_empty() {
+            return (
+                StatusCode::BAD_REQUEST,
+                "User name can't be empty".to_string(),
+            );
+        }
+
+        match self.user_service.create(name).await {
+            Ok(user) => {
+                self.logger.debug(&format!("User(id: {}) created", user.id));
+                (StatusCode::CREATED, serde_json::json!(user).to_string())
+            }
+            Err(e)
+                if e.as_database_error()
+                    .is_some_and(|e| e.is_unique_violation()) =>
+            {
+                (StatusCode::CONFLICT, format!("User already exists: {name}"))
+            }
+            Err(e) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to create user: {e}"),
+            ),
+        }
+    }
+
+    pub async fn list(&self) -> impl IntoResponse {
+        match self.user_service.find_all().await {
+            Ok(users) => (StatusCode::OK, serde_json::json!(users).to_string()),
+            Err(e) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to list users: {e}"),
+            ),
+        }
+    }
+
+    pub async fn get(&self, id: String) -> impl IntoResponse {
+        let id_uuid: Uuid = match id.parse() {
+            Ok(uuid) => uuid,
+            Err(_) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    format!("Invalid ID format: {}", id),
+                );
+            }
+        };
+
+        match self.user_service.find_one(id_uuid).await {
+            Ok(user) => (StatusCode::OK, serde_json::json!(user).to_string()),
+            Err(e) => (StatusCode::NOT_FOUND, format!("User not found: {e}")),
+        }
+    }
+
+    /// Removes the user, their API keys stop working right away
+    pub async fn delete(&self, id: String) -> impl IntoResponse {
+        let id_uuid: Uuid = match id.parse() {
+            Ok(uuid) => uuid,
+            Err(_) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    format!("Invalid ID format: {}", id),
+                );
+            }
+        };
+
+        match self.user_service.delete(id_uuid).await {
+            Ok(result) if result.rows_affected() > 0 => (StatusCode::NO_CONTENT, String::new()),
+            Ok(_) => (StatusCode::NOT_FOUND, format!("User not found: {id_uuid}")),
+            Err(e) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to delete user: {e}"),
+            ),
+        }
+    }
+}
@@ -0,0 +1,245 @@
+use std::sync::Arc;
+
+use axum::{http::StatusCode, response::IntoResponse};
+use rand::RngCore;
+use sqlx::types::Uuid;
+use tokio::sync::Notify;
+
+use crate::{
+    app::AppState,
+    dtos::{CreateWebhook, ListDeliveriesQuery, WebhookRequest},
+    helpers::{
+        logger::{DefaultLogger, Logger},
+        pagination::Pagination,
+    },
+    models::{
+        page::SortField,
+        webhook::{DeliveryStatus, WebhookEvent},
+    },
+    services::webhook_service::WebhookService,
+};
+
+// Random bytes in a generated secret, hex encoded
+const SECRET_SIZE: usize = 32;
+
+/// Manages the webhook subscriptions and their delivery log
+pub struct WebhookHandler {
+    webhook_notify: Arc<Notify>,
+    webhook_service: WebhookService,
+    logger: Arc<dyn Logger>,
+}
+
+impl WebhookHandler {
+    pub fn new(state: Arc<AppState>) -> Self {
+        Self {
+            webhook_notify: state.webhook_notify.clone(),
+            webhook_service: WebhookService::new(state.pool.clone()),
+
+            // Initialize the logger
+            logger: Arc::new(DefaultLogger::new::<WebhookHandler>()),
+        }
+    }
+}
+
+impl WebhookHandler {
+    /// Subscribes a URL to events, the response is the only one including the signing secret
+    pub async fn create(&self, request: WebhookRequest) -> impl IntoResponse {
+        match reqwest::Url::parse(&request.url) {
+            Ok(url) if matches!(url.scheme(), "http" | "https") => {}
+            _ => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    format!("Invalid webhook URL: {}", request.url),
+                )
+            }
+        }
+        if request.events.is_empty() {
+            return (
+                StatusCode::BAD_REQUEST,
+                "At least one event is required".to_string(),
+            );
+        }
+        let mut events = Vec::with_capacity(request.events.len());
+        for event in &request.events {
+            match event.parse::<WebhookEvent>() {
+                Ok(event) if !events.contains(&event.name().to_string()) => {
+                    events.push(event.name().to_string())
+                }
+                Ok(_) => {}
+                Err(e) => return (StatusCode::BAD_REQUEST, e),
+            }
+        }
+        let secret = match request.secret {
+            Some(secret) if secret.is_empty() => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    "Webhook secret can't be empty".to_string(),
+                )
+            }
+            Some(secret) => secret,
+            None => {
+                let mut bytes = [0; SECRET_SIZE];
+                rand::thread_rng().fill_bytes(&mut bytes);
+                hex::encode(bytes)
+            }
+        };
+
+        match self
+            .webhook_service
+            .create(CreateWebhook {
+                url: request.url,
+                secret: secret.clone(),
+                events,
+            })
+            .await
+        {
+            Ok(webhook) => {
+                self.logger
+                    .debug(&format!("Webhook(id: {}) created", webhook.id));
+                let mut body = serde_json::json!(webhook);
+                body["secret"] = secret.into();
+                (StatusCode::CREATED, body.to_string())
+            }
+            Err(e) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to create webhook: {e}"),
+            ),
+        }
+    }
+
+    pub async fn list(&self) -> impl IntoResponse {
+        match self.webhook_service.find_all().await {
+            Ok(webhooks) => (StatusCode::OK, serde_json::json!(webhooks).to_string()),
+            Err(e) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to list webhooks: {e}"),
+            ),
+        }
+    }
+
+    pub async fn get(&self, id: String) -> impl IntoResponse {
+        let id_uuid: Uuid = match id.parse() {
+            Ok(uuid) => uuid,
+            Err(_) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    format!("Invalid ID format: {}", id),
+                );
+            }
+        };
+
+        match self.webhook_service.find_one(id_uuid).await {
+            Ok(webhook) => (StatusCode::OK, serde_json::json!(webhook).to_string()),
+            Err(e) => (StatusCode::NOT_FOUND, format!("Webhook not found: {e}")),
+        }
+    }
+
+    /// Unsubscribes the webhook, its pending deliveries are dropped
+    pub async fn delete(&self, id: String) -> impl IntoResponse {
+        let id_uuid: Uuid = match id.parse() {
+            Ok(uuid) => uuid,
+            Err(_) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    format!("Invalid ID format: {}", id),
+                );
+            }
+        };
+
+        match self.webhook_service.delete(id_uuid).await {
+            Ok(result) if result.rows_affected() > 0 => (StatusCode::NO_CONTENT, String::new()),
+            Ok(_) => (
+                StatusCode::NOT_FOUND,
+                format!("Webhook not found: {id_uuid}"),
+            ),
+            Err(e) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to delete webhook: {e}"),
+            ),
+        }
+    }
+
+    /// Delivery log of a webhook, most recent first by default
+    pub async fn list_deliveries(
+        &self,
+        id: String,
+        query: ListDeliveriesQuery,
+    ) -> impl IntoResponse {
+        let id_uuid: Uuid = match id.parse() {
+            Ok(uuid) => uuid,
+            Err(_) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    format!("Invalid ID format: {}", id),
+                );
+            }
+        };
+        let status = match query.status.as_deref().map(str::parse::<DeliveryStatus>) {
+            Some(Ok(status)) => Some(status),
+            Some(Err(e)) => return (StatusCode::BAD_REQUEST, e),
+            None => None,
+        };
+        let pagination = match Pagination::new(
+            SortField::CreatedAt,
+            query.order,
+            query.cursor.as_deref(),
+            query.limit,
+        ) {
+            Ok(pagination) => pagination,
+            Err(e) => return (StatusCode::BAD_REQUEST, e),
+        };
+
+        if let Err(e) = self.webhook_service.find_one(id_uuid).await {
+            return (StatusCode::NOT_FOUND, format!("Webhook not found: {e}"));
+        }
+        let deliveries = match self
+            .webhook_service
+            .list_deliveries(id_uuid, status, &pagination)
+            .await
+        {
+            Ok(deliveries) => deliveries,
+            Err(e) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Failed to list deliveries: {e}"),
+                )
+            }
+        };
+
+        let page = pagination.page(deliveries, |delivery| {
+            (delivery.created_at.timestamp_micros(), &delivery.id)
+        });
+        (StatusCode::OK, serde_json::json!(page).to_string())
+    }
+
+    /// Sends a delivered or failed delivery again
+    pub async fn redeliver(&self, id: String) -> impl IntoResponse {
+        let id_uuid: Uuid = match id.parse() {
+            Ok(uuid) => uuid,
+            Err(_) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    format!("Invalid ID format: {}", id),
+                );
+            }
+        };
+
+        match self.webhook_service.redeliver(id_uuid).await {
+            Ok(Some(delivery)) => {
+                self.webhook_notify.notify_one();
+                (
+                    StatusCode::ACCEPTED,
+                    serde_json::json!(delivery).to_string(),
+                )
+            }
+            Ok(None) => (
+                StatusCode::CONFLICT,
+                format!("Delivery not found or still pending: {id_uuid}"),
+            ),
+            Err(e) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to redeliver: {e}"),
+            ),
+        }
+    }
+}
@@ -0,0 +1,224 @@
+use sqlx::{types::Uuid, Postgres, QueryBuilder};
+
+use crate::models::{share::Access, user::Principal};
+
+/// Which files and compressed files a query may reach on behalf of a caller
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessFilter {
+    // Admins, and the server's own background work
+    Everything,
+    // What the user owns, or what is shared with them or one of their groups for the access
+    User(Uuid, Access),
+    // Callers that are neither admins nor users
+    Nothing,
+}
+
+impl AccessFilter {
+    pub fn new(principal: &Principal, access: Access) -> Self {
+        match principal.user_id {
+            _ if principal.is_admin() => AccessFilter::Everything,
+            Some(user_id) => AccessFilter::User(user_id, access),
+            None => AccessFilter::Nothing,
+        }
+    }
+
+    /// Appends ` AND <condition>` on the `files` rows of `table` to a query already ending in a `WHERE` clause
+    pub fn push_files(&self, query: &mut QueryBuilder<'_, Postgres>, table: &str) {
+        match *self {
+            AccessFilter::Everything => {}
+            AccessFilter::User(user_id, access) => {
+                query.push(" AND ");
+                Self::push_file_condition(query, table, user_id, access);
+            }
+            AccessFilter::Nothing => {
+                query.push(" AND false");
+            }
+        }
+    }
+
+    /// Appends ` AND <condition>` on the `compressed_files` rows of `table` to a query already ending in a
+    /// `WHERE` clause. Besides their owner, whoever may access the source file may access its compressions,
+    /// and for `Read` those reused from another file of the same content. Changing a compression goes by its
+    /// own source file only, with the permission `Compress` needs or as the owner for `Own`. Archives have
+    /// no source file.
+    pub fn push_compressed_files(&self, query: &mut QueryBuilder<'_, Postgres>, table: &str) {
+        match *self {
+            AccessFilter::Everything => {}
+            AccessFilter::User(user_id, access) => {
+                query
+                    .push(format!(" AND ({table}.owner_id = "))
+                    .push_bind(user_id)
+                    .push(format!(
+                        " OR EXISTS (SELECT 1 FROM files AS source WHERE (source.id = {table}.file_id"
+                    ));
+                if access == Access::Read {
+                    query.push(format!(
+                        " OR (source.blob_sha256 = {table}.input_sha256 AND source.deleted_at IS NULL)"
+                    ));
+                }
+                query.push(") AND ");
+                Self::push_file_condition(query, "source", user_id, access);
+                query.push("))");
+            }
+            AccessFilter::Nothing => {
+                query.push(" AND false");
+            }
+        }
+    }
+
+    fn push_file_condition(
+        query: &mut QueryBuilder<'_, Postgres>,
+        table: &str,
+        user_id: Uuid,
+        access: Access,
+    ) {
+        query
+            .push(format!("({table}.owner_id = "))
+            .push_bind(user_id);
+        let permissions = match access {
+            Access::Read => "('read', 'compress')",
+            Access::Compress => "('compress')",
+            Access::Own => {
+                query.push(")");
+                return;
+            }
+        };
+        query
+            .push(format!(
+                " OR EXISTS (SELECT 1 FROM file_shares WHERE file_shares.file_id = {table}.id AND file_shares.permission IN {permissions} AND (file_shares.user_id = "
+            ))
+            .push_bind(user_id)
+            .push(" OR file_shares.group_id IN (SELECT group_id FROM group_members WHERE user_id = ")
+            .push_bind(user_id)
+            .push("))))");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::user::Scope;
+
+    const USER_ID: &str = "7f1f3a52-8f0e-4f47-9d0c-64c6b4a9b1de";
+
+    fn user(access: Access) -> AccessFilter {
+        AccessFilter::User(USER_ID.parse().unwrap(), access)
+    }
+
+    fn files_sql(access: AccessFilter) -> String {
+        let mut query =
+            QueryBuilder::<Postgres>::new("SELECT * FROM files WHERE deleted_at IS NULL");
+        access.push_files(&mut query, "files");
+        query.sql().to_string()
+    }
+
+    fn compressed_files_sql(access: AccessFilter) -> String {
+        let mut query = QueryBuilder::<Postgres>::new(
+            "SELECT * FROM compressed_files WHERE deleted_at IS NULL",
+        );
+        access.push_compressed_files(&mut query, "compressed_files");
+        query.sql().to_string()
+    }
+
+    #[test]
+    fn test_new() {
+        let user_id: Uuid = USER_ID.parse().unwrap();
+        let principal = |user_id, scopes| Principal { user_id, scopes };
+
+        assert_eq!(
+            AccessFilter::new(&principal(Some(user_id), vec![Scope::Read]), Access::Read),
+            AccessFilter::User(user_id, Access::Read)
+        );
+        assert_eq!(
+            AccessFilter::new(&principal(Some(user_id), vec![Scope::Admin]), Access::Own),
+            AccessFilter::Everything
+        );
+        assert_eq!(
+            AccessFilter::new(&principal(None, vec![Scope::Admin]), Access::Own),
+            AccessFilter::Everything
+        );
+        assert_eq!(
+            AccessFilter::new(&principal(None, vec![Scope::Read]), Access::Read),
+            AccessFilter::Nothing
+        );
+    }
+
+    #[test]
+    fn test_everything_and_nothing() {
+        assert_eq!(
+            files_sql(AccessFilter::Everything),
+            "SELECT * FROM files WHERE deleted_at IS NULL"
+        );
+        assert_eq!(
+            compressed_files_sql(AccessFilter::Everything),
+            "SELECT * FROM compressed_files WHERE deleted_at IS NULL"
+        );
+        assert_eq!(
+            files_sql(AccessFilter::Nothing),
+            "SELECT * FROM files WHERE deleted_at IS NULL AND false"
+        );
+        assert_eq!(
+            compressed_files_sql(AccessFilter::Nothing),
+            "SELECT * FROM compressed_files WHERE deleted_at IS NULL AND false"
+        );
+    }
+
+    #[test]
+    fn test_files() {
+        assert_eq!(
+            files_sql(user(Access::Read)),
+            "SELECT * FROM files WHERE deleted_at IS NULL AND (files.owner_id = $1 \
+            OR EXISTS (SELECT 1 FROM file_shares WHERE file_shares.file_id = files.id \
+            AND file_shares.permission IN ('read', 'compress') AND (file_shares.user_id = $2 \
+            OR file_shares.group_id IN (SELECT group_id FROM group_members WHERE user_id = $3))))"
+        );
+        assert_eq!(
+            files_sql(user(Access::Compress)),
+            "SELECT * FROM files WHERE deleted_at IS NULL AND (files.owner_id = $1 \
+            OR EXISTS (SELECT 1 FROM file_shares WHERE file_shares.file_id = files.id \
+            AND file_shares.permission IN ('compress') AND (file_shares.user_id = $2 \
+            OR file_shares.group_id IN (SELECT group_id FROM group_members WHERE user_id = $3))))"
+        );
+        assert_eq!(
+            files_sql(user(Access::Own)),
+            "SELECT * FROM files WHERE deleted_at IS NULL AND (files.owner_id = $1)"
+        );
+    }
+
+    #[test]
+    fn test_compressed_files_read() {
+        // Reads also reach compressions of another file with the same content
+        assert_eq!(
+            compressed_files_sql(user(Access::Read)),
+            "SELECT * FROM compressed_files WHERE deleted_at IS NULL AND (compressed_files.owner_id = $1 \
+            OR EXISTS (SELECT 1 FROM files AS source WHERE (source.id = compressed_files.file_id \
+            OR (source.blob_sha256 = compressed_files.input_sha256 AND source.deleted_at IS NULL)) \
+            AND (source.owner_id = $2 OR EXISTS (SELECT 1 FROM file_shares WHERE file_shares.file_id = source.id \
+            AND file_shares.permission IN ('read', 'compress') AND (file_shares.user_id = $3 \
+            OR file_shares.group_id IN (SELECT group_id FROM group_members WHERE user_id = $4))))))"
+        );
+    }
+
+    #[test]
+    fn test_compressed_files_compress() {
+        // Changes only go by the compression's own source file
+        assert_eq!(
+            compressed_files_sql(user(Access::Compress)),
+            "SELECT * FROM compressed_files WHERE deleted_at IS NULL AND (compressed_files.owner_id = $1 \
+            OR EXISTS (SELECT 1 FROM files AS source WHERE (source.id = compressed_files.file_id) \
+            AND (source.owner_id = $2 OR EXISTS (SELECT 1 FROM file_shares WHERE file_shares.file_id = source.id \
+            AND file_shares.permission IN ('compress') AND (file_shares.user_id = $3 \
+            OR file_shares.group_id IN (SELECT group_id FROM group_members WHERE user_id = $4))))))"
+        );
+    }
+
+    #[test]
+    fn test_compressed_files_own() {
+        assert_eq!(
+            compressed_files_sql(user(Access::Own)),
+            "SELECT * FROM compressed_files WHERE deleted_at IS NULL AND (compressed_files.owner_id = $1 \
+            OR EXISTS (SELECT 1 FROM files AS source WHERE (source.id = compressed_files.file_id) \
+            AND (source.owner_id = $2)))"
+        );
+    }
+}
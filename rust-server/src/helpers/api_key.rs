@@ -0,0 +1,34 @@
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+// Every key starts with it, so keys are recognizable in configs and told apart from JWTs
+const KEY_PREFIX: &str = "ck_";
+// Random bytes in a key, hex encoded
+const KEY_SIZE: usize = 32;
+// Characters of the key kept in the clear to identify it
+const VISIBLE_LENGTH: usize = 11;
+
+pub struct ApiKeyHelper;
+
+impl ApiKeyHelper {
+    /// New random key, e.g. `ck_3f9a…`, it is only shown once
+    pub fn generate() -> String {
+        let mut bytes = [0; KEY_SIZE];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        format!("{KEY_PREFIX}{}", hex::encode(bytes))
+    }
+
+    pub fn is_api_key(token: &str) -> bool {
+        token.starts_with(KEY_PREFIX)
+    }
+
+    /// What is stored of the key, keys are random enough that a plain SHA-256 can't be reversed
+    pub fn hash(key: &str) -> String {
+        format!("{:x}", Sha256::digest(key.as_bytes()))
+    }
+
+    /// Start of the key, to recognize it in listings
+    pub fn prefix(key: &str) -> String {
+        key.chars().take(VISIBLE_LENGTH).collect()
+    }
+}
@@ -0,0 +1,147 @@
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::models::share::DownloadLink;
+
+pub struct DownloadLinkHelper;
+
+impl DownloadLinkHelper {
+    /// Hex encoded HMAC-SHA256 of `<id>.<expires>`, expires being a Unix timestamp
+    pub fn sign(secret: &str, id: &str, expires: i64) -> String {
+        hex::encode(Self::mac(secret, id, expires).finalize().into_bytes())
+    }
+
+    /// Checks the signature in constant time
+    pub fn verify(secret: &str, id: &str, expires: i64, signature: &str) -> bool {
+        let Ok(signature) = hex::decode(signature) else {
+            return false;
+        };
+        Self::mac(secret, id, expires)
+            .verify_slice(&signature)
+            .is_ok()
+    }
+
+    /// Whether a link expiring at the Unix timestamp `expires` has expired at `now`
+    pub fn is_expired(expires: i64, now: i64) -> bool {
+        expires <= now
+    }
+
+    /// Where the link is downloaded from, e.g. `https://example.com/downloads/<id>?expires=…&signature=…`
+    pub fn url(public_url: &str, secret: &str, link: &DownloadLink) -> String {
+        let expires = link.expires_at.timestamp();
+        format!(
+            "{}/downloads/{}?expires={expires}&signature={}",
+            public_url.trim_end_matches('/'),
+            link.id,
+            Self::sign(secret, &link.id, expires)
+        )
+    }
+
+    fn mac(secret: &str, id: &str, expires: i64) -> Hmac<Sha256> {
+        // HMAC takes keys of any size
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(id.as_bytes());
+        mac.update(b".");
+        mac.update(expires.to_string().as_bytes());
+        mac
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    const SECRET: &str = "secret";
+    const ID: &str = "7f1f3a52-8f0e-4f47-9d0c-64c6b4a9b1de";
+    const EXPIRES: i64 = 1_747_650_000;
+
+    #[test]
+    fn test_verify() {
+        let signature = DownloadLinkHelper::sign(SECRET, ID, EXPIRES);
+        assert_eq!(signature.len(), 64);
+        assert!(DownloadLinkHelper::verify(SECRET, ID, EXPIRES, &signature));
+        assert!(DownloadLinkHelper::verify(
+            SECRET,
+            ID,
+            EXPIRES,
+            &signature.to_uppercase()
+        ));
+    }
+
+    #[test]
+    fn test_verify_tampered() {
+        let signature = DownloadLinkHelper::sign(SECRET, ID, EXPIRES);
+        assert!(!DownloadLinkHelper::verify(
+            "another secret",
+            ID,
+            EXPIRES,
+            &signature
+        ));
+        assert!(!DownloadLinkHelper::verify(
+            SECRET,
+            "0a4bd8b2-6f55-4c1e-8a44-1b8e1e0f7c3a",
+            EXPIRES,
+            &signature
+        ));
+        // A later expiry can't be swapped in
+        assert!(!DownloadLinkHelper::verify(
+            SECRET,
+            ID,
+            EXPIRES + 3600,
+            &signature
+        ));
+    }
+
+    #[test]
+    fn test_verify_malformed() {
+        let signature = DownloadLinkHelper::sign(SECRET, ID, EXPIRES);
+        for signature in [
+            "",
+            "zz",
+            &signature[..63],
+            &signature[..62],
+            &format!("{signature}00"),
+        ] {
+            assert!(
+                !DownloadLinkHelper::verify(SECRET, ID, EXPIRES, signature),
+                "{signature}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_is_expired() {
+        assert!(!DownloadLinkHelper::is_expired(EXPIRES, EXPIRES - 1));
+        // The expiry is exclusive
+        assert!(DownloadLinkHelper::is_expired(EXPIRES, EXPIRES));
+        assert!(DownloadLinkHelper::is_expired(EXPIRES, EXPIRES + 1));
+    }
+
+    #[test]
+    fn test_url() {
+        let link = DownloadLink {
+            id: ID.to_string(),
+            file_id: Some("a1d4bd2b-f3c5-4d0a-9c44-0f8b9e1a2c3d".to_string()),
+            compressed_file_id: None,
+            expires_at: Utc.timestamp_opt(EXPIRES, 0).unwrap(),
+            max_downloads: None,
+            downloads: 0,
+            allowed_ip: None,
+            created_by: None,
+            created_at: Utc.timestamp_opt(EXPIRES - 3600, 0).unwrap(),
+        };
+        let signature = DownloadLinkHelper::sign(SECRET, ID, EXPIRES);
+        let expected =
+            format!("https://example.com/downloads/{ID}?expires={EXPIRES}&signature={signature}");
+
+        assert_eq!(
+            DownloadLinkHelper::url("https://example.com", SECRET, &link),
+            expected
+        );
+        assert_eq!(
+            DownloadLinkHelper::url("https://example.com/", SECRET, &link),
+            expected
+        );
+    }
+}
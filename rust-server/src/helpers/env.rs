@@ -1,5 +1,7 @@
 use std::env;
 
+use sqlx::types::Uuid;
+
 use super::logger::Logger;
 
 #[derive(Debug, Clone)]
@@ -9,6 +11,24 @@ pub struct Env {
     pub port: String,
     pub uploads_dir: String,
     pub compressed_dir: String,
+    pub max_upload_size: u64,
+    pub compression_threads: usize,
+    pub min_compression_ratio: f64,
+    pub deleted_retention_secs: u64,
+    pub encode_on_the_fly: bool,
+    pub compression_workers: usize,
+    pub job_max_attempts: u32,
+    pub job_visibility_timeout_secs: u64,
+    pub worker_id: String,
+    pub webhook_max_attempts: u32,
+    pub admin_token: Option<String>,
+    pub jwt_algorithm: Option<String>,
+    pub jwt_secret: Option<String>,
+    pub jwt_public_key: Option<String>,
+    pub jwt_issuer: Option<String>,
+    pub jwt_audience: Option<String>,
+    pub download_link_secret: Option<String>,
+    pub public_url: String,
 }
 
 impl Env {
@@ -17,36 +37,188 @@ impl Env {
         dotenvy::dotenv().ok();
 
         let database_url = env::var("DATABASE_URL");
-        if let Err(_) = &database_url {
+        if database_url.is_err() {
             logger.warn("Missing environment variable: DATABASE_URL");
         };
 
         let host = env::var("HOST");
-        if let Err(_) = &host {
+        if host.is_err() {
             logger.warn("Missing environment variable: HOST");
         };
 
         let port = env::var("PORT");
-        if let Err(_) = &port {
+        if port.is_err() {
             logger.warn("Missing environment variable: PORT");
         };
 
         let uploads_dir = env::var("UPLOADS_DIR");
-        if let Err(_) = &uploads_dir {
+        if uploads_dir.is_err() {
             logger.warn("Missing environment variable: UPLOADS_DIR");
         };
 
         let compressed_dir = env::var("COMPRESSED_DIR");
-        if let Err(_) = &compressed_dir {
+        if compressed_dir.is_err() {
             logger.warn("Missing environment variable: COMPRESSED_DIR");
         };
 
+        // Optional, larger uploads are refused with 413, in bytes. Sizes are stored as BIGINT.
+        let max_upload_size = env::var("MAX_UPLOAD_SIZE")
+            .ok()
+            .and_then(|size| size.parse::<u64>().ok())
+            .filter(|size| *size > 0 && *size <= i64::MAX as u64);
+        if max_upload_size.is_none() {
+            logger.log("MAX_UPLOAD_SIZE not set, using 10 GiB");
+        };
+
+        // Optional, defaults to one thread per available core
+        let compression_threads = env::var("COMPRESSION_THREADS")
+            .ok()
+            .and_then(|threads| threads.parse::<usize>().ok())
+            .filter(|threads| *threads > 0);
+        if compression_threads.is_none() {
+            logger.log("COMPRESSION_THREADS not set, using all available cores");
+        };
+
+        // Optional, compressions that can't reach this ratio are skipped
+        let min_compression_ratio = env::var("MIN_COMPRESSION_RATIO")
+            .ok()
+            .and_then(|ratio| ratio.parse::<f64>().ok())
+            .filter(|ratio| ratio.is_finite());
+        if min_compression_ratio.is_none() {
+            logger.log("MIN_COMPRESSION_RATIO not set, using 1.1");
+        };
+
+        // Optional, deleted files can be restored for this long before they are purged
+        let deleted_retention_secs = env::var("DELETED_RETENTION_SECS")
+            .ok()
+            .and_then(|secs| secs.parse::<u64>().ok());
+        if deleted_retention_secs.is_none() {
+            logger.log("DELETED_RETENTION_SECS not set, using 7 days");
+        };
+
+        // Optional, downloads are compressed while they are sent when no compressed variant matches
+        let encode_on_the_fly = env::var("ENCODE_ON_THE_FLY")
+            .ok()
+            .and_then(|enabled| enabled.parse::<bool>().ok());
+        if encode_on_the_fly.is_none() {
+            logger.log("ENCODE_ON_THE_FLY not set, only serving existing compressed variants");
+        };
+
+        // Optional, number of compressions that run at the same time
+        let compression_workers = env::var("COMPRESSION_WORKERS")
+            .ok()
+            .and_then(|workers| workers.parse::<usize>().ok())
+            .filter(|workers| *workers > 0);
+        if compression_workers.is_none() {
+            logger.log("COMPRESSION_WORKERS not set, using 2");
+        };
+
+        // Optional, a compression is marked as failed after this many tries
+        let job_max_attempts = env::var("JOB_MAX_ATTEMPTS")
+            .ok()
+            .and_then(|attempts| attempts.parse::<u32>().ok())
+            .filter(|attempts| *attempts > 0);
+        if job_max_attempts.is_none() {
+            logger.log("JOB_MAX_ATTEMPTS not set, using 3");
+        };
+
+        // Optional, a job whose worker stops reporting for this long is handed to another worker
+        let job_visibility_timeout_secs = env::var("JOB_VISIBILITY_TIMEOUT_SECS")
+            .ok()
+            .and_then(|secs| secs.parse::<u64>().ok())
+            .filter(|secs| *secs > 0);
+        if job_visibility_timeout_secs.is_none() {
+            logger.log("JOB_VISIBILITY_TIMEOUT_SECS not set, using 60");
+        };
+
+        // Optional, identifies this instance's jobs so they are taken back after a restart.
+        // Unset, every run gets its own, and interrupted jobs wait for the visibility timeout instead.
+        let worker_id = env::var("WORKER_ID").ok().filter(|id| !id.is_empty());
+        if worker_id.is_none() {
+            logger.log("WORKER_ID not set, using the host name, process ID and a random suffix");
+        };
+
+        // Optional, a webhook delivery is given up after this many tries
+        let webhook_max_attempts = env::var("WEBHOOK_MAX_ATTEMPTS")
+            .ok()
+            .and_then(|attempts| attempts.parse::<u32>().ok())
+            .filter(|attempts| *attempts > 0);
+        if webhook_max_attempts.is_none() {
+            logger.log("WEBHOOK_MAX_ATTEMPTS not set, using 5");
+        };
+
+        // Optional, grants admin access without a user, e.g. to create the first users and API keys
+        let admin_token = env::var("ADMIN_TOKEN")
+            .ok()
+            .filter(|token| !token.is_empty());
+        if admin_token.is_none() {
+            logger.log("ADMIN_TOKEN not set, only API keys and JWTs are accepted");
+        };
+
+        // Optional, HS256 with JWT_SECRET or RS256 with JWT_PUBLIC_KEY (PEM or path to it)
+        let jwt_algorithm = env::var("JWT_ALGORITHM").ok();
+        if jwt_algorithm.is_none() {
+            logger.log("JWT_ALGORITHM not set, JWTs are not accepted");
+        };
+        let jwt_secret = env::var("JWT_SECRET").ok();
+        let jwt_public_key = env::var("JWT_PUBLIC_KEY").ok();
+        // Optional, checked against the `iss` and `aud` claims when set
+        let jwt_issuer = env::var("JWT_ISSUER").ok();
+        let jwt_audience = env::var("JWT_AUDIENCE").ok();
+
+        // Optional, signs the download links handed out to people without credentials
+        let download_link_secret = env::var("DOWNLOAD_LINK_SECRET")
+            .ok()
+            .filter(|secret| !secret.is_empty());
+        if download_link_secret.is_none() {
+            logger.log("DOWNLOAD_LINK_SECRET not set, download links are disabled");
+        };
+
+        // Optional, where clients reach the server, download links start with it
+        let public_url = env::var("PUBLIC_URL").ok();
+        if public_url.is_none() {
+            logger.log("PUBLIC_URL not set, using http://HOST:PORT");
+        };
+
+        let public_url = public_url.unwrap_or_else(|| {
+            format!(
+                "http://{}:{}",
+                host.as_deref().unwrap_or_default(),
+                port.as_deref().unwrap_or_default()
+            )
+        });
+
         Env {
             database_url: database_url.unwrap_or("".to_owned()),
             host: host.unwrap_or("".to_owned()),
             port: port.unwrap_or("".to_owned()),
             uploads_dir: uploads_dir.unwrap_or("".to_owned()),
             compressed_dir: compressed_dir.unwrap_or("".to_owned()),
+            max_upload_size: max_upload_size.unwrap_or(10 * 1024 * 1024 * 1024),
+            compression_threads: compression_threads.unwrap_or_else(|| {
+                std::thread::available_parallelism().map_or(1, |threads| threads.get())
+            }),
+            min_compression_ratio: min_compression_ratio.unwrap_or(1.1),
+            deleted_retention_secs: deleted_retention_secs.unwrap_or(7 * 24 * 60 * 60),
+            encode_on_the_fly: encode_on_the_fly.unwrap_or(false),
+            compression_workers: compression_workers.unwrap_or(2),
+            job_max_attempts: job_max_attempts.unwrap_or(3),
+            job_visibility_timeout_secs: job_visibility_timeout_secs.unwrap_or(60),
+            worker_id: worker_id.unwrap_or_else(|| {
+                let host = hostname::get().map_or("localhost".to_owned(), |host| {
+                    host.to_string_lossy().into_owned()
+                });
+                format!("{host}-{}-{}", std::process::id(), Uuid::new_v4())
+            }),
+            webhook_max_attempts: webhook_max_attempts.unwrap_or(5),
+            admin_token,
+            jwt_algorithm,
+            jwt_secret,
+            jwt_public_key,
+            jwt_issuer,
+            jwt_audience,
+            download_link_secret,
+            public_url,
         }
     }
 }
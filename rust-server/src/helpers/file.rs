@@ -1,15 +1,30 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+use rust_server::{Algorithm, ArchiveFormat};
+use sha2::{Digest, Sha256};
+use sqlx::types::Uuid;
+
+use crate::models::file::{CompressedFile, File};
+
+// Blobs live under `<uploads_dir>/blobs`, on the same filesystem as the temporary uploads
+const BLOBS_DIR: &str = "blobs";
 
 pub struct FileHelper;
 
 impl FileHelper {
-    pub fn get_compressed_file_path(file_ref: &str, compressed_dir: &str) -> Option<String> {
+    pub fn get_compressed_file_path(
+        id: &str,
+        compressed_dir: &str,
+        algorithm: Algorithm,
+    ) -> Option<String> {
         let compressed_dir = PathBuf::from(compressed_dir);
-        if let Err(_) = std::fs::create_dir_all(&compressed_dir) {
+        if std::fs::create_dir_all(&compressed_dir).is_err() {
             return None;
         }
-    
-        let output_path = compressed_dir.join(file_ref).with_extension("gz");
+
+        // Named after the compressed file, so compressions of the same file at other levels never share it
+        let id: Uuid = id.parse().ok()?;
+        let output_path = compressed_dir.join(format!("{id}.{}", algorithm.extension()));
         let output_path = match output_path.to_str() {
             Some(path) => path.to_string(),
             None => {
@@ -20,13 +35,44 @@ impl FileHelper {
         Some(output_path)
     }
     
+    /// Where the output of a compression or an archive is stored
+    pub fn get_output_path(
+        compressed_file: &CompressedFile,
+        compressed_dir: &str,
+    ) -> Option<String> {
+        if let Ok(format) = compressed_file.alg.parse::<ArchiveFormat>() {
+            return Self::get_archive_file_path(&compressed_file.id, compressed_dir, format);
+        }
+
+        let algorithm: Algorithm = compressed_file.alg.parse().ok()?;
+        Self::get_compressed_file_path(&compressed_file.id, compressed_dir, algorithm)
+    }
+
+    // Named after the archive's compressed file, e.g. `<compressed_dir>/<id>.tar.gz`
+    pub fn get_archive_file_path(
+        id: &str,
+        compressed_dir: &str,
+        format: ArchiveFormat,
+    ) -> Option<String> {
+        let compressed_dir = PathBuf::from(compressed_dir);
+        if std::fs::create_dir_all(&compressed_dir).is_err() {
+            return None;
+        }
+
+        let id: Uuid = id.parse().ok()?;
+        compressed_dir
+            .join(format!("{id}.{}", format.extension()))
+            .to_str()
+            .map(|path| path.to_string())
+    }
+
     pub fn get_uploaded_file_path(file_ref: &str, uploads_dir: &str) -> Option<String> {
         let uploads_dir = PathBuf::from(uploads_dir);
-        if let Err(_) = std::fs::create_dir_all(&uploads_dir) {
+        if std::fs::create_dir_all(&uploads_dir).is_err() {
             return None;
         }
 
-        let input_path_buf = PathBuf::from(uploads_dir).join(file_ref);
+        let input_path_buf = uploads_dir.join(Self::file_name(file_ref)?);
         let input_path = match input_path_buf.to_str() {
             Some(path) => path.to_string(),
             None => {
@@ -36,4 +82,78 @@ impl FileHelper {
     
         Some(input_path)
     }
-}
\ No newline at end of file
+
+    /// Where the content of a file is stored: its blob, or the upload itself for files stored before blobs
+    pub fn get_file_path(file: &File, uploads_dir: &str) -> Option<String> {
+        match &file.blob_sha256 {
+            Some(sha256) => Self::get_blob_path(sha256, uploads_dir),
+            None => Self::get_uploaded_file_path(&file.file_ref, uploads_dir),
+        }
+    }
+
+    /// Content-addressed path of a blob, sharded by the first two bytes of its hash,
+    /// e.g. `uploads/blobs/ab/cd/abcd...`
+    pub fn get_blob_path(sha256: &str, uploads_dir: &str) -> Option<String> {
+        if sha256.len() != 64 || !sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+
+        let shard_dir = PathBuf::from(uploads_dir)
+            .join(BLOBS_DIR)
+            .join(&sha256[..2])
+            .join(&sha256[2..4]);
+        if std::fs::create_dir_all(&shard_dir).is_err() {
+            return None;
+        }
+
+        shard_dir.join(sha256).to_str().map(|path| path.to_string())
+    }
+
+    /// Moves a fully written temporary file into the blob store.
+    /// The blob is always replaced, even when it exists: the bytes are identical and the rename is atomic,
+    /// while keeping the existing one would rely on it surviving until the new file references it.
+    pub async fn store_blob(
+        temp_path: &str,
+        sha256: &str,
+        uploads_dir: &str,
+    ) -> std::io::Result<String> {
+        let blob_path = Self::get_blob_path(sha256, uploads_dir)
+            .ok_or_else(|| std::io::Error::other("Failed to get blob path"))?;
+
+        tokio::fs::rename(temp_path, &blob_path).await?;
+
+        Ok(blob_path)
+    }
+
+    /// SHA-256 of a file on disk, in hex
+    pub async fn hash_file(path: &str) -> std::io::Result<String> {
+        let path = path.to_owned();
+        tokio::task::spawn_blocking(move || {
+            let mut hasher = Sha256::new();
+            std::io::copy(&mut std::fs::File::open(path)?, &mut hasher)?;
+            Ok(format!("{:x}", hasher.finalize()))
+        })
+        .await
+        .map_err(std::io::Error::other)?
+    }
+
+    /// Name the file was uploaded with, without the timestamp prefix added on upload
+    pub fn original_file_name(file_ref: &str) -> Option<&str> {
+        let file_name = Self::file_name(file_ref)?;
+        match file_name.split_once('_') {
+            Some((prefix, name))
+                if !prefix.is_empty()
+                    && !name.is_empty()
+                    && prefix.chars().all(|c| c.is_ascii_digit()) =>
+            {
+                Some(name)
+            }
+            _ => Some(file_name),
+        }
+    }
+
+    // File refs look like `/uploads/<name>`, joining them as-is would escape the target directory
+    fn file_name(file_ref: &str) -> Option<&str> {
+        Path::new(file_ref).file_name().and_then(|name| name.to_str())
+    }
+}
@@ -0,0 +1,346 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use rsa::{
+    pkcs1::DecodeRsaPublicKey, pkcs1v15::Pkcs1v15Sign, pkcs8::DecodePublicKey, RsaPublicKey,
+};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use super::env::Env;
+
+// Allowed clock difference with the issuer when checking `exp` and `nbf`
+const LEEWAY_SECS: i64 = 60;
+
+/// Key JWTs are signed with, the algorithm of a token must match it
+#[derive(Debug)]
+pub enum JwtKey {
+    Hs256(Vec<u8>),
+    Rs256(Box<RsaPublicKey>),
+}
+
+/// Claims read from a valid token
+#[derive(Debug, Deserialize)]
+pub struct Claims {
+    // ID of the user the token was issued for
+    pub sub: String,
+    pub exp: i64,
+    pub nbf: Option<i64>,
+    pub iss: Option<String>,
+    // A single audience or a list of them
+    pub aud: Option<serde_json::Value>,
+    // Space separated, as in OAuth 2.0
+    pub scope: Option<String>,
+    pub scopes: Option<Vec<String>>,
+}
+
+impl Claims {
+    pub fn scopes(&self) -> Vec<String> {
+        let mut scopes = self.scopes.clone().unwrap_or_default();
+        if let Some(scope) = &self.scope {
+            scopes.extend(scope.split_whitespace().map(str::to_string));
+        }
+        scopes
+    }
+}
+
+#[derive(Deserialize)]
+struct Header {
+    alg: String,
+}
+
+/// Checks the signature and the time and audience claims of JWTs issued by the configured issuer
+#[derive(Debug)]
+pub struct JwtValidator {
+    key: JwtKey,
+    issuer: Option<String>,
+    audience: Option<String>,
+}
+
+impl JwtValidator {
+    /// Builds the validator from `JWT_ALGORITHM` and its key, None when JWTs aren't configured
+    pub fn from_env(env: &Env) -> Result<Option<Self>, String> {
+        let Some(algorithm) = &env.jwt_algorithm else {
+            return Ok(None);
+        };
+        let key = match algorithm.to_uppercase().as_str() {
+            "HS256" => match &env.jwt_secret {
+                Some(secret) if !secret.is_empty() => JwtKey::Hs256(secret.as_bytes().to_vec()),
+                _ => return Err("JWT_SECRET is required with HS256".to_string()),
+            },
+            "RS256" => {
+                let Some(public_key) = &env.jwt_public_key else {
+                    return Err("JWT_PUBLIC_KEY is required with RS256".to_string());
+                };
+                // The PEM itself, or the path of a file holding it
+                let pem = match public_key.trim_start().starts_with("-----BEGIN") {
+                    true => public_key.clone(),
+                    false => std::fs::read_to_string(public_key)
+                        .map_err(|e| format!("Failed to read JWT_PUBLIC_KEY {public_key}: {e}"))?,
+                };
+                let key = RsaPublicKey::from_public_key_pem(&pem)
+                    .or_else(|_| RsaPublicKey::from_pkcs1_pem(&pem))
+                    .map_err(|e| format!("Invalid JWT_PUBLIC_KEY: {e}"))?;
+                JwtKey::Rs256(Box::new(key))
+            }
+            _ => return Err(format!("Unsupported JWT_ALGORITHM: {algorithm}")),
+        };
+
+        Ok(Some(Self {
+            key,
+            issuer: env.jwt_issuer.clone(),
+            audience: env.jwt_audience.clone(),
+        }))
+    }
+
+    pub fn validate(&self, token: &str) -> Result<Claims, String> {
+        let mut parts = token.split('.');
+        let (Some(header_part), Some(payload_part), Some(signature_part), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err("Malformed token".to_string());
+        };
+        let decode = |part: &str| URL_SAFE_NO_PAD.decode(part).map_err(|_| "Malformed token");
+
+        let header: Header =
+            serde_json::from_slice(&decode(header_part)?).map_err(|_| "Malformed token header")?;
+        let signature = decode(signature_part)?;
+        // The signature covers the encoded header and payload as they were sent
+        let signed = &token[..header_part.len() + 1 + payload_part.len()];
+        match (&self.key, header.alg.as_str()) {
+            (JwtKey::Hs256(secret), "HS256") => {
+                // HMAC takes keys of any size
+                let mut mac = Hmac::<Sha256>::new_from_slice(secret).unwrap();
+                mac.update(signed.as_bytes());
+                mac.verify_slice(&signature)
+                    .map_err(|_| "Invalid token signature")?;
+            }
+            (JwtKey::Rs256(public_key), "RS256") => {
+                let hashed = Sha256::digest(signed.as_bytes());
+                public_key
+                    .verify(Pkcs1v15Sign::new::<Sha256>(), &hashed, &signature)
+                    .map_err(|_| "Invalid token signature")?;
+            }
+            (_, alg) => return Err(format!("Unexpected token algorithm: {alg}")),
+        }
+
+        let claims: Claims = serde_json::from_slice(&decode(payload_part)?)
+            .map_err(|e| format!("Invalid token claims: {e}"))?;
+        let now = chrono::Utc::now().timestamp();
+        if claims.exp + LEEWAY_SECS < now {
+            return Err("Token has expired".to_string());
+        }
+        if claims.nbf.is_some_and(|nbf| nbf - LEEWAY_SECS > now) {
+            return Err("Token isn't valid yet".to_string());
+        }
+        if let Some(issuer) = &self.issuer {
+            if claims.iss.as_ref() != Some(issuer) {
+                return Err("Unexpected token issuer".to_string());
+            }
+        }
+        if let Some(audience) = &self.audience {
+            let matches = match &claims.aud {
+                Some(serde_json::Value::String(aud)) => aud == audience,
+                Some(serde_json::Value::Array(auds)) => auds.iter().any(|aud| aud == audience),
+                _ => false,
+            };
+            if !matches {
+                return Err("Unexpected token audience".to_string());
+            }
+        }
+
+        Ok(claims)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rsa::RsaPrivateKey;
+    use serde_json::json;
+
+    const SECRET: &[u8] = b"secret";
+
+    fn validator(key: JwtKey) -> JwtValidator {
+        JwtValidator {
+            key,
+            issuer: None,
+            audience: None,
+        }
+    }
+
+    fn now() -> i64 {
+        chrono::Utc::now().timestamp()
+    }
+
+    fn unsigned(alg: &str, claims: &serde_json::Value) -> String {
+        let header = URL_SAFE_NO_PAD.encode(json!({ "alg": alg, "typ": "JWT" }).to_string());
+        let payload = URL_SAFE_NO_PAD.encode(claims.to_string());
+        format!("{header}.{payload}")
+    }
+
+    fn hs256(secret: &[u8], claims: serde_json::Value) -> String {
+        let signed = unsigned("HS256", &claims);
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret).unwrap();
+        mac.update(signed.as_bytes());
+        let signature = URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes());
+        format!("{signed}.{signature}")
+    }
+
+    fn rs256(private_key: &RsaPrivateKey, claims: serde_json::Value) -> String {
+        let signed = unsigned("RS256", &claims);
+        let hashed = Sha256::digest(signed.as_bytes());
+        let signature = private_key
+            .sign(Pkcs1v15Sign::new::<Sha256>(), &hashed)
+            .unwrap();
+        format!("{signed}.{}", URL_SAFE_NO_PAD.encode(signature))
+    }
+
+    fn rsa_keys() -> (RsaPrivateKey, JwtKey) {
+        let private_key = RsaPrivateKey::new(&mut rand::thread_rng(), 1024).unwrap();
+        let public_key = RsaPublicKey::from(&private_key);
+        (private_key, JwtKey::Rs256(Box::new(public_key)))
+    }
+
+    #[test]
+    fn test_validate_hs256() {
+        let validator = validator(JwtKey::Hs256(SECRET.to_vec()));
+        let token = hs256(
+            SECRET,
+            json!({ "sub": "user", "exp": now() + 60, "scope": "files:read files:write", "scopes": ["admin"] }),
+        );
+
+        let claims = validator.validate(&token).unwrap();
+        assert_eq!(claims.sub, "user");
+        assert_eq!(claims.scopes(), ["admin", "files:read", "files:write"]);
+    }
+
+    #[test]
+    fn test_validate_bad_signature() {
+        let validator = validator(JwtKey::Hs256(SECRET.to_vec()));
+        let claims = json!({ "sub": "user", "exp": now() + 60 });
+
+        let token = hs256(b"another secret", claims.clone());
+        assert_eq!(
+            validator.validate(&token).unwrap_err(),
+            "Invalid token signature"
+        );
+
+        // Claims changed after signing
+        let token = hs256(SECRET, claims);
+        let (signed, signature) = token.rsplit_once('.').unwrap();
+        let (header, _) = signed.split_once('.').unwrap();
+        let payload =
+            URL_SAFE_NO_PAD.encode(json!({ "sub": "admin", "exp": now() + 60 }).to_string());
+        assert_eq!(
+            validator
+                .validate(&format!("{header}.{payload}.{signature}"))
+                .unwrap_err(),
+            "Invalid token signature"
+        );
+
+        let (private_key, key) = rsa_keys();
+        let validator = self::validator(key);
+        let token = rs256(&private_key, json!({ "sub": "user", "exp": now() + 60 }));
+        assert!(validator.validate(&token).is_ok());
+        let (other_key, _) = rsa_keys();
+        let token = rs256(&other_key, json!({ "sub": "user", "exp": now() + 60 }));
+        assert_eq!(
+            validator.validate(&token).unwrap_err(),
+            "Invalid token signature"
+        );
+    }
+
+    #[test]
+    fn test_validate_algorithm_mismatch() {
+        let (_, key) = rsa_keys();
+        let JwtKey::Rs256(public_key) = &key else {
+            unreachable!()
+        };
+        // An HS256 token keyed with the public key must not pass for one signed with the private key
+        let public_der = rsa::pkcs1::EncodeRsaPublicKey::to_pkcs1_der(public_key.as_ref()).unwrap();
+        let token = hs256(
+            public_der.as_bytes(),
+            json!({ "sub": "user", "exp": now() + 60 }),
+        );
+        assert_eq!(
+            validator(key).validate(&token).unwrap_err(),
+            "Unexpected token algorithm: HS256"
+        );
+
+        let token = unsigned("none", &json!({ "sub": "user", "exp": now() + 60 })) + ".";
+        assert_eq!(
+            validator(JwtKey::Hs256(SECRET.to_vec()))
+                .validate(&token)
+                .unwrap_err(),
+            "Unexpected token algorithm: none"
+        );
+    }
+
+    #[test]
+    fn test_validate_malformed() {
+        let validator = validator(JwtKey::Hs256(SECRET.to_vec()));
+        for token in ["", "a.b", "a.b.c.d", "!.!.!"] {
+            assert!(validator.validate(token).is_err(), "{token}");
+        }
+    }
+
+    #[test]
+    fn test_validate_exp_nbf_leeway() {
+        let validator = validator(JwtKey::Hs256(SECRET.to_vec()));
+        let validate = |claims| validator.validate(&hs256(SECRET, claims));
+
+        assert!(validate(json!({ "sub": "user", "exp": now() - LEEWAY_SECS / 2 })).is_ok());
+        assert_eq!(
+            validate(json!({ "sub": "user", "exp": now() - LEEWAY_SECS * 2 })).unwrap_err(),
+            "Token has expired"
+        );
+        assert!(validate(
+            json!({ "sub": "user", "exp": now() + 60, "nbf": now() + LEEWAY_SECS / 2 })
+        )
+        .is_ok());
+        assert_eq!(
+            validate(json!({ "sub": "user", "exp": now() + 600, "nbf": now() + LEEWAY_SECS * 2 }))
+                .unwrap_err(),
+            "Token isn't valid yet"
+        );
+        // exp is required
+        assert!(validate(json!({ "sub": "user" })).is_err());
+    }
+
+    #[test]
+    fn test_validate_issuer_audience() {
+        let validator = JwtValidator {
+            key: JwtKey::Hs256(SECRET.to_vec()),
+            issuer: Some("issuer".to_string()),
+            audience: Some("crate".to_string()),
+        };
+        let validate = |claims| validator.validate(&hs256(SECRET, claims));
+        let exp = now() + 60;
+
+        assert!(
+            validate(json!({ "sub": "user", "exp": exp, "iss": "issuer", "aud": "crate" })).is_ok()
+        );
+        assert!(validate(
+            json!({ "sub": "user", "exp": exp, "iss": "issuer", "aud": ["other", "crate"] })
+        )
+        .is_ok());
+        assert_eq!(
+            validate(json!({ "sub": "user", "exp": exp, "iss": "other", "aud": "crate" }))
+                .unwrap_err(),
+            "Unexpected token issuer"
+        );
+        assert_eq!(
+            validate(json!({ "sub": "user", "exp": exp, "aud": "crate" })).unwrap_err(),
+            "Unexpected token issuer"
+        );
+        assert_eq!(
+            validate(json!({ "sub": "user", "exp": exp, "iss": "issuer", "aud": ["other"] }))
+                .unwrap_err(),
+            "Unexpected token audience"
+        );
+        assert_eq!(
+            validate(json!({ "sub": "user", "exp": exp, "iss": "issuer" })).unwrap_err(),
+            "Unexpected token audience"
+        );
+    }
+}
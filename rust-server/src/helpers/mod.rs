@@ -1,4 +1,9 @@
 pub mod env;
 pub mod logger;
+pub mod access;
+pub mod api_key;
 pub mod date_formater;
-pub mod file;
\ No newline at end of file
+pub mod download_link;
+pub mod file;
+pub mod jwt;
+pub mod pagination;
\ No newline at end of file
@@ -0,0 +1,279 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::DateTime;
+use sqlx::{types::Uuid, Postgres, QueryBuilder};
+
+use crate::models::page::{Page, SortField, SortOrder};
+
+pub const DEFAULT_PAGE_SIZE: i64 = 50;
+pub const MAX_PAGE_SIZE: i64 = 200;
+
+/// Position right after the last item of a page: the sort and order it was taken with, that item's sort key
+/// and its ID. `created_at` keys are stored as microseconds since the epoch, the precision Postgres keeps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cursor {
+    pub sort: SortField,
+    pub order: SortOrder,
+    pub key: i64,
+    pub id: Uuid,
+}
+
+impl Cursor {
+    pub fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(format!(
+            "{}:{}:{}:{}",
+            self.sort.name(),
+            self.order.name(),
+            self.key,
+            self.id
+        ))
+    }
+
+    pub fn decode(cursor: &str) -> Result<Self, String> {
+        let invalid = || format!("Invalid cursor: {cursor}");
+        let decoded = URL_SAFE_NO_PAD.decode(cursor).map_err(|_| invalid())?;
+        let decoded = String::from_utf8(decoded).map_err(|_| invalid())?;
+
+        let mut parts = decoded.splitn(4, ':');
+        let sort = match parts.next() {
+            Some("created_at") => SortField::CreatedAt,
+            Some("size") => SortField::Size,
+            _ => return Err(invalid()),
+        };
+        let order = match parts.next() {
+            Some("asc") => SortOrder::Asc,
+            Some("desc") => SortOrder::Desc,
+            _ => return Err(invalid()),
+        };
+        let key = parts
+            .next()
+            .and_then(|key| key.parse().ok())
+            .ok_or_else(invalid)?;
+        let id = parts
+            .next()
+            .and_then(|id| id.parse().ok())
+            .ok_or_else(invalid)?;
+
+        Ok(Self {
+            sort,
+            order,
+            key,
+            id,
+        })
+    }
+}
+
+pub struct Pagination {
+    pub sort: SortField,
+    pub order: SortOrder,
+    pub cursor: Option<Cursor>,
+    pub limit: i64,
+}
+
+impl Pagination {
+    /// Checks the raw query parameters, a cursor only continues the sort and order it was created with
+    pub fn new(
+        sort: SortField,
+        order: SortOrder,
+        cursor: Option<&str>,
+        limit: Option<i64>,
+    ) -> Result<Self, String> {
+        let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE);
+        if !(1..=MAX_PAGE_SIZE).contains(&limit) {
+            return Err(format!("limit must be between 1 and {MAX_PAGE_SIZE}"));
+        }
+
+        let cursor = cursor.map(Cursor::decode).transpose()?;
+        if cursor.is_some_and(|cursor| cursor.sort != sort) {
+            return Err("Cursor was created with a different sort".to_string());
+        }
+        if cursor.is_some_and(|cursor| cursor.order != order) {
+            return Err("Cursor was created with a different order".to_string());
+        }
+
+        Ok(Self {
+            sort,
+            order,
+            cursor,
+            limit,
+        })
+    }
+
+    /// Appends the keyset condition, ordering and limit to a query already ending in a `WHERE` clause.
+    /// One row more than the page size is fetched, so the caller can tell whether another page follows.
+    pub fn push(&self, query: &mut QueryBuilder<'_, Postgres>, sort_column: &str) {
+        let (comparison, direction) = match self.order {
+            SortOrder::Asc => (">", "ASC"),
+            SortOrder::Desc => ("<", "DESC"),
+        };
+
+        if let Some(cursor) = self.cursor {
+            query.push(format!(" AND ({sort_column}, id) {comparison} ("));
+            match self.sort {
+                SortField::CreatedAt => {
+                    query.push_bind(DateTime::from_timestamp_micros(cursor.key))
+                }
+                SortField::Size => query.push_bind(cursor.key),
+            };
+            query.push(", ").push_bind(cursor.id).push(")");
+        }
+
+        query.push(format!(
+            " ORDER BY {sort_column} {direction}, id {direction} LIMIT "
+        ));
+        query.push_bind(self.limit + 1);
+    }
+
+    /// Builds the page from the rows fetched by a query `push` was applied to
+    pub fn page<T>(&self, mut rows: Vec<T>, sort_key: impl Fn(&T) -> (i64, &str)) -> Page<T> {
+        let has_more = rows.len() as i64 > self.limit;
+        rows.truncate(self.limit as usize);
+
+        let next_cursor = match rows.last() {
+            Some(last) if has_more => {
+                let (key, id) = sort_key(last);
+                id.parse().ok().map(|id| {
+                    Cursor {
+                        sort: self.sort,
+                        order: self.order,
+                        key,
+                        id,
+                    }
+                    .encode()
+                })
+            }
+            _ => None,
+        };
+
+        Page {
+            data: rows,
+            next_cursor,
+            has_more,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID: &str = "7f1f3a52-8f0e-4f47-9d0c-64c6b4a9b1de";
+
+    fn cursor(sort: SortField, order: SortOrder) -> Cursor {
+        Cursor {
+            sort,
+            order,
+            key: 1_747_650_000_250_000,
+            id: ID.parse().unwrap(),
+        }
+    }
+
+    fn pagination(limit: i64) -> Pagination {
+        Pagination::new(SortField::Size, SortOrder::Asc, None, Some(limit)).unwrap()
+    }
+
+    fn rows(count: i64) -> Vec<(i64, String)> {
+        (0..count)
+            .map(|i| (i * 10, format!("00000000-0000-0000-0000-{i:012}")))
+            .collect()
+    }
+
+    fn sort_key(row: &(i64, String)) -> (i64, &str) {
+        (row.0, &row.1)
+    }
+
+    #[test]
+    fn test_cursor_round_trip() {
+        for sort in [SortField::CreatedAt, SortField::Size] {
+            for order in [SortOrder::Asc, SortOrder::Desc] {
+                let cursor = cursor(sort, order);
+                assert_eq!(Cursor::decode(&cursor.encode()), Ok(cursor));
+            }
+        }
+
+        let cursor = Cursor {
+            key: -1,
+            ..cursor(SortField::Size, SortOrder::Desc)
+        };
+        assert_eq!(Cursor::decode(&cursor.encode()), Ok(cursor));
+    }
+
+    #[test]
+    fn test_cursor_invalid() {
+        let encode = |raw: &str| URL_SAFE_NO_PAD.encode(raw);
+        for cursor in [
+            "not base64!".to_string(),
+            URL_SAFE_NO_PAD.encode([0xff, 0xfe]),
+            encode(""),
+            encode(&format!("name:asc:1:{ID}")),
+            encode(&format!("size:up:1:{ID}")),
+            encode(&format!("size:asc:one:{ID}")),
+            encode(&format!("size:asc:1.5:{ID}")),
+            encode("size:asc:1:not-a-uuid"),
+            encode("size:asc:1"),
+            // Cursors from before the order was recorded
+            encode(&format!("size:1:{ID}")),
+        ] {
+            assert_eq!(
+                Cursor::decode(&cursor),
+                Err(format!("Invalid cursor: {cursor}"))
+            );
+        }
+    }
+
+    #[test]
+    fn test_pagination_cursor_mismatch() {
+        let cursor = cursor(SortField::Size, SortOrder::Desc).encode();
+        let new = |sort, order| Pagination::new(sort, order, Some(&cursor), None);
+
+        assert!(new(SortField::Size, SortOrder::Desc).is_ok());
+        assert_eq!(
+            new(SortField::CreatedAt, SortOrder::Desc).err(),
+            Some("Cursor was created with a different sort".to_string())
+        );
+        assert_eq!(
+            new(SortField::Size, SortOrder::Asc).err(),
+            Some("Cursor was created with a different order".to_string())
+        );
+    }
+
+    #[test]
+    fn test_pagination_limit() {
+        let new = |limit| Pagination::new(SortField::CreatedAt, SortOrder::Desc, None, limit);
+
+        assert_eq!(new(None).unwrap().limit, DEFAULT_PAGE_SIZE);
+        assert_eq!(new(Some(1)).unwrap().limit, 1);
+        assert_eq!(new(Some(MAX_PAGE_SIZE)).unwrap().limit, MAX_PAGE_SIZE);
+        for limit in [0, -1, MAX_PAGE_SIZE + 1] {
+            assert!(new(Some(limit)).is_err(), "{limit}");
+        }
+    }
+
+    #[test]
+    fn test_page() {
+        // Exactly a page, nothing follows
+        let page = pagination(3).page(rows(3), sort_key);
+        assert_eq!(page.data.len(), 3);
+        assert!(!page.has_more);
+        assert_eq!(page.next_cursor, None);
+
+        // The extra row only tells that another page follows
+        let page = pagination(3).page(rows(4), sort_key);
+        assert_eq!(page.data, rows(3));
+        assert!(page.has_more);
+        let next_cursor = Cursor::decode(&page.next_cursor.unwrap()).unwrap();
+        assert_eq!(
+            next_cursor,
+            Cursor {
+                sort: SortField::Size,
+                order: SortOrder::Asc,
+                key: 20,
+                id: rows(3)[2].1.parse().unwrap(),
+            }
+        );
+
+        let page = pagination(3).page(rows(0), sort_key);
+        assert!(page.data.is_empty());
+        assert!(!page.has_more);
+        assert_eq!(page.next_cursor, None);
+    }
+}
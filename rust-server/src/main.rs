@@ -6,14 +6,17 @@ mod helpers;
 mod middlewares;
 mod models;
 mod services;
+mod workers;
 
 use std::sync::Arc;
 
 use app::App;
+use rust_server::parallel::build_thread_pool;
 
 use database::sqlx::SqlxPgPool;
 use helpers::{
     env::Env,
+    jwt::JwtValidator,
     logger::{DefaultLogger, Logger},
 };
 
@@ -29,7 +32,30 @@ async fn main() {
         return;
     }
 
-    let app = App::new(Arc::new(pool.unwrap()), env.clone());
+    let compression_pool = match build_thread_pool(env.compression_threads) {
+        Ok(compression_pool) => compression_pool,
+        Err(e) => {
+            logger.error(&format!(
+                "Could not initialize compression thread pool: {e}"
+            ));
+            return;
+        }
+    };
+
+    let jwt_validator = match JwtValidator::from_env(&env) {
+        Ok(jwt_validator) => jwt_validator.map(Arc::new),
+        Err(e) => {
+            logger.error(&format!("Could not load the JWT key: {e}"));
+            return;
+        }
+    };
+
+    let app = App::new(
+        Arc::new(pool.unwrap()),
+        env.clone(),
+        Arc::new(compression_pool),
+        jwt_validator,
+    );
 
     // Define the address for the server to listen on
     let ip_addr = format!("{}:{}", env.host, env.port);
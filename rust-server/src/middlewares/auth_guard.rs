@@ -1,12 +1,214 @@
-use axum::{extract::Request, http::StatusCode, middleware::Next, response::Response};
+use std::sync::Arc;
 
-pub async fn auth_guard(req: Request, next: Next) -> Result<Response, StatusCode> {
-    let headers = req.headers();
-    if let Some(auth_header) = headers.get("Authorization") {
-        if auth_header == "Bearer my_secret_token" {
-            return Ok(next.run(req).await);
+use axum::{
+    extract::{Request, State},
+    http::{header, Method, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use subtle::ConstantTimeEq;
+
+use crate::{
+    app::AppState,
+    helpers::api_key::ApiKeyHelper,
+    models::user::{Principal, Scope},
+    services::api_key_service::ApiKeyService,
+};
+
+/// Authenticates the request with an API key, a JWT or the admin token, then checks it has the scope the
+/// route needs. The caller is added to the request as a `Principal`. OPTIONS requests pass unauthenticated,
+/// tus clients and browsers send them without credentials to discover what the server supports.
+pub async fn auth_guard(
+    State(state): State<Arc<AppState>>,
+    mut req: Request,
+    next: Next,
+) -> Result<Response, (StatusCode, String)> {
+    if req.method() == Method::OPTIONS {
+        return Ok(next.run(req).await);
+    }
+
+    let token = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim);
+    let Some(token) = token else {
+        return Err((StatusCode::UNAUTHORIZED, "Missing bearer token".to_string()));
+    };
+
+    let principal = authenticate(&state, token).await?;
+    if let Some(scope) = required_scope(req.method(), req.uri().path()) {
+        if !principal.has_scope(scope) {
+            return Err((
+                StatusCode::FORBIDDEN,
+                format!("Requires the {} scope", scope.name()),
+            ));
+        }
+    }
+
+    req.extensions_mut().insert(principal);
+    Ok(next.run(req).await)
+}
+
+async fn authenticate(state: &AppState, token: &str) -> Result<Principal, (StatusCode, String)> {
+    if ApiKeyHelper::is_api_key(token) {
+        let api_key_service = ApiKeyService::new(state.pool.clone());
+        return match api_key_service
+            .authenticate(&ApiKeyHelper::hash(token))
+            .await
+        {
+            Ok(Some(api_key)) => Ok(Principal {
+                user_id: api_key.user_id.parse().ok(),
+                scopes: parse_scopes(&api_key.scopes),
+            }),
+            Ok(None) => Err((
+                StatusCode::UNAUTHORIZED,
+                "Invalid, expired or revoked API key".to_string(),
+            )),
+            Err(e) => Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to check API key: {e}"),
+            )),
+        };
+    }
+
+    if let Some(admin_token) = &state.env.admin_token {
+        if bool::from(token.as_bytes().ct_eq(admin_token.as_bytes())) {
+            return Ok(Principal {
+                user_id: None,
+                scopes: vec![Scope::Admin],
+            });
         }
     }
 
-    Err(StatusCode::UNAUTHORIZED)
+    let Some(jwt_validator) = &state.jwt_validator else {
+        return Err((StatusCode::UNAUTHORIZED, "Invalid token".to_string()));
+    };
+    let claims = jwt_validator
+        .validate(token)
+        .map_err(|e| (StatusCode::UNAUTHORIZED, e))?;
+    let user_id = claims.sub.parse().map_err(|_| {
+        (
+            StatusCode::UNAUTHORIZED,
+            "Token subject must be a user ID".to_string(),
+        )
+    })?;
+    Ok(Principal {
+        user_id: Some(user_id),
+        scopes: parse_scopes(&claims.scopes()),
+    })
+}
+
+// Scopes this server doesn't know about are ignored
+fn parse_scopes(scopes: &[String]) -> Vec<Scope> {
+    scopes
+        .iter()
+        .filter_map(|scope| scope.parse().ok())
+        .collect()
+}
+
+// Scope a request needs, by the part of the API it targets and whether it changes anything
+fn required_scope(method: &Method, path: &str) -> Option<Scope> {
+    let section = path.trim_start_matches('/').split('/').next();
+    match section.unwrap_or_default() {
+        "users" | "groups" | "webhooks" => Some(Scope::Admin),
+        // Callers manage their own keys, the handler checks whose key it is
+        "api-keys" => None,
+        _ if matches!(*method, Method::GET | Method::HEAD) => Some(Scope::Read),
+        "files" => Some(Scope::Upload),
+        _ => Some(Scope::Compress),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_required_scope_admin_sections() {
+        for path in [
+            "/users",
+            "/users/1/groups",
+            "/groups",
+            "/groups/1",
+            "/webhooks",
+        ] {
+            for method in [Method::GET, Method::POST, Method::DELETE] {
+                assert_eq!(
+                    required_scope(&method, path),
+                    Some(Scope::Admin),
+                    "{method} {path}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_required_scope_api_keys() {
+        assert_eq!(required_scope(&Method::GET, "/api-keys"), None);
+        assert_eq!(required_scope(&Method::POST, "/api-keys"), None);
+        assert_eq!(required_scope(&Method::DELETE, "/api-keys/1"), None);
+    }
+
+    #[test]
+    fn test_required_scope_reads() {
+        for path in [
+            "/files",
+            "/files/1/content",
+            "/files/tus/1",
+            "/compressed-files/1/events",
+            "/downloads/1",
+        ] {
+            assert_eq!(
+                required_scope(&Method::GET, path),
+                Some(Scope::Read),
+                "{path}"
+            );
+            assert_eq!(
+                required_scope(&Method::HEAD, path),
+                Some(Scope::Read),
+                "{path}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_required_scope_writes() {
+        assert_eq!(
+            required_scope(&Method::POST, "/files/upload"),
+            Some(Scope::Upload)
+        );
+        assert_eq!(
+            required_scope(&Method::PATCH, "/files/tus/1"),
+            Some(Scope::Upload)
+        );
+        assert_eq!(
+            required_scope(&Method::DELETE, "/files/1"),
+            Some(Scope::Upload)
+        );
+        assert_eq!(
+            required_scope(&Method::POST, "/compressed-files/1/compress"),
+            Some(Scope::Compress)
+        );
+        assert_eq!(
+            required_scope(&Method::DELETE, "/compressed-files/1"),
+            Some(Scope::Compress)
+        );
+    }
+
+    #[test]
+    fn test_required_scope_uploads() {
+        // The uploads directory isn't served anymore, its paths are like any other unknown section
+        assert_eq!(
+            required_scope(&Method::GET, "/uploads/a.txt"),
+            Some(Scope::Read)
+        );
+        assert_eq!(
+            required_scope(&Method::PUT, "/uploads/a.txt"),
+            Some(Scope::Compress)
+        );
+        assert_eq!(required_scope(&Method::GET, "/"), Some(Scope::Read));
+        assert_eq!(required_scope(&Method::POST, "/"), Some(Scope::Compress));
+    }
 }
@@ -1,3 +1,6 @@
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::prelude::FromRow;
 
@@ -6,21 +9,137 @@ pub struct File {
     pub id: String,
     pub size: i64,
     pub file_ref: String,
+    pub sha256: Option<String>,
+    // Set when the content lives in the blob store, shared with every file of the same content
+    pub blob_sha256: Option<String>,
+    // Set on files extracted from an uploaded archive
+    pub parent_id: Option<String>,
+    pub archive_path: Option<String>,
+    // User who uploaded the file, unset on files uploaded with the admin token
+    pub owner_id: Option<String>,
+    pub created_at: DateTime<Utc>,
 }
 
-#[derive(Debug, Serialize, Deserialize, sqlx::Type, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, sqlx::Type, PartialEq, Eq)]
 #[sqlx(type_name = "status_enum", rename_all = "lowercase")]
 pub enum FileStatus {
+    // Waiting for a compression worker
+    Queued,
     Compressing,
     Passed,
     Failed,
+    // Not worth compressing, no output was kept
+    Skipped,
+    Cancelled,
+}
+
+impl FileStatus {
+    /// Whether the compression is over, nothing changes anymore unless it is retried
+    pub fn is_final(&self) -> bool {
+        !matches!(self, FileStatus::Queued | FileStatus::Compressing)
+    }
+}
+
+impl FromStr for FileStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "queued" => Ok(FileStatus::Queued),
+            "compressing" => Ok(FileStatus::Compressing),
+            "passed" => Ok(FileStatus::Passed),
+            "failed" => Ok(FileStatus::Failed),
+            "skipped" => Ok(FileStatus::Skipped),
+            "cancelled" => Ok(FileStatus::Cancelled),
+            _ => Err(format!("Unknown status: {s}")),
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, FromRow)]
 pub struct CompressedFile {
     pub id: String,
+    // File that was compressed, unset on archives
+    pub file_id: Option<String>,
     pub status: FileStatus,
     pub file_ref: String,
     pub level: i32,
     pub alg: String,
+    pub bytes_processed: i64,
+    pub total_bytes: Option<i64>,
+    pub percent: f64,
+    // Statistics, filled in once the compression has passed
+    pub input_size: Option<i64>,
+    pub output_size: Option<i64>,
+    pub ratio: Option<f64>,
+    pub wall_time_ms: Option<i64>,
+    pub cpu_time_ms: Option<i64>,
+    pub sha256: Option<String>,
+    // Set when the algorithm was picked automatically
+    pub goal: Option<String>,
+    pub selection_reason: Option<String>,
+    pub skip_reason: Option<String>,
+    // Blob that was compressed, identical inputs share their compressions
+    pub input_sha256: Option<String>,
+    // Why the compression failed, once it has run out of attempts
+    pub error: Option<String>,
+    // User who started the compression, unset when started with the admin token
+    pub owner_id: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, sqlx::Type, PartialEq, Eq)]
+#[sqlx(type_name = "decompression_mode_enum", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum DecompressionMode {
+    Restore,
+    #[default]
+    Validate,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct Decompression {
+    pub id: String,
+    pub compressed_file_id: String,
+    pub mode: DecompressionMode,
+    pub status: FileStatus,
+    pub alg: String,
+    pub size: Option<i64>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ArchiveMember {
+    pub file_id: String,
+    // Name of the file inside the archive
+    pub name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Archive {
+    #[serde(flatten)]
+    pub compressed_file: CompressedFile,
+    pub members: Vec<ArchiveMember>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TusUpload {
+    pub id: String,
+    // Name the file gets in the uploads directory once complete
+    pub file_name: String,
+    pub upload_length: i64,
+    pub upload_offset: i64,
+    // `Upload-Metadata` header as sent on creation
+    pub metadata: Option<String>,
+    // Set once the upload has completed and been registered in `files`
+    pub file_id: Option<String>,
+    // User the upload and its file belong to
+    pub owner_id: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FileWithCompressed {
+    #[serde(flatten)]
+    pub file: File,
+    pub compressed: Vec<CompressedFile>,
 }
@@ -0,0 +1,69 @@
+use serde::{Deserialize, Serialize};
+
+use super::file::FileStatus;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, sqlx::Type, PartialEq, Eq)]
+#[sqlx(type_name = "job_kind_enum", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum JobKind {
+    // Compresses a file with the algorithm and level of its compressed file row
+    Compress,
+    // Builds an archive from its members
+    Archive,
+}
+
+/// Queued work producing the output of a compressed file row
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Job {
+    pub id: String,
+    pub compressed_file_id: String,
+    pub kind: JobKind,
+    // Counts the current run once claimed
+    pub attempts: i32,
+    pub max_attempts: i32,
+    pub last_error: Option<String>,
+}
+
+/// Change to a compressed file's job, pushed to the event stream subscribers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum JobEvent {
+    Status {
+        compressed_file_id: String,
+        status: FileStatus,
+        // Set when the job failed
+        error: Option<String>,
+    },
+    Progress {
+        compressed_file_id: String,
+        bytes_processed: u64,
+        total_bytes: Option<u64>,
+        percent: Option<f64>,
+    },
+}
+
+impl JobEvent {
+    pub fn compressed_file_id(&self) -> &str {
+        match self {
+            JobEvent::Status {
+                compressed_file_id, ..
+            }
+            | JobEvent::Progress {
+                compressed_file_id, ..
+            } => compressed_file_id,
+        }
+    }
+
+    /// Name of the event on the stream
+    pub fn name(&self) -> &'static str {
+        match self {
+            JobEvent::Status { .. } => "status",
+            JobEvent::Progress { .. } => "progress",
+        }
+    }
+
+    /// Whether it is the last event of the job
+    pub fn is_final(&self) -> bool {
+        matches!(self, JobEvent::Status { status, .. } if status.is_final())
+    }
+}
@@ -1 +1,6 @@
-pub mod file;
\ No newline at end of file
+pub mod file;
+pub mod job;
+pub mod page;
+pub mod share;
+pub mod user;
+pub mod webhook;
\ No newline at end of file
@@ -0,0 +1,74 @@
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// What a share lets its users do with a file
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, sqlx::Type, PartialEq, Eq)]
+#[sqlx(type_name = "share_permission_enum", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum Permission {
+    // Download the file and its compressed files
+    Read,
+    // Also compress, archive and decompress it
+    Compress,
+}
+
+impl FromStr for Permission {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "read" => Ok(Permission::Read),
+            "compress" => Ok(Permission::Compress),
+            _ => Err(format!("Unknown permission: {s}")),
+        }
+    }
+}
+
+/// What a request does with a file. Reading and compressing can be shared, the rest is up to the owner.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Compress,
+    // Delete, restore and share
+    Own,
+}
+
+/// A file shared with a user or with every member of a group
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FileShare {
+    pub id: String,
+    pub file_id: String,
+    // Exactly one of the user and the group is set
+    pub user_id: Option<String>,
+    pub group_id: Option<String>,
+    pub permission: Permission,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Group {
+    pub id: String,
+    pub name: String,
+    // IDs of the users in the group
+    pub members: Vec<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Signed link to download a file or compressed file without credentials
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DownloadLink {
+    pub id: String,
+    // Exactly one of the file and the compressed file is set
+    pub file_id: Option<String>,
+    pub compressed_file_id: Option<String>,
+    pub expires_at: DateTime<Utc>,
+    // Unlimited when unset
+    pub max_downloads: Option<i32>,
+    pub downloads: i32,
+    // Only this client address may download when set
+    pub allowed_ip: Option<String>,
+    pub created_by: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
@@ -0,0 +1,86 @@
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::types::Uuid;
+
+/// What an API key or token is allowed to do
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    // Upload, delete and restore files
+    Upload,
+    // Start, cancel and delete compressions, archives and decompressions
+    Compress,
+    // List and download files and compressed files
+    Read,
+    // Everything, including users, API keys and webhooks
+    Admin,
+}
+
+impl Scope {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Scope::Upload => "upload",
+            Scope::Compress => "compress",
+            Scope::Read => "read",
+            Scope::Admin => "admin",
+        }
+    }
+}
+
+impl FromStr for Scope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "upload" => Ok(Scope::Upload),
+            "compress" => Ok(Scope::Compress),
+            "read" => Ok(Scope::Read),
+            "admin" => Ok(Scope::Admin),
+            _ => Err(format!("Unknown scope: {s}")),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct User {
+    pub id: String,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Key a user authenticates with, only its hash is stored
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApiKey {
+    pub id: String,
+    pub user_id: String,
+    pub name: String,
+    pub prefix: String,
+    pub scopes: Vec<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    // Set once the key was rotated
+    pub replaced_by: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Who a request is made by, added to the request by the auth guard
+#[derive(Debug, Clone)]
+pub struct Principal {
+    // Unset for the admin token, which doesn't belong to a user
+    pub user_id: Option<Uuid>,
+    pub scopes: Vec<Scope>,
+}
+
+impl Principal {
+    /// Admins have every scope
+    pub fn has_scope(&self, scope: Scope) -> bool {
+        self.scopes.contains(&Scope::Admin) || self.scopes.contains(&scope)
+    }
+
+    pub fn is_admin(&self) -> bool {
+        self.scopes.contains(&Scope::Admin)
+    }
+}
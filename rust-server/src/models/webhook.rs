@@ -0,0 +1,101 @@
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum WebhookEvent {
+    #[serde(rename = "file.uploaded")]
+    FileUploaded,
+    #[serde(rename = "compression.passed")]
+    CompressionPassed,
+    #[serde(rename = "compression.failed")]
+    CompressionFailed,
+}
+
+impl WebhookEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            WebhookEvent::FileUploaded => "file.uploaded",
+            WebhookEvent::CompressionPassed => "compression.passed",
+            WebhookEvent::CompressionFailed => "compression.failed",
+        }
+    }
+}
+
+impl FromStr for WebhookEvent {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "file.uploaded" => Ok(WebhookEvent::FileUploaded),
+            "compression.passed" => Ok(WebhookEvent::CompressionPassed),
+            "compression.failed" => Ok(WebhookEvent::CompressionFailed),
+            _ => Err(format!("Unknown webhook event: {s}")),
+        }
+    }
+}
+
+/// URL notified of the events it is subscribed to, its secret is only returned when it is created
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Webhook {
+    pub id: String,
+    pub url: String,
+    pub events: Vec<String>,
+    pub active: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, sqlx::Type, PartialEq, Eq)]
+#[sqlx(type_name = "delivery_status_enum", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum DeliveryStatus {
+    // Waiting for its first or next attempt
+    Pending,
+    Delivered,
+    // Ran out of attempts
+    Failed,
+}
+
+impl FromStr for DeliveryStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "pending" => Ok(DeliveryStatus::Pending),
+            "delivered" => Ok(DeliveryStatus::Delivered),
+            "failed" => Ok(DeliveryStatus::Failed),
+            _ => Err(format!("Unknown delivery status: {s}")),
+        }
+    }
+}
+
+/// One event sent to one webhook, kept as the delivery log
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WebhookDelivery {
+    pub id: String,
+    pub webhook_id: String,
+    pub event: String,
+    pub payload: serde_json::Value,
+    pub status: DeliveryStatus,
+    pub attempts: i32,
+    pub max_attempts: i32,
+    // HTTP status the webhook answered the last attempt with, unset when it couldn't be reached
+    pub response_status: Option<i32>,
+    pub last_error: Option<String>,
+    pub delivered_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Delivery claimed by a worker, with what it takes to send it
+#[derive(Debug)]
+pub struct PendingDelivery {
+    pub id: String,
+    pub event: String,
+    pub payload: String,
+    pub url: String,
+    pub secret: String,
+    // Counts the current attempt
+    pub attempts: i32,
+    pub max_attempts: i32,
+}
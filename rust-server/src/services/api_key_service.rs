@@ -0,0 +1,127 @@
+use std::sync::Arc;
+
+use sqlx::Row;
+use sqlx::{postgres::PgRow, types::Uuid, PgPool};
+
+use crate::dtos::CreateApiKey;
+use crate::models::user::ApiKey;
+
+// Columns returned for every API key, in the shape `from_row` expects
+const COLUMNS: &str =
+    "id::text, user_id::text, name, prefix, scopes, expires_at, last_used_at, revoked_at, \
+    replaced_by::text, created_at";
+// Keys that can still be used
+const USABLE: &str = "revoked_at IS NULL AND (expires_at IS NULL OR expires_at > now())";
+
+#[derive(Debug, Clone)]
+pub struct ApiKeyService {
+    pool: Arc<PgPool>,
+}
+
+impl ApiKeyService {
+    pub fn new(pool: Arc<PgPool>) -> Self {
+        Self { pool }
+    }
+}
+
+impl ApiKeyService {
+    pub async fn create(&self, create_api_key: CreateApiKey) -> Result<ApiKey, sqlx::Error> {
+        sqlx::query(&format!("INSERT INTO api_keys (user_id, name, prefix, key_hash, scopes, expires_at) VALUES ($1, $2, $3, $4, $5, now() + make_interval(secs => $6)) RETURNING {COLUMNS}"))
+            .bind(create_api_key.user_id)
+            .bind(create_api_key.name)
+            .bind(create_api_key.prefix)
+            .bind(create_api_key.key_hash)
+            .bind(create_api_key.scopes)
+            .bind(create_api_key.expires_in_secs.map(|secs| secs as f64))
+            .fetch_one(&*self.pool)
+            .await
+            .map(Self::from_row)
+    }
+
+    pub async fn find_one(&self, id: Uuid) -> Result<ApiKey, sqlx::Error> {
+        sqlx::query(&format!("SELECT {COLUMNS} FROM api_keys WHERE id = $1"))
+            .bind(id)
+            .fetch_one(&*self.pool)
+            .await
+            .map(Self::from_row)
+    }
+
+    /// Every key of the user, revoked and expired ones included
+    pub async fn find_for_user(&self, user_id: Uuid) -> Result<Vec<ApiKey>, sqlx::Error> {
+        sqlx::query(&format!(
+            "SELECT {COLUMNS} FROM api_keys WHERE user_id = $1 ORDER BY created_at"
+        ))
+        .bind(user_id)
+        .fetch_all(&*self.pool)
+        .await
+        .map(|rows| rows.into_iter().map(Self::from_row).collect())
+    }
+
+    /// Finds the usable key with this hash and records that it was used
+    pub async fn authenticate(&self, key_hash: &str) -> Result<Option<ApiKey>, sqlx::Error> {
+        sqlx::query(&format!(
+            "UPDATE api_keys SET last_used_at = now() WHERE key_hash = $1 AND {USABLE} RETURNING {COLUMNS}"
+        ))
+        .bind(key_hash)
+        .fetch_optional(&*self.pool)
+        .await
+        .map(|row| row.map(Self::from_row))
+    }
+
+    /// Replaces a usable key with a new one of the same user, name, scopes and expiry.
+    /// The old key keeps working for `grace_secs`. Returns None when the key isn't usable.
+    pub async fn rotate(
+        &self,
+        id: Uuid,
+        prefix: &str,
+        key_hash: &str,
+        grace_secs: u64,
+    ) -> Result<Option<ApiKey>, sqlx::Error> {
+        let mut transaction = self.pool.begin().await?;
+
+        let rotated = sqlx::query(&format!("INSERT INTO api_keys (user_id, name, prefix, key_hash, scopes, expires_at) SELECT user_id, name, $1, $2, scopes, expires_at FROM api_keys WHERE id = $3 AND replaced_by IS NULL AND {USABLE} RETURNING {COLUMNS}"))
+            .bind(prefix)
+            .bind(key_hash)
+            .bind(id)
+            .fetch_optional(&mut *transaction)
+            .await?
+            .map(Self::from_row);
+        let Some(rotated) = rotated else {
+            return Ok(None);
+        };
+
+        sqlx::query("UPDATE api_keys SET replaced_by = $1::uuid, expires_at = LEAST(COALESCE(expires_at, 'infinity'), now() + make_interval(secs => $2)) WHERE id = $3")
+            .bind(&rotated.id)
+            .bind(grace_secs as f64)
+            .bind(id)
+            .execute(&mut *transaction)
+            .await?;
+
+        transaction.commit().await?;
+        Ok(Some(rotated))
+    }
+
+    /// Stops the key from working, returns false when it was already revoked or doesn't exist
+    pub async fn revoke(&self, id: Uuid) -> Result<bool, sqlx::Error> {
+        sqlx::query("UPDATE api_keys SET revoked_at = now() WHERE id = $1 AND revoked_at IS NULL")
+            .bind(id)
+            .execute(&*self.pool)
+            .await
+            .map(|result| result.rows_affected() > 0)
+    }
+
+    fn from_row(row: PgRow) -> ApiKey {
+        ApiKey {
+            id: row.get("id"),
+            user_id: row.get("user_id"),
+            name: row.get("name"),
+            prefix: row.get("prefix"),
+            scopes: row.get("scopes"),
+            expires_at: row.get("expires_at"),
+            last_used_at: row.get("last_used_at"),
+            revoked_at: row.get("revoked_at"),
+            replaced_by: row.get("replaced_by"),
+            created_at: row.get("created_at"),
+        }
+    }
+}
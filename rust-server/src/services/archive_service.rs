@@ -0,0 +1,61 @@
+use std::sync::Arc;
+
+use sqlx::Row;
+use sqlx::{postgres::PgRow, types::Uuid, PgPool};
+
+use crate::dtos::CreateArchiveMember;
+use crate::models::file::ArchiveMember;
+
+#[derive(Debug, Clone)]
+pub struct ArchiveService {
+    pool: Arc<PgPool>,
+}
+
+impl ArchiveService {
+    pub fn new(pool: Arc<PgPool>) -> Self {
+        Self { pool }
+    }
+}
+
+impl ArchiveService {
+    /// Links the files to the archive's compressed file row, in archive order
+    pub async fn add_members(
+        &self,
+        compressed_file_id: Uuid,
+        members: Vec<CreateArchiveMember>,
+    ) -> Result<Vec<ArchiveMember>, sqlx::Error> {
+        let mut transaction = self.pool.begin().await?;
+        let mut created = Vec::with_capacity(members.len());
+        for (position, member) in members.into_iter().enumerate() {
+            let row = sqlx::query("INSERT INTO archive_members (compressed_file_id, file_id, name, position) VALUES ($1, $2, $3, $4) RETURNING file_id::text, name")
+                .bind(compressed_file_id)
+                .bind(member.file_id)
+                .bind(member.name)
+                .bind(position as i32)
+                .fetch_one(&mut *transaction)
+                .await?;
+            created.push(Self::from_row(row));
+        }
+        transaction.commit().await?;
+
+        Ok(created)
+    }
+
+    pub async fn find_members(
+        &self,
+        compressed_file_id: Uuid,
+    ) -> Result<Vec<ArchiveMember>, sqlx::Error> {
+        sqlx::query("SELECT file_id::text, name FROM archive_members WHERE compressed_file_id = $1 ORDER BY position")
+            .bind(compressed_file_id)
+            .fetch_all(&*self.pool)
+            .await
+            .map(|rows| rows.into_iter().map(Self::from_row).collect())
+    }
+
+    fn from_row(row: PgRow) -> ArchiveMember {
+        ArchiveMember {
+            file_id: row.get("file_id"),
+            name: row.get("name"),
+        }
+    }
+}
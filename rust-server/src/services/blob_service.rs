@@ -0,0 +1,58 @@
+use std::sync::Arc;
+
+use sqlx::PgPool;
+use sqlx::Row;
+
+#[derive(Debug, Clone)]
+pub struct BlobService {
+    pool: Arc<PgPool>,
+}
+
+impl BlobService {
+    pub fn new(pool: Arc<PgPool>) -> Self {
+        Self { pool }
+    }
+}
+
+impl BlobService {
+    /// Records one more file referencing the blob, registering the blob on first use.
+    /// Returns the new reference count.
+    pub async fn acquire(&self, sha256: &str, size: u64) -> Result<i32, sqlx::Error> {
+        sqlx::query("INSERT INTO blobs (sha256, size, ref_count) VALUES ($1, $2, 1) ON CONFLICT (sha256) DO UPDATE SET ref_count = blobs.ref_count + 1 RETURNING ref_count")
+            .bind(sha256)
+            .bind(size as i64)
+            .fetch_one(&*self.pool)
+            .await
+            .map(|row| row.get("ref_count"))
+    }
+
+    /// Drops one reference to the blob. The last one removes the blob row, and its content at `blob_path`.
+    /// The content is removed while the row is still locked, so a concurrent `acquire` waits for it
+    /// and registers the blob anew, its caller then moves the content back in with `store_blob`.
+    /// Returns whether the blob was removed.
+    pub async fn release(&self, sha256: &str, blob_path: &str) -> Result<bool, sqlx::Error> {
+        let mut transaction = self.pool.begin().await?;
+        let removed = sqlx::query(
+            "UPDATE blobs SET ref_count = ref_count - 1 WHERE sha256 = $1 RETURNING ref_count",
+        )
+        .bind(sha256)
+        .fetch_optional(&mut *transaction)
+        .await?
+        .is_some_and(|row| row.get::<i32, _>("ref_count") <= 0);
+
+        if removed {
+            sqlx::query("DELETE FROM blobs WHERE sha256 = $1")
+                .bind(sha256)
+                .execute(&mut *transaction)
+                .await?;
+            match tokio::fs::remove_file(blob_path).await {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(sqlx::Error::Io(e)),
+            }
+        }
+        transaction.commit().await?;
+
+        Ok(removed)
+    }
+}
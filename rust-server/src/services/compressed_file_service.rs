@@ -1,13 +1,20 @@
 use std::sync::Arc;
 
-use flate2::Compression;
+use rust_server::{auto::Selection, CompressionStats, Progress};
 use sqlx::postgres::PgQueryResult;
 use sqlx::Row;
-use sqlx::{postgres::PgRow, types::Uuid, PgPool};
+use sqlx::{postgres::PgRow, types::Uuid, PgPool, Postgres, QueryBuilder};
 
-use crate::dtos::CreateCompressedFile;
+use crate::dtos::{CreateCompressedFile, ListCompressedFilesQuery};
+use crate::helpers::{access::AccessFilter, pagination::Pagination};
 use crate::models::file::CompressedFile;
 use crate::models::file::FileStatus;
+use crate::models::page::SortField;
+
+// Columns returned for every compressed file, in the shape `from_row` expects
+const COLUMNS: &str = "id::text, file_id::text, status, file_ref, level, alg, bytes_processed, total_bytes, \
+    percent, input_size, output_size, ratio, wall_time_ms, cpu_time_ms, sha256, goal, selection_reason, \
+    skip_reason, input_sha256, error, owner_id::text, created_at";
 
 #[derive(Debug, Clone)]
 pub struct CompressedFileService {
@@ -24,27 +31,20 @@ impl CompressedFileService {
     pub async fn create(
         &self,
         create_compressed_file: CreateCompressedFile,
-    ) -> (Compression, Result<CompressedFile, sqlx::Error>) {
-        let compression_level = match create_compressed_file.level {
-            0 => Compression::none(),
-            1..=9 => Compression::new(create_compressed_file.level),
-            _ => Compression::default(),
-        };
-
-        let compressed_file = sqlx::query("INSERT INTO compressed_files (status, file_ref, level, alg) VALUES ($1, $2, $3, $4) RETURNING id::text, status, file_ref, level, alg")
-            .bind(FileStatus::Compressing)
+    ) -> Result<CompressedFile, sqlx::Error> {
+        sqlx::query(&format!("INSERT INTO compressed_files (status, file_id, file_ref, level, alg, goal, selection_reason, input_sha256, owner_id) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) RETURNING {COLUMNS}"))
+            .bind(FileStatus::Queued)
+            .bind(create_compressed_file.file_id)
             .bind(create_compressed_file.file_ref)
-            .bind(compression_level.level() as i32)
+            .bind(create_compressed_file.level as i32)
             .bind(create_compressed_file.alg)
+            .bind(create_compressed_file.goal)
+            .bind(create_compressed_file.selection_reason)
+            .bind(create_compressed_file.input_sha256)
+            .bind(create_compressed_file.owner_id)
             .fetch_one(&*self.pool)
-            .await.map(|row: PgRow| CompressedFile {
-                id: row.get("id"),
-                status: row.get("status"),
-                file_ref: row.get("file_ref"),
-                level: row.get("level"),
-                alg: row.get("alg"),
-            });
-        (compression_level, compressed_file)
+            .await
+            .map(Self::from_row)
     }
 
     pub async fn update_status(
@@ -59,17 +59,378 @@ impl CompressedFileService {
             .await
     }
 
-    pub async fn find_one(&self, id: Uuid) -> Result<CompressedFile, sqlx::Error> {
-        sqlx::query("SELECT id::text, status, file_ref, level, alg FROM compressed_files WHERE id = $1")
+    pub async fn update_progress(
+        &self,
+        id: Uuid,
+        progress: Progress,
+    ) -> Result<PgQueryResult, sqlx::Error> {
+        sqlx::query("UPDATE compressed_files SET bytes_processed = $1, total_bytes = $2, percent = $3 WHERE id = $4")
+            .bind(progress.bytes_read as i64)
+            .bind(progress.total_bytes.map(|total| total as i64))
+            .bind(progress.percent().unwrap_or_default())
+            .bind(id)
+            .execute(&*self.pool)
+            .await
+    }
+
+    /// Marks a queued compression as started, returns false when it was cancelled or removed meanwhile
+    pub async fn start(&self, id: Uuid) -> Result<bool, sqlx::Error> {
+        sqlx::query("UPDATE compressed_files SET status = $1 WHERE id = $2 AND status IN ('queued', 'compressing')")
+            .bind(FileStatus::Compressing)
+            .bind(id)
+            .execute(&*self.pool)
+            .await
+            .map(|result| result.rows_affected() > 0)
+    }
+
+    /// Records the algorithm and level automatic selection picked for a queued compression
+    pub async fn select(
+        &self,
+        id: Uuid,
+        selection: &Selection,
+    ) -> Result<PgQueryResult, sqlx::Error> {
+        sqlx::query(
+            "UPDATE compressed_files SET alg = $1, level = $2, selection_reason = $3 WHERE id = $4",
+        )
+        .bind(selection.algorithm.name())
+        .bind(selection.level as i32)
+        .bind(&selection.reason)
+        .bind(id)
+        .execute(&*self.pool)
+        .await
+    }
+
+    /// Marks the compression as passed and records its statistics
+    pub async fn complete(
+        &self,
+        id: Uuid,
+        stats: &CompressionStats,
+    ) -> Result<PgQueryResult, sqlx::Error> {
+        sqlx::query("UPDATE compressed_files SET status = $1, input_size = $2, output_size = $3, ratio = $4, wall_time_ms = $5, cpu_time_ms = $6, sha256 = $7 WHERE id = $8 AND status = 'compressing'")
+            .bind(FileStatus::Passed)
+            .bind(stats.input_size as i64)
+            .bind(stats.output_size as i64)
+            .bind(stats.ratio())
+            .bind(stats.wall_time.as_millis() as i64)
+            .bind(stats.cpu_time.as_millis() as i64)
+            .bind(stats.sha256_hex())
             .bind(id)
+            .execute(&*self.pool)
+            .await
+    }
+
+    /// Marks the compression as skipped because the content isn't worth compressing
+    pub async fn skip(&self, id: Uuid, reason: &str) -> Result<PgQueryResult, sqlx::Error> {
+        sqlx::query("UPDATE compressed_files SET status = $1, skip_reason = $2 WHERE id = $3 AND status = 'compressing'")
+            .bind(FileStatus::Skipped)
+            .bind(reason)
+            .bind(id)
+            .execute(&*self.pool)
+            .await
+    }
+
+    /// Marks the compression as failed for good
+    pub async fn fail(&self, id: Uuid, error: &str) -> Result<PgQueryResult, sqlx::Error> {
+        sqlx::query("UPDATE compressed_files SET status = $1, error = $2 WHERE id = $3 AND status IN ('queued', 'compressing')")
+            .bind(FileStatus::Failed)
+            .bind(error)
+            .bind(id)
+            .execute(&*self.pool)
+            .await
+    }
+
+    /// Stops a queued or running compression and drops its job, returns false when there is none to stop
+    pub async fn cancel(&self, id: Uuid, access: &AccessFilter) -> Result<bool, sqlx::Error> {
+        let mut transaction = self.pool.begin().await?;
+        let mut query = QueryBuilder::<Postgres>::new("UPDATE compressed_files SET status = ");
+        query
+            .push_bind(FileStatus::Cancelled)
+            .push(" WHERE status IN ('queued', 'compressing') AND deleted_at IS NULL AND id = ")
+            .push_bind(id);
+        access.push_compressed_files(&mut query, "compressed_files");
+        let cancelled = query
+            .build()
+            .execute(&mut *transaction)
+            .await?
+            .rows_affected()
+            > 0;
+        if cancelled {
+            sqlx::query("DELETE FROM compression_jobs WHERE compressed_file_id = $1")
+                .bind(id)
+                .execute(&mut *transaction)
+                .await?;
+        }
+        transaction.commit().await?;
+
+        Ok(cancelled)
+    }
+
+    /// Queues a failed or cancelled compression again from scratch, returns false when it is neither
+    pub async fn requeue(&self, id: Uuid, access: &AccessFilter) -> Result<bool, sqlx::Error> {
+        let mut query = QueryBuilder::<Postgres>::new("UPDATE compressed_files SET status = ");
+        query
+            .push_bind(FileStatus::Queued)
+            .push(", error = NULL, bytes_processed = 0, total_bytes = NULL, percent = 0 WHERE status IN ('failed', 'cancelled') AND deleted_at IS NULL AND id = ")
+            .push_bind(id);
+        access.push_compressed_files(&mut query, "compressed_files");
+
+        query
+            .build()
+            .execute(&*self.pool)
+            .await
+            .map(|result| result.rows_affected() > 0)
+    }
+
+    pub async fn find_one(
+        &self,
+        id: Uuid,
+        access: &AccessFilter,
+    ) -> Result<CompressedFile, sqlx::Error> {
+        let mut query = QueryBuilder::<Postgres>::new(format!(
+            "SELECT {COLUMNS} FROM compressed_files WHERE deleted_at IS NULL AND id = "
+        ));
+        query.push_bind(id);
+        access.push_compressed_files(&mut query, "compressed_files");
+
+        query
+            .build()
             .fetch_one(&*self.pool)
             .await
-            .map(|row: PgRow| CompressedFile {
-                id: row.get("id"),
-                status: row.get("status"),
-                file_ref: row.get("file_ref"),
-                level: row.get("level"),
-                alg: row.get("alg"),
-            })
+            .map(Self::from_row)
+    }
+
+    /// One page of compressed files, plus the first row of the next page if there is one.
+    /// Sizes are output sizes, compressions without output sort as empty.
+    pub async fn list(
+        &self,
+        filter: &ListCompressedFilesQuery,
+        file_id: Option<Uuid>,
+        status: Option<FileStatus>,
+        pagination: &Pagination,
+        access: &AccessFilter,
+    ) -> Result<Vec<CompressedFile>, sqlx::Error> {
+        let mut query = QueryBuilder::<Postgres>::new(format!(
+            "SELECT {COLUMNS} FROM compressed_files WHERE deleted_at IS NULL"
+        ));
+        access.push_compressed_files(&mut query, "compressed_files");
+        if let Some(file_id) = file_id {
+            // Compressions reused from another file of the same content count as the file's too
+            query
+                .push(" AND (file_id = ")
+                .push_bind(file_id)
+                .push(" OR input_sha256 = (SELECT blob_sha256 FROM files WHERE id = ")
+                .push_bind(file_id)
+                .push("))");
+        }
+        if let Some(status) = status {
+            query.push(" AND status = ").push_bind(status);
+        }
+        if let Some(alg) = &filter.alg {
+            query.push(" AND alg = ").push_bind(alg.to_lowercase());
+        }
+        if let Some(level) = filter.level {
+            query.push(" AND level = ").push_bind(level);
+        }
+        if let Some(min_size) = filter.min_size {
+            query.push(" AND output_size >= ").push_bind(min_size);
+        }
+        if let Some(max_size) = filter.max_size {
+            query.push(" AND output_size <= ").push_bind(max_size);
+        }
+        pagination.push(
+            &mut query,
+            match pagination.sort {
+                SortField::CreatedAt => "created_at",
+                SortField::Size => "COALESCE(output_size, 0)",
+            },
+        );
+
+        query
+            .build()
+            .fetch_all(&*self.pool)
+            .await
+            .map(|rows| rows.into_iter().map(Self::from_row).collect())
+    }
+
+    /// Finds a compression of the same blob with the same algorithm and level that is done or underway.
+    /// Only those `access` reaches are reused, so the caller gets a row it may act on.
+    pub async fn find_reusable(
+        &self,
+        input_sha256: &str,
+        alg: &str,
+        level: u32,
+        access: &AccessFilter,
+    ) -> Result<Option<CompressedFile>, sqlx::Error> {
+        let mut query = QueryBuilder::<Postgres>::new(format!(
+            "SELECT {COLUMNS} FROM compressed_files WHERE input_sha256 = "
+        ));
+        query
+            .push_bind(input_sha256)
+            .push(" AND alg = ")
+            .push_bind(alg)
+            .push(" AND level = ")
+            .push_bind(level as i32)
+            .push(" AND status IN ('queued', 'compressing', 'passed', 'skipped') AND deleted_at IS NULL");
+        access.push_compressed_files(&mut query, "compressed_files");
+        query.push(" ORDER BY status = 'passed' DESC LIMIT 1");
+
+        query
+            .build()
+            .fetch_optional(&*self.pool)
+            .await
+            .map(|row| row.map(Self::from_row))
+    }
+
+    /// Compressed files of the file, including those reused from another file of the same content
+    pub async fn find_for_file(
+        &self,
+        file_id: Uuid,
+        access: &AccessFilter,
+    ) -> Result<Vec<CompressedFile>, sqlx::Error> {
+        let mut query = QueryBuilder::<Postgres>::new(format!(
+            "SELECT {COLUMNS} FROM compressed_files WHERE deleted_at IS NULL AND (file_id = "
+        ));
+        query
+            .push_bind(file_id)
+            .push(" OR input_sha256 = (SELECT blob_sha256 FROM files WHERE id = ")
+            .push_bind(file_id)
+            .push("))");
+        access.push_compressed_files(&mut query, "compressed_files");
+        query.push(" ORDER BY created_at, id");
+
+        query
+            .build()
+            .fetch_all(&*self.pool)
+            .await
+            .map(|rows| rows.into_iter().map(Self::from_row).collect())
+    }
+
+    /// Finds a compressed file whether it is deleted or not
+    pub async fn find_any(
+        &self,
+        id: Uuid,
+        access: &AccessFilter,
+    ) -> Result<Option<CompressedFile>, sqlx::Error> {
+        let mut query = QueryBuilder::<Postgres>::new(format!(
+            "SELECT {COLUMNS} FROM compressed_files WHERE id = "
+        ));
+        query.push_bind(id);
+        access.push_compressed_files(&mut query, "compressed_files");
+
+        query
+            .build()
+            .fetch_optional(&*self.pool)
+            .await
+            .map(|row| row.map(Self::from_row))
+    }
+
+    /// Every compressed file made from the file, deleted or not
+    pub async fn find_by_file_id(&self, file_id: Uuid) -> Result<Vec<CompressedFile>, sqlx::Error> {
+        sqlx::query(&format!(
+            "SELECT {COLUMNS} FROM compressed_files WHERE file_id = $1"
+        ))
+        .bind(file_id)
+        .fetch_all(&*self.pool)
+        .await
+        .map(|rows| rows.into_iter().map(Self::from_row).collect())
+    }
+
+    /// Hands the compressions of the file over to another live file of the same blob, so files reusing them
+    /// keep them once this one is purged. Those deleted along with the file come back, those deleted on their
+    /// own stay deleted. Returns how many were moved, none when no such file is left.
+    pub async fn transfer(&self, file_id: Uuid, input_sha256: &str) -> Result<u64, sqlx::Error> {
+        sqlx::query("UPDATE compressed_files SET file_id = heir.id, file_ref = heir.file_ref, deleted_at = CASE WHEN compressed_files.deleted_at = file.deleted_at THEN NULL ELSE compressed_files.deleted_at END FROM files file, (SELECT id, file_ref FROM files WHERE blob_sha256 = $2 AND id <> $1 AND deleted_at IS NULL ORDER BY created_at LIMIT 1) heir WHERE file.id = $1 AND compressed_files.file_id = $1 AND compressed_files.input_sha256 = $2")
+            .bind(file_id)
+            .bind(input_sha256)
+            .execute(&*self.pool)
+            .await
+            .map(|result| result.rows_affected())
+    }
+
+    /// Hides the compressed file, returns false when it doesn't exist, is already deleted or is out of reach
+    pub async fn soft_delete(&self, id: Uuid, access: &AccessFilter) -> Result<bool, sqlx::Error> {
+        let mut query = QueryBuilder::<Postgres>::new(
+            "UPDATE compressed_files SET deleted_at = now() WHERE deleted_at IS NULL AND id = ",
+        );
+        query.push_bind(id);
+        access.push_compressed_files(&mut query, "compressed_files");
+
+        query
+            .build()
+            .execute(&*self.pool)
+            .await
+            .map(|result| result.rows_affected() > 0)
+    }
+
+    /// Brings back a compressed file deleted less than `retention_secs` ago.
+    /// Compressed files of a deleted file only come back with the file itself.
+    pub async fn restore(
+        &self,
+        id: Uuid,
+        retention_secs: u64,
+        access: &AccessFilter,
+    ) -> Result<bool, sqlx::Error> {
+        let mut query = QueryBuilder::<Postgres>::new(
+            "UPDATE compressed_files SET deleted_at = NULL WHERE id = ",
+        );
+        query
+            .push_bind(id)
+            .push(" AND deleted_at > now() - make_interval(secs => ")
+            .push_bind(retention_secs as f64)
+            .push(") AND NOT EXISTS (SELECT 1 FROM files WHERE files.id = compressed_files.file_id AND files.deleted_at IS NOT NULL)");
+        access.push_compressed_files(&mut query, "compressed_files");
+
+        query
+            .build()
+            .execute(&*self.pool)
+            .await
+            .map(|result| result.rows_affected() > 0)
+    }
+
+    /// Removes the row for good, returns false when it was already gone
+    pub async fn delete(&self, id: Uuid) -> Result<bool, sqlx::Error> {
+        sqlx::query("DELETE FROM compressed_files WHERE id = $1")
+            .bind(id)
+            .execute(&*self.pool)
+            .await
+            .map(|result| result.rows_affected() > 0)
+    }
+
+    /// Compressed files deleted more than `retention_secs` ago, which can no longer be restored
+    pub async fn find_expired(&self, retention_secs: u64) -> Result<Vec<Uuid>, sqlx::Error> {
+        sqlx::query(
+            "SELECT id FROM compressed_files WHERE deleted_at <= now() - make_interval(secs => $1)",
+        )
+        .bind(retention_secs as f64)
+        .fetch_all(&*self.pool)
+        .await
+        .map(|rows| rows.into_iter().map(|row| row.get("id")).collect())
+    }
+
+    fn from_row(row: PgRow) -> CompressedFile {
+        CompressedFile {
+            id: row.get("id"),
+            file_id: row.get("file_id"),
+            status: row.get("status"),
+            file_ref: row.get("file_ref"),
+            level: row.get("level"),
+            alg: row.get("alg"),
+            bytes_processed: row.get("bytes_processed"),
+            total_bytes: row.get("total_bytes"),
+            percent: row.get("percent"),
+            input_size: row.get("input_size"),
+            output_size: row.get("output_size"),
+            ratio: row.get("ratio"),
+            wall_time_ms: row.get("wall_time_ms"),
+            cpu_time_ms: row.get("cpu_time_ms"),
+            sha256: row.get("sha256"),
+            goal: row.get("goal"),
+            selection_reason: row.get("selection_reason"),
+            skip_reason: row.get("skip_reason"),
+            input_sha256: row.get("input_sha256"),
+            error: row.get("error"),
+            owner_id: row.get("owner_id"),
+            created_at: row.get("created_at"),
+        }
     }
 }
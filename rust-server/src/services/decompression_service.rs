@@ -0,0 +1,44 @@
+use std::sync::Arc;
+
+use sqlx::Row;
+use sqlx::{postgres::PgRow, PgPool};
+
+use crate::dtos::CreateDecompression;
+use crate::models::file::Decompression;
+
+#[derive(Debug, Clone)]
+pub struct DecompressionService {
+    pool: Arc<PgPool>,
+}
+
+impl DecompressionService {
+    pub fn new(pool: Arc<PgPool>) -> Self {
+        Self { pool }
+    }
+}
+
+impl DecompressionService {
+    pub async fn create(
+        &self,
+        create_decompression: CreateDecompression,
+    ) -> Result<Decompression, sqlx::Error> {
+        sqlx::query("INSERT INTO decompressions (compressed_file_id, mode, status, alg, size, error) VALUES ($1, $2, $3, $4, $5, $6) RETURNING id::text, compressed_file_id::text, mode, status, alg, size, error")
+            .bind(create_decompression.compressed_file_id)
+            .bind(create_decompression.mode)
+            .bind(create_decompression.status)
+            .bind(create_decompression.alg)
+            .bind(create_decompression.size.map(|size| size as i64))
+            .bind(create_decompression.error)
+            .fetch_one(&*self.pool)
+            .await
+            .map(|row: PgRow| Decompression {
+                id: row.get("id"),
+                compressed_file_id: row.get("compressed_file_id"),
+                mode: row.get("mode"),
+                status: row.get("status"),
+                alg: row.get("alg"),
+                size: row.get("size"),
+                error: row.get("error"),
+            })
+    }
+}
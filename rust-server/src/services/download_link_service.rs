@@ -0,0 +1,85 @@
+use std::sync::Arc;
+
+use sqlx::Row;
+use sqlx::{postgres::PgRow, types::Uuid, PgPool};
+
+use crate::dtos::CreateDownloadLink;
+use crate::models::share::DownloadLink;
+
+// Columns returned for every link, in the shape `from_row` expects
+const COLUMNS: &str =
+    "id::text, file_id::text, compressed_file_id::text, expires_at, max_downloads, downloads, \
+    allowed_ip, created_by::text, created_at";
+
+#[derive(Debug, Clone)]
+pub struct DownloadLinkService {
+    pool: Arc<PgPool>,
+}
+
+impl DownloadLinkService {
+    pub fn new(pool: Arc<PgPool>) -> Self {
+        Self { pool }
+    }
+}
+
+impl DownloadLinkService {
+    pub async fn create(&self, link: CreateDownloadLink) -> Result<DownloadLink, sqlx::Error> {
+        // Whole seconds, as the expiry is signed as a Unix timestamp
+        sqlx::query(&format!("INSERT INTO download_links (file_id, compressed_file_id, expires_at, max_downloads, allowed_ip, created_by) VALUES ($1, $2, date_trunc('second', now() + make_interval(secs => $3)), $4, $5, $6) RETURNING {COLUMNS}"))
+            .bind(link.file_id)
+            .bind(link.compressed_file_id)
+            .bind(link.expires_in_secs as f64)
+            .bind(link.max_downloads.map(|max| max.min(i32::MAX as u32) as i32))
+            .bind(link.allowed_ip)
+            .bind(link.created_by)
+            .fetch_one(&*self.pool)
+            .await
+            .map(Self::from_row)
+    }
+
+    pub async fn find_one(&self, id: Uuid) -> Result<DownloadLink, sqlx::Error> {
+        sqlx::query(&format!(
+            "SELECT {COLUMNS} FROM download_links WHERE id = $1"
+        ))
+        .bind(id)
+        .fetch_one(&*self.pool)
+        .await
+        .map(Self::from_row)
+    }
+
+    /// Counts a download, returns None when the link has expired or has no downloads left
+    pub async fn claim(&self, id: Uuid) -> Result<Option<DownloadLink>, sqlx::Error> {
+        sqlx::query(&format!(
+            "UPDATE download_links SET downloads = downloads + 1 WHERE id = $1 AND expires_at > now() AND (max_downloads IS NULL OR downloads < max_downloads) RETURNING {COLUMNS}"
+        ))
+        .bind(id)
+        .fetch_optional(&*self.pool)
+        .await
+        .map(|row| row.map(Self::from_row))
+    }
+
+    /// Gives back a download counted by `claim` that didn't deliver the content
+    pub async fn refund(&self, id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE download_links SET downloads = downloads - 1 WHERE id = $1 AND downloads > 0",
+        )
+        .bind(id)
+        .execute(&*self.pool)
+        .await
+        .map(|_| ())
+    }
+
+    fn from_row(row: PgRow) -> DownloadLink {
+        DownloadLink {
+            id: row.get("id"),
+            file_id: row.get("file_id"),
+            compressed_file_id: row.get("compressed_file_id"),
+            expires_at: row.get("expires_at"),
+            max_downloads: row.get("max_downloads"),
+            downloads: row.get("downloads"),
+            allowed_ip: row.get("allowed_ip"),
+            created_by: row.get("created_by"),
+            created_at: row.get("created_at"),
+        }
+    }
+}
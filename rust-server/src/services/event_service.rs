@@ -0,0 +1,45 @@
+use std::sync::Arc;
+
+use sqlx::{postgres::PgListener, PgPool};
+use tokio::sync::broadcast;
+
+use crate::models::job::JobEvent;
+
+// Postgres channel job events travel on, so every instance sees the events of every worker
+const CHANNEL: &str = "job_events";
+
+#[derive(Debug, Clone)]
+pub struct EventService {
+    pool: Arc<PgPool>,
+}
+
+impl EventService {
+    pub fn new(pool: Arc<PgPool>) -> Self {
+        Self { pool }
+    }
+}
+
+impl EventService {
+    pub async fn publish(&self, event: &JobEvent) -> Result<(), sqlx::Error> {
+        sqlx::query("SELECT pg_notify($1, $2)")
+            .bind(CHANNEL)
+            .bind(serde_json::json!(event).to_string())
+            .execute(&*self.pool)
+            .await
+            .map(|_| ())
+    }
+
+    /// Forwards the published events to `sender` until the connection fails for good
+    pub async fn relay(&self, sender: &broadcast::Sender<JobEvent>) -> Result<(), sqlx::Error> {
+        let mut listener = PgListener::connect_with(&self.pool).await?;
+        listener.listen(CHANNEL).await?;
+
+        loop {
+            let notification = listener.recv().await?;
+            if let Ok(event) = serde_json::from_str(notification.payload()) {
+                // Nobody listening is fine
+                let _ = sender.send(event);
+            }
+        }
+    }
+}
@@ -1,8 +1,18 @@
-use crate::{dtos::CreateFile, models::file::File};
+use crate::{
+    dtos::{CreateFile, ListFilesQuery},
+    helpers::{access::AccessFilter, pagination::Pagination},
+    models::{file::File, page::SortField},
+};
+use chrono::{DateTime, Utc};
 use sqlx::Row;
-use sqlx::{postgres::PgRow, types::Uuid, PgPool};
+use sqlx::{postgres::PgRow, types::Uuid, PgPool, Postgres, QueryBuilder};
 use std::sync::Arc;
 
+// Columns returned for every file, in the shape `from_row` expects
+const COLUMNS: &str =
+    "id::text, size, file_ref, sha256, blob_sha256, parent_id::text, archive_path, \
+    owner_id::text, created_at";
+
 #[derive(Debug, Clone)]
 pub struct FileService {
     pool: Arc<PgPool>,
@@ -15,32 +25,178 @@ impl FileService {
 }
 
 impl FileService {
-    pub async fn find_one(&self, id: Uuid) -> Result<File, sqlx::Error> {
-        sqlx::query(
-            "SELECT id::text, size, file_ref FROM files WHERE id = $1",
-        )
-        .bind(id)
-        .fetch_one(&*self.pool)
-        .await
-        .map(|row: PgRow| File {
-            id: row.get("id"),
-            file_ref: row.get("file_ref"),
-            size: row.get("size"),
-        })
+    pub async fn find_one(&self, id: Uuid, access: &AccessFilter) -> Result<File, sqlx::Error> {
+        let mut query = QueryBuilder::<Postgres>::new(format!(
+            "SELECT {COLUMNS} FROM files WHERE deleted_at IS NULL AND id = "
+        ));
+        query.push_bind(id);
+        access.push_files(&mut query, "files");
+
+        query
+            .build()
+            .fetch_one(&*self.pool)
+            .await
+            .map(Self::from_row)
+    }
+
+    /// One page of files, plus the first row of the next page if there is one
+    pub async fn list(
+        &self,
+        filter: &ListFilesQuery,
+        pagination: &Pagination,
+        access: &AccessFilter,
+    ) -> Result<Vec<File>, sqlx::Error> {
+        let mut query = QueryBuilder::<Postgres>::new(format!(
+            "SELECT {COLUMNS} FROM files WHERE deleted_at IS NULL"
+        ));
+        access.push_files(&mut query, "files");
+        if let Some(min_size) = filter.min_size {
+            query.push(" AND size >= ").push_bind(min_size);
+        }
+        if let Some(max_size) = filter.max_size {
+            query.push(" AND size <= ").push_bind(max_size);
+        }
+        pagination.push(
+            &mut query,
+            match pagination.sort {
+                SortField::CreatedAt => "created_at",
+                SortField::Size => "size",
+            },
+        );
+
+        query
+            .build()
+            .fetch_all(&*self.pool)
+            .await
+            .map(|rows| rows.into_iter().map(Self::from_row).collect())
     }
 
     pub async fn create(&self, file: CreateFile) -> Result<File, sqlx::Error> {
-        sqlx::query(
-            "INSERT INTO files (size, file_ref) VALUES ($1, $2) RETURNING id::text, size, file_ref",
-        )
+        sqlx::query(&format!(
+            "INSERT INTO files (size, file_ref, sha256, blob_sha256, parent_id, archive_path, owner_id) VALUES ($1, $2, $3, $4, $5, $6, $7) RETURNING {COLUMNS}"
+        ))
         .bind(file.size as i64)
         .bind(file.file_ref)
+        .bind(file.sha256)
+        .bind(file.blob_sha256)
+        .bind(file.parent_id)
+        .bind(file.archive_path)
+        .bind(file.owner_id)
         .fetch_one(&*self.pool)
         .await
-        .map(|row: PgRow| File {
+        .map(Self::from_row)
+    }
+
+    /// Finds a file whether it is deleted or not
+    pub async fn find_any(
+        &self,
+        id: Uuid,
+        access: &AccessFilter,
+    ) -> Result<Option<File>, sqlx::Error> {
+        let mut query =
+            QueryBuilder::<Postgres>::new(format!("SELECT {COLUMNS} FROM files WHERE id = "));
+        query.push_bind(id);
+        access.push_files(&mut query, "files");
+
+        query
+            .build()
+            .fetch_optional(&*self.pool)
+            .await
+            .map(|row| row.map(Self::from_row))
+    }
+
+    /// Hides the file and the compressed files made from it, all with the same deletion time.
+    /// Returns false when the file doesn't exist, is already deleted or is out of reach.
+    pub async fn soft_delete(&self, id: Uuid, access: &AccessFilter) -> Result<bool, sqlx::Error> {
+        let mut transaction = self.pool.begin().await?;
+        let mut query = QueryBuilder::<Postgres>::new(
+            "UPDATE files SET deleted_at = now() WHERE deleted_at IS NULL AND id = ",
+        );
+        query.push_bind(id);
+        access.push_files(&mut query, "files");
+        query.push(" RETURNING deleted_at");
+        let Some(row) = query.build().fetch_optional(&mut *transaction).await? else {
+            return Ok(false);
+        };
+
+        sqlx::query(
+            "UPDATE compressed_files SET deleted_at = $1 WHERE file_id = $2 AND deleted_at IS NULL",
+        )
+        .bind(row.get::<DateTime<Utc>, _>("deleted_at"))
+        .bind(id)
+        .execute(&mut *transaction)
+        .await?;
+        transaction.commit().await?;
+
+        Ok(true)
+    }
+
+    /// Brings back a file deleted less than `retention_secs` ago, along with the compressed files deleted with it.
+    /// Returns false when there is no such file within reach.
+    pub async fn restore(
+        &self,
+        id: Uuid,
+        retention_secs: u64,
+        access: &AccessFilter,
+    ) -> Result<bool, sqlx::Error> {
+        let mut transaction = self.pool.begin().await?;
+        let mut query = QueryBuilder::<Postgres>::new("SELECT deleted_at FROM files WHERE id = ");
+        query
+            .push_bind(id)
+            .push(" AND deleted_at > now() - make_interval(secs => ")
+            .push_bind(retention_secs as f64)
+            .push(")");
+        access.push_files(&mut query, "files");
+        query.push(" FOR UPDATE");
+        let Some(row) = query.build().fetch_optional(&mut *transaction).await? else {
+            return Ok(false);
+        };
+
+        sqlx::query("UPDATE files SET deleted_at = NULL WHERE id = $1")
+            .bind(id)
+            .execute(&mut *transaction)
+            .await?;
+        sqlx::query(
+            "UPDATE compressed_files SET deleted_at = NULL WHERE file_id = $1 AND deleted_at = $2",
+        )
+        .bind(id)
+        .bind(row.get::<DateTime<Utc>, _>("deleted_at"))
+        .execute(&mut *transaction)
+        .await?;
+        transaction.commit().await?;
+
+        Ok(true)
+    }
+
+    /// Removes the row for good, returns false when it was already gone
+    pub async fn delete(&self, id: Uuid) -> Result<bool, sqlx::Error> {
+        sqlx::query("DELETE FROM files WHERE id = $1")
+            .bind(id)
+            .execute(&*self.pool)
+            .await
+            .map(|result| result.rows_affected() > 0)
+    }
+
+    /// Files deleted more than `retention_secs` ago, which can no longer be restored
+    pub async fn find_expired(&self, retention_secs: u64) -> Result<Vec<Uuid>, sqlx::Error> {
+        sqlx::query("SELECT id FROM files WHERE deleted_at <= now() - make_interval(secs => $1)")
+            .bind(retention_secs as f64)
+            .fetch_all(&*self.pool)
+            .await
+            .map(|rows| rows.into_iter().map(|row| row.get("id")).collect())
+    }
+
+    fn from_row(row: PgRow) -> File {
+        File {
             id: row.get("id"),
             size: row.get("size"),
             file_ref: row.get("file_ref"),
-        })
+            sha256: row.get("sha256"),
+            blob_sha256: row.get("blob_sha256"),
+            parent_id: row.get("parent_id"),
+            archive_path: row.get("archive_path"),
+            owner_id: row.get("owner_id"),
+            created_at: row.get("created_at"),
+        }
     }
 }
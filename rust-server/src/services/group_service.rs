@@ -0,0 +1,88 @@
+use std::sync::Arc;
+
+use sqlx::postgres::PgQueryResult;
+use sqlx::Row;
+use sqlx::{postgres::PgRow, types::Uuid, PgPool};
+
+use crate::models::share::Group;
+
+// Columns returned for every group, in the shape `from_row` expects
+const COLUMNS: &str = "id::text, name, ARRAY(SELECT user_id::text FROM group_members WHERE group_id = groups.id ORDER BY user_id) AS members, created_at";
+
+#[derive(Debug, Clone)]
+pub struct GroupService {
+    pool: Arc<PgPool>,
+}
+
+impl GroupService {
+    pub fn new(pool: Arc<PgPool>) -> Self {
+        Self { pool }
+    }
+}
+
+impl GroupService {
+    pub async fn create(&self, name: &str) -> Result<Group, sqlx::Error> {
+        sqlx::query(&format!(
+            "INSERT INTO groups (name) VALUES ($1) RETURNING {COLUMNS}"
+        ))
+        .bind(name)
+        .fetch_one(&*self.pool)
+        .await
+        .map(Self::from_row)
+    }
+
+    pub async fn find_all(&self) -> Result<Vec<Group>, sqlx::Error> {
+        sqlx::query(&format!("SELECT {COLUMNS} FROM groups ORDER BY created_at"))
+            .fetch_all(&*self.pool)
+            .await
+            .map(|rows| rows.into_iter().map(Self::from_row).collect())
+    }
+
+    pub async fn find_one(&self, id: Uuid) -> Result<Group, sqlx::Error> {
+        sqlx::query(&format!("SELECT {COLUMNS} FROM groups WHERE id = $1"))
+            .bind(id)
+            .fetch_one(&*self.pool)
+            .await
+            .map(Self::from_row)
+    }
+
+    /// Removes the group, the files shared with it are no longer shared with its members
+    pub async fn delete(&self, id: Uuid) -> Result<PgQueryResult, sqlx::Error> {
+        sqlx::query("DELETE FROM groups WHERE id = $1")
+            .bind(id)
+            .execute(&*self.pool)
+            .await
+    }
+
+    /// Adds the user to the group, adding a member twice changes nothing
+    pub async fn add_member(&self, id: Uuid, user_id: Uuid) -> Result<PgQueryResult, sqlx::Error> {
+        sqlx::query(
+            "INSERT INTO group_members (group_id, user_id) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+        )
+        .bind(id)
+        .bind(user_id)
+        .execute(&*self.pool)
+        .await
+    }
+
+    pub async fn remove_member(
+        &self,
+        id: Uuid,
+        user_id: Uuid,
+    ) -> Result<PgQueryResult, sqlx::Error> {
+        sqlx::query("DELETE FROM group_members WHERE group_id = $1 AND user_id = $2")
+            .bind(id)
+            .bind(user_id)
+            .execute(&*self.pool)
+            .await
+    }
+
+    fn from_row(row: PgRow) -> Group {
+        Group {
+            id: row.get("id"),
+            name: row.get("name"),
+            members: row.get("members"),
+            created_at: row.get("created_at"),
+        }
+    }
+}
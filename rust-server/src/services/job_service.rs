@@ -0,0 +1,123 @@
+use std::{sync::Arc, time::Duration};
+
+use sqlx::postgres::PgQueryResult;
+use sqlx::Row;
+use sqlx::{postgres::PgRow, types::Uuid, PgPool};
+
+use crate::models::job::{Job, JobKind};
+
+// Columns returned for every job, in the shape `from_row` expects
+const COLUMNS: &str =
+    "id::text, compressed_file_id::text, kind, attempts, max_attempts, last_error";
+
+#[derive(Debug, Clone)]
+pub struct JobService {
+    pool: Arc<PgPool>,
+}
+
+impl JobService {
+    pub fn new(pool: Arc<PgPool>) -> Self {
+        Self { pool }
+    }
+}
+
+impl JobService {
+    /// Queues the work for a compressed file, to run as soon as a worker is free
+    pub async fn enqueue(
+        &self,
+        compressed_file_id: Uuid,
+        kind: JobKind,
+        max_attempts: u32,
+    ) -> Result<Job, sqlx::Error> {
+        sqlx::query(&format!("INSERT INTO compression_jobs (compressed_file_id, kind, max_attempts) VALUES ($1, $2, $3) RETURNING {COLUMNS}"))
+            .bind(compressed_file_id)
+            .bind(kind)
+            .bind(max_attempts as i32)
+            .fetch_one(&*self.pool)
+            .await
+            .map(Self::from_row)
+    }
+
+    /// Takes the next due job for the worker, hidden from other workers for `visibility_secs`.
+    /// Jobs whose worker stopped renewing that lock are due again.
+    pub async fn claim(
+        &self,
+        worker_id: &str,
+        visibility_secs: u64,
+    ) -> Result<Option<Job>, sqlx::Error> {
+        sqlx::query(&format!("UPDATE compression_jobs SET attempts = attempts + 1, locked_by = $1, locked_until = now() + make_interval(secs => $2) WHERE id = (SELECT id FROM compression_jobs WHERE run_at <= now() AND (locked_until IS NULL OR locked_until < now()) ORDER BY run_at, created_at FOR UPDATE SKIP LOCKED LIMIT 1) RETURNING {COLUMNS}"))
+            .bind(worker_id)
+            .bind(visibility_secs as f64)
+            .fetch_optional(&*self.pool)
+            .await
+            .map(|row| row.map(Self::from_row))
+    }
+
+    /// Renews the worker's lock on a job it is still running, returns false when the job is gone
+    pub async fn heartbeat(
+        &self,
+        id: Uuid,
+        worker_id: &str,
+        visibility_secs: u64,
+    ) -> Result<bool, sqlx::Error> {
+        sqlx::query("UPDATE compression_jobs SET locked_until = now() + make_interval(secs => $1) WHERE id = $2 AND locked_by = $3")
+            .bind(visibility_secs as f64)
+            .bind(id)
+            .bind(worker_id)
+            .execute(&*self.pool)
+            .await
+            .map(|result| result.rows_affected() > 0)
+    }
+
+    /// Puts a failed job back in the queue, to run again after `delay`
+    pub async fn retry(
+        &self,
+        id: Uuid,
+        error: &str,
+        delay: Duration,
+    ) -> Result<PgQueryResult, sqlx::Error> {
+        sqlx::query("UPDATE compression_jobs SET locked_by = NULL, locked_until = NULL, run_at = now() + make_interval(secs => $1), last_error = $2 WHERE id = $3")
+            .bind(delay.as_secs_f64())
+            .bind(error)
+            .bind(id)
+            .execute(&*self.pool)
+            .await
+    }
+
+    /// Removes a job that is done with, whether it passed or ran out of attempts
+    pub async fn finish(&self, id: Uuid) -> Result<PgQueryResult, sqlx::Error> {
+        sqlx::query("DELETE FROM compression_jobs WHERE id = $1")
+            .bind(id)
+            .execute(&*self.pool)
+            .await
+    }
+
+    /// Hands back the jobs a worker held when it stopped, they can't be running anymore
+    pub async fn release(&self, worker_id: &str) -> Result<u64, sqlx::Error> {
+        sqlx::query("UPDATE compression_jobs SET locked_by = NULL, locked_until = NULL WHERE locked_by = $1")
+            .bind(worker_id)
+            .execute(&*self.pool)
+            .await
+            .map(|result| result.rows_affected())
+    }
+
+    /// Queues compressions still waiting or marked as compressing that no job will ever finish
+    pub async fn enqueue_orphans(&self, max_attempts: u32) -> Result<u64, sqlx::Error> {
+        sqlx::query("INSERT INTO compression_jobs (compressed_file_id, kind, max_attempts) SELECT id, CASE WHEN file_id IS NULL THEN 'archive' ELSE 'compress' END::job_kind_enum, $1 FROM compressed_files WHERE status IN ('queued', 'compressing') AND NOT EXISTS (SELECT 1 FROM compression_jobs WHERE compression_jobs.compressed_file_id = compressed_files.id) ON CONFLICT (compressed_file_id) DO NOTHING")
+            .bind(max_attempts as i32)
+            .execute(&*self.pool)
+            .await
+            .map(|result| result.rows_affected())
+    }
+
+    fn from_row(row: PgRow) -> Job {
+        Job {
+            id: row.get("id"),
+            compressed_file_id: row.get("compressed_file_id"),
+            kind: row.get("kind"),
+            attempts: row.get("attempts"),
+            max_attempts: row.get("max_attempts"),
+            last_error: row.get("last_error"),
+        }
+    }
+}
@@ -1,2 +1,14 @@
 pub mod file_service;
-pub mod compressed_file_service;
\ No newline at end of file
+pub mod compressed_file_service;
+pub mod decompression_service;
+pub mod archive_service;
+pub mod tus_upload_service;
+pub mod blob_service;
+pub mod job_service;
+pub mod event_service;
+pub mod webhook_service;
+pub mod api_key_service;
+pub mod user_service;
+pub mod group_service;
+pub mod share_service;
+pub mod download_link_service;
\ No newline at end of file
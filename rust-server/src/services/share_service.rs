@@ -0,0 +1,73 @@
+use std::sync::Arc;
+
+use sqlx::postgres::PgQueryResult;
+use sqlx::Row;
+use sqlx::{postgres::PgRow, types::Uuid, PgPool};
+
+use crate::dtos::CreateFileShare;
+use crate::models::share::FileShare;
+
+// Columns returned for every share, in the shape `from_row` expects
+const COLUMNS: &str =
+    "id::text, file_id::text, user_id::text, group_id::text, permission, created_at";
+
+#[derive(Debug, Clone)]
+pub struct ShareService {
+    pool: Arc<PgPool>,
+}
+
+impl ShareService {
+    pub fn new(pool: Arc<PgPool>) -> Self {
+        Self { pool }
+    }
+}
+
+impl ShareService {
+    /// Shares the file with the user or group, sharing it again only changes the permission
+    pub async fn create(&self, share: CreateFileShare) -> Result<FileShare, sqlx::Error> {
+        let conflict = match share.user_id {
+            Some(_) => "file_id, user_id",
+            None => "file_id, group_id",
+        };
+        sqlx::query(&format!(
+            "INSERT INTO file_shares (file_id, user_id, group_id, permission) VALUES ($1, $2, $3, $4) ON CONFLICT ({conflict}) DO UPDATE SET permission = EXCLUDED.permission RETURNING {COLUMNS}"
+        ))
+        .bind(share.file_id)
+        .bind(share.user_id)
+        .bind(share.group_id)
+        .bind(share.permission)
+        .fetch_one(&*self.pool)
+        .await
+        .map(Self::from_row)
+    }
+
+    pub async fn find_for_file(&self, file_id: Uuid) -> Result<Vec<FileShare>, sqlx::Error> {
+        sqlx::query(&format!(
+            "SELECT {COLUMNS} FROM file_shares WHERE file_id = $1 ORDER BY created_at"
+        ))
+        .bind(file_id)
+        .fetch_all(&*self.pool)
+        .await
+        .map(|rows| rows.into_iter().map(Self::from_row).collect())
+    }
+
+    /// Stops sharing the file this way, the share must belong to the file
+    pub async fn delete(&self, file_id: Uuid, id: Uuid) -> Result<PgQueryResult, sqlx::Error> {
+        sqlx::query("DELETE FROM file_shares WHERE id = $1 AND file_id = $2")
+            .bind(id)
+            .bind(file_id)
+            .execute(&*self.pool)
+            .await
+    }
+
+    fn from_row(row: PgRow) -> FileShare {
+        FileShare {
+            id: row.get("id"),
+            file_id: row.get("file_id"),
+            user_id: row.get("user_id"),
+            group_id: row.get("group_id"),
+            permission: row.get("permission"),
+            created_at: row.get("created_at"),
+        }
+    }
+}
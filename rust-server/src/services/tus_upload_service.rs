@@ -0,0 +1,92 @@
+use std::sync::Arc;
+
+use sqlx::postgres::PgQueryResult;
+use sqlx::Row;
+use sqlx::{postgres::PgRow, types::Uuid, PgPool};
+
+use crate::dtos::CreateTusUpload;
+use crate::models::file::TusUpload;
+
+// Columns returned for every upload, in the shape `from_row` expects
+const COLUMNS: &str =
+    "id::text, file_name, upload_length, upload_offset, metadata, file_id::text, owner_id::text";
+
+#[derive(Debug, Clone)]
+pub struct TusUploadService {
+    pool: Arc<PgPool>,
+}
+
+impl TusUploadService {
+    pub fn new(pool: Arc<PgPool>) -> Self {
+        Self { pool }
+    }
+}
+
+impl TusUploadService {
+    pub async fn create(&self, upload: CreateTusUpload) -> Result<TusUpload, sqlx::Error> {
+        sqlx::query(&format!(
+            "INSERT INTO tus_uploads (file_name, upload_length, metadata, owner_id) VALUES ($1, $2, $3, $4) RETURNING {COLUMNS}"
+        ))
+        .bind(upload.file_name)
+        .bind(upload.upload_length as i64)
+        .bind(upload.metadata)
+        .bind(upload.owner_id)
+        .fetch_one(&*self.pool)
+        .await
+        .map(Self::from_row)
+    }
+
+    pub async fn find_one(&self, id: Uuid) -> Result<TusUpload, sqlx::Error> {
+        sqlx::query(&format!("SELECT {COLUMNS} FROM tus_uploads WHERE id = $1"))
+            .bind(id)
+            .fetch_one(&*self.pool)
+            .await
+            .map(Self::from_row)
+    }
+
+    /// Moves the offset forward from `expected_offset`, returns false when another request moved it first
+    pub async fn update_offset(
+        &self,
+        id: Uuid,
+        expected_offset: u64,
+        upload_offset: u64,
+    ) -> Result<bool, sqlx::Error> {
+        sqlx::query(
+            "UPDATE tus_uploads SET upload_offset = $1 WHERE id = $2 AND upload_offset = $3",
+        )
+        .bind(upload_offset as i64)
+        .bind(id)
+        .bind(expected_offset as i64)
+        .execute(&*self.pool)
+        .await
+        .map(|result| result.rows_affected() > 0)
+    }
+
+    /// Links the completed upload to the `files` row created for it
+    pub async fn complete(&self, id: Uuid, file_id: Uuid) -> Result<PgQueryResult, sqlx::Error> {
+        sqlx::query("UPDATE tus_uploads SET file_id = $1 WHERE id = $2")
+            .bind(file_id)
+            .bind(id)
+            .execute(&*self.pool)
+            .await
+    }
+
+    pub async fn delete(&self, id: Uuid) -> Result<PgQueryResult, sqlx::Error> {
+        sqlx::query("DELETE FROM tus_uploads WHERE id = $1")
+            .bind(id)
+            .execute(&*self.pool)
+            .await
+    }
+
+    fn from_row(row: PgRow) -> TusUpload {
+        TusUpload {
+            id: row.get("id"),
+            file_name: row.get("file_name"),
+            upload_length: row.get("upload_length"),
+            upload_offset: row.get("upload_offset"),
+            metadata: row.get("metadata"),
+            file_id: row.get("file_id"),
+            owner_id: row.get("owner_id"),
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use std::sync::Arc;
+
+use sqlx::postgres::PgQueryResult;
+use sqlx::Row;
+use sqlx::{postgres::PgRow, types::Uuid, PgPool};
+
+use crate::models::user::User;
+
+// Columns returned for every user, in the shape `from_row` expects
+const COLUMNS: &str = "id::text, name, created_at";
+
+#[derive(Debug, Clone)]
+pub struct UserService {
+    pool: Arc<PgPool>,
+}
+
+impl UserService {
+    pub fn new(pool: Arc<PgPool>) -> Self {
+        Self { pool }
+    }
+}
+
+impl UserService {
+    pub async fn create(&self, name: &str) -> Result<User, sqlx::Error> {
+        sqlx::query(&format!(
+            "INSERT INTO users (name) VALUES ($1) RETURNING {COLUMNS}"
+        ))
+        .bind(name)
+        .fetch_one(&*self.pool)
+        .await
+        .map(Self::from_row)
+    }
+
+    pub async fn find_all(&self) -> Result<Vec<User>, sqlx::Error> {
+        sqlx::query(&format!("SELECT {COLUMNS} FROM users ORDER BY created_at"))
+            .fetch_all(&*self.pool)
+            .await
+            .map(|rows| rows.into_iter().map(Self::from_row).collect())
+    }
+
+    pub async fn find_one(&self, id: Uuid) -> Result<User, sqlx::Error> {
+        sqlx::query(&format!("SELECT {COLUMNS} FROM users WHERE id = $1"))
+            .bind(id)
+            .fetch_one(&*self.pool)
+            .await
+            .map(Self::from_row)
+    }
+
+    /// Removes the user along with their API keys, their files are left to admins
+    pub async fn delete(&self, id: Uuid) -> Result<PgQueryResult, sqlx::Error> {
+        sqlx::query("DELETE FROM users WHERE id = $1")
+            .bind(id)
+            .execute(&*self.pool)
+            .await
+    }
+
+    fn from_row(row: PgRow) -> User {
+        User {
+            id: row.get("id"),
+            name: row.get("name"),
+            created_at: row.get("created_at"),
+        }
+    }
+}
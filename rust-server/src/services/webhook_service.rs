@@ -0,0 +1,213 @@
+use std::{sync::Arc, time::Duration};
+
+use sqlx::postgres::PgQueryResult;
+use sqlx::Row;
+use sqlx::{postgres::PgRow, types::Uuid, PgPool, Postgres, QueryBuilder};
+
+use crate::dtos::CreateWebhook;
+use crate::helpers::pagination::Pagination;
+use crate::models::webhook::{
+    DeliveryStatus, PendingDelivery, Webhook, WebhookDelivery, WebhookEvent,
+};
+
+// Columns returned for every webhook, in the shape `from_row` expects
+const COLUMNS: &str = "id::text, url, events, active, created_at";
+// Columns returned for every delivery, in the shape `delivery_from_row` expects
+const DELIVERY_COLUMNS: &str =
+    "id::text, webhook_id::text, event, payload::text, status, attempts, max_attempts, \
+    response_status, last_error, delivered_at, created_at";
+
+#[derive(Debug, Clone)]
+pub struct WebhookService {
+    pool: Arc<PgPool>,
+}
+
+impl WebhookService {
+    pub fn new(pool: Arc<PgPool>) -> Self {
+        Self { pool }
+    }
+}
+
+impl WebhookService {
+    pub async fn create(&self, create_webhook: CreateWebhook) -> Result<Webhook, sqlx::Error> {
+        sqlx::query(&format!(
+            "INSERT INTO webhooks (url, secret, events) VALUES ($1, $2, $3) RETURNING {COLUMNS}"
+        ))
+        .bind(create_webhook.url)
+        .bind(create_webhook.secret)
+        .bind(create_webhook.events)
+        .fetch_one(&*self.pool)
+        .await
+        .map(Self::from_row)
+    }
+
+    pub async fn find_all(&self) -> Result<Vec<Webhook>, sqlx::Error> {
+        sqlx::query(&format!(
+            "SELECT {COLUMNS} FROM webhooks ORDER BY created_at"
+        ))
+        .fetch_all(&*self.pool)
+        .await
+        .map(|rows| rows.into_iter().map(Self::from_row).collect())
+    }
+
+    pub async fn find_one(&self, id: Uuid) -> Result<Webhook, sqlx::Error> {
+        sqlx::query(&format!("SELECT {COLUMNS} FROM webhooks WHERE id = $1"))
+            .bind(id)
+            .fetch_one(&*self.pool)
+            .await
+            .map(Self::from_row)
+    }
+
+    /// Removes the webhook along with its delivery log
+    pub async fn delete(&self, id: Uuid) -> Result<PgQueryResult, sqlx::Error> {
+        sqlx::query("DELETE FROM webhooks WHERE id = $1")
+            .bind(id)
+            .execute(&*self.pool)
+            .await
+    }
+
+    /// Queues a delivery of the event to every active webhook subscribed to it, returns how many were queued.
+    /// The payload wraps `data` with the event name and the time it happened.
+    pub async fn enqueue(
+        &self,
+        event: WebhookEvent,
+        data: &serde_json::Value,
+        max_attempts: u32,
+    ) -> Result<u64, sqlx::Error> {
+        sqlx::query("INSERT INTO webhook_deliveries (webhook_id, event, payload, max_attempts) SELECT id, $1, jsonb_build_object('event', $1, 'created_at', now(), 'data', $2::jsonb), $3 FROM webhooks WHERE active AND $1 = ANY(events)")
+            .bind(event.name())
+            .bind(data.to_string())
+            .bind(max_attempts as i32)
+            .execute(&*self.pool)
+            .await
+            .map(|result| result.rows_affected())
+    }
+
+    /// Takes the next due delivery, hidden from other workers for `visibility_secs`
+    pub async fn claim(
+        &self,
+        visibility_secs: u64,
+    ) -> Result<Option<PendingDelivery>, sqlx::Error> {
+        sqlx::query("UPDATE webhook_deliveries SET attempts = attempts + 1, locked_until = now() + make_interval(secs => $1) FROM webhooks WHERE webhook_deliveries.id = (SELECT id FROM webhook_deliveries WHERE status = 'pending' AND run_at <= now() AND (locked_until IS NULL OR locked_until < now()) ORDER BY run_at FOR UPDATE SKIP LOCKED LIMIT 1) AND webhooks.id = webhook_deliveries.webhook_id RETURNING webhook_deliveries.id::text, webhook_deliveries.event, webhook_deliveries.payload::text, webhooks.url, webhooks.secret, webhook_deliveries.attempts, webhook_deliveries.max_attempts")
+            .bind(visibility_secs as f64)
+            .fetch_optional(&*self.pool)
+            .await
+            .map(|row| {
+                row.map(|row| PendingDelivery {
+                    id: row.get("id"),
+                    event: row.get("event"),
+                    payload: row.get("payload"),
+                    url: row.get("url"),
+                    secret: row.get("secret"),
+                    attempts: row.get("attempts"),
+                    max_attempts: row.get("max_attempts"),
+                })
+            })
+    }
+
+    pub async fn delivered(
+        &self,
+        id: Uuid,
+        response_status: u16,
+    ) -> Result<PgQueryResult, sqlx::Error> {
+        sqlx::query("UPDATE webhook_deliveries SET status = $1, response_status = $2, last_error = NULL, locked_until = NULL, delivered_at = now() WHERE id = $3")
+            .bind(DeliveryStatus::Delivered)
+            .bind(response_status as i32)
+            .bind(id)
+            .execute(&*self.pool)
+            .await
+    }
+
+    /// Records a failed attempt, the delivery runs again after `delay`
+    pub async fn retry(
+        &self,
+        id: Uuid,
+        response_status: Option<u16>,
+        error: &str,
+        delay: Duration,
+    ) -> Result<PgQueryResult, sqlx::Error> {
+        sqlx::query("UPDATE webhook_deliveries SET response_status = $1, last_error = $2, locked_until = NULL, run_at = now() + make_interval(secs => $3) WHERE id = $4")
+            .bind(response_status.map(|status| status as i32))
+            .bind(error)
+            .bind(delay.as_secs_f64())
+            .bind(id)
+            .execute(&*self.pool)
+            .await
+    }
+
+    /// Records the last failed attempt, the delivery isn't tried again
+    pub async fn fail(
+        &self,
+        id: Uuid,
+        response_status: Option<u16>,
+        error: &str,
+    ) -> Result<PgQueryResult, sqlx::Error> {
+        sqlx::query("UPDATE webhook_deliveries SET status = $1, response_status = $2, last_error = $3, locked_until = NULL WHERE id = $4")
+            .bind(DeliveryStatus::Failed)
+            .bind(response_status.map(|status| status as i32))
+            .bind(error)
+            .bind(id)
+            .execute(&*self.pool)
+            .await
+    }
+
+    /// Sends a delivered or failed delivery again, with a fresh set of attempts.
+    /// Returns None when it doesn't exist or is still pending.
+    pub async fn redeliver(&self, id: Uuid) -> Result<Option<WebhookDelivery>, sqlx::Error> {
+        sqlx::query(&format!("UPDATE webhook_deliveries SET status = $1, attempts = 0, run_at = now(), locked_until = NULL, delivered_at = NULL WHERE id = $2 AND status IN ('delivered', 'failed') RETURNING {DELIVERY_COLUMNS}"))
+            .bind(DeliveryStatus::Pending)
+            .bind(id)
+            .fetch_optional(&*self.pool)
+            .await
+            .map(|row| row.map(Self::delivery_from_row))
+    }
+
+    /// Delivery log of a webhook
+    pub async fn list_deliveries(
+        &self,
+        webhook_id: Uuid,
+        status: Option<DeliveryStatus>,
+        pagination: &Pagination,
+    ) -> Result<Vec<WebhookDelivery>, sqlx::Error> {
+        let mut query = QueryBuilder::<Postgres>::new(format!(
+            "SELECT {DELIVERY_COLUMNS} FROM webhook_deliveries WHERE webhook_id = "
+        ));
+        query.push_bind(webhook_id);
+        if let Some(status) = status {
+            query.push(" AND status = ").push_bind(status);
+        }
+        pagination.push(&mut query, "created_at");
+
+        query
+            .build()
+            .fetch_all(&*self.pool)
+            .await
+            .map(|rows| rows.into_iter().map(Self::delivery_from_row).collect())
+    }
+
+    fn from_row(row: PgRow) -> Webhook {
+        Webhook {
+            id: row.get("id"),
+            url: row.get("url"),
+            events: row.get("events"),
+            active: row.get("active"),
+            created_at: row.get("created_at"),
+        }
+    }
+
+    fn delivery_from_row(row: PgRow) -> WebhookDelivery {
+        WebhookDelivery {
+            id: row.get("id"),
+            webhook_id: row.get("webhook_id"),
+            event: row.get("event"),
+            payload: serde_json::from_str(row.get("payload")).unwrap_or_default(),
+            status: row.get("status"),
+            attempts: row.get("attempts"),
+            max_attempts: row.get("max_attempts"),
+            response_status: row.get("response_status"),
+            last_error: row.get("last_error"),
+            delivered_at: row.get("delivered_at"),
+            created_at: row.get("created_at"),
+        }
+    }
+}
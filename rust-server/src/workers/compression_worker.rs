@@ -0,0 +1,657 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use flate2::Compression;
+use rayon::ThreadPool;
+use rust_server::{
+    archive::create_archive_cancellable,
+    auto::{self, Goal},
+    compress_file_cancellable,
+    content::{self, Assessment},
+    is_cancellation, Algorithm, ArchiveEntry, ArchiveFormat, CancellationToken, Compressor,
+    ParallelGzipCompressor, Progress,
+};
+use sqlx::types::Uuid;
+use tokio::{
+    sync::{watch, Notify},
+    task::JoinHandle,
+};
+
+use crate::{
+    app::AppState,
+    helpers::{
+        access::AccessFilter,
+        env::Env,
+        file::FileHelper,
+        logger::{DefaultLogger, Logger},
+    },
+    models::{
+        file::{CompressedFile, FileStatus},
+        job::{Job, JobEvent, JobKind},
+        webhook::WebhookEvent,
+    },
+    services::{
+        archive_service::ArchiveService, compressed_file_service::CompressedFileService,
+        event_service::EventService, file_service::FileService, job_service::JobService,
+        webhook_service::WebhookService,
+    },
+};
+
+// Progress reaches the database at most this often, however fast chunks are compressed
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+// Idle workers look for due jobs this often, enqueuing on this instance wakes them right away
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+// Delay before the first retry, doubled on every further attempt
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(10);
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(10 * 60);
+
+/// Why a job didn't finish, only transient failures are worth another attempt
+enum JobError {
+    Transient(String),
+    Permanent(String),
+    // Stopped on request, or because the job was taken away from this worker
+    Cancelled,
+}
+
+/// Runs the queued compressions and archives, `compression_workers` of them at a time
+#[derive(Clone)]
+pub struct CompressionWorker {
+    env: Arc<Env>,
+    compression_pool: Arc<ThreadPool>,
+    job_notify: Arc<Notify>,
+    webhook_notify: Arc<Notify>,
+    running_compressions: Arc<Mutex<HashMap<Uuid, CancellationToken>>>,
+    logger: Arc<dyn Logger>,
+    job_service: JobService,
+    file_service: FileService,
+    compressed_file_service: CompressedFileService,
+    archive_service: ArchiveService,
+    event_service: EventService,
+    webhook_service: WebhookService,
+}
+
+impl CompressionWorker {
+    pub fn new(state: Arc<AppState>) -> Self {
+        Self {
+            env: state.env.clone(),
+            compression_pool: state.compression_pool.clone(),
+            job_notify: state.job_notify.clone(),
+            webhook_notify: state.webhook_notify.clone(),
+            running_compressions: state.running_compressions.clone(),
+            job_service: JobService::new(state.pool.clone()),
+            file_service: FileService::new(state.pool.clone()),
+            compressed_file_service: CompressedFileService::new(state.pool.clone()),
+            archive_service: ArchiveService::new(state.pool.clone()),
+            event_service: EventService::new(state.pool.clone()),
+            webhook_service: WebhookService::new(state.pool.clone()),
+
+            // Initialize the logger
+            logger: Arc::new(DefaultLogger::new::<CompressionWorker>()),
+        }
+    }
+}
+
+impl CompressionWorker {
+    /// Recovers the jobs left behind by a previous run, then starts the workers
+    pub async fn start(self) {
+        // Whatever this instance held when it stopped isn't running anymore
+        match self.job_service.release(&self.env.worker_id).await {
+            Ok(0) => {}
+            Ok(released) => self
+                .logger
+                .log(&format!("Requeued {released} interrupted compression jobs")),
+            Err(e) => self
+                .logger
+                .error(&format!("Failed to requeue interrupted jobs: {e}")),
+        }
+        match self
+            .job_service
+            .enqueue_orphans(self.env.job_max_attempts)
+            .await
+        {
+            Ok(0) => {}
+            Ok(queued) => self
+                .logger
+                .log(&format!("Queued {queued} compressions that had no job")),
+            Err(e) => self
+                .logger
+                .error(&format!("Failed to queue orphaned compressions: {e}")),
+        }
+
+        for _ in 0..self.env.compression_workers {
+            tokio::spawn(self.clone().run());
+        }
+    }
+
+    async fn run(self) {
+        loop {
+            match self
+                .job_service
+                .claim(&self.env.worker_id, self.env.job_visibility_timeout_secs)
+                .await
+            {
+                Ok(Some(job)) => self.process(job).await,
+                Ok(None) => {
+                    tokio::select! {
+                        _ = self.job_notify.notified() => {}
+                        _ = tokio::time::sleep(POLL_INTERVAL) => {}
+                    }
+                }
+                Err(e) => {
+                    self.logger.error(&format!("Failed to claim a job: {e}"));
+                    tokio::time::sleep(POLL_INTERVAL).await;
+                }
+            }
+        }
+    }
+
+    async fn process(&self, job: Job) {
+        let (Ok(id), Ok(compressed_file_id)) = (
+            Uuid::parse_str(&job.id),
+            Uuid::parse_str(&job.compressed_file_id),
+        ) else {
+            return;
+        };
+
+        let result = if job.attempts > job.max_attempts {
+            // Its worker kept dying while running it
+            Err(JobError::Permanent(format!(
+                "Gave up after {} attempts: {}",
+                job.max_attempts,
+                job.last_error.as_deref().unwrap_or("interrupted")
+            )))
+        } else {
+            // Cancelling the compressed file reaches the job through the token
+            let cancellation = CancellationToken::new();
+            self.running_compressions
+                .lock()
+                .unwrap()
+                .insert(compressed_file_id, cancellation.clone());
+            let heartbeat = self.heartbeat(id, cancellation.clone());
+            self.logger.debug(&format!(
+                "Starting {:?} job(id: {}) for compressed file(id: {}), attempt {}...",
+                job.kind, id, compressed_file_id, job.attempts
+            ));
+            let result = self
+                .execute(compressed_file_id, job.kind, &cancellation)
+                .await;
+            heartbeat.abort();
+            self.running_compressions
+                .lock()
+                .unwrap()
+                .remove(&compressed_file_id);
+            result
+        };
+
+        let error = match result {
+            Ok(()) => None,
+            // Whoever cancelled it already took care of the row and the job
+            Err(JobError::Cancelled) => {
+                self.logger
+                    .debug(&format!("Job(id: {}) was cancelled.", id));
+                return;
+            }
+            Err(JobError::Transient(e)) if job.attempts < job.max_attempts => {
+                let delay = Self::backoff(job.attempts as u32);
+                self.logger.warn(&format!(
+                    "Job(id: {}) failed, retrying in {:?}: {e}",
+                    id, delay
+                ));
+                if let Err(db_err) = self.job_service.retry(id, &e, delay).await {
+                    self.logger
+                        .error(&format!("Failed to requeue job(id: {}): {db_err}", id));
+                }
+                return;
+            }
+            Err(JobError::Transient(e)) | Err(JobError::Permanent(e)) => Some(e),
+        };
+
+        if let Some(e) = &error {
+            self.logger.error(&format!("Job(id: {}) failed: {e}", id));
+            match self
+                .compressed_file_service
+                .fail(compressed_file_id, e)
+                .await
+            {
+                Ok(result) if result.rows_affected() > 0 => {
+                    self.publish_status(compressed_file_id, FileStatus::Failed, Some(e))
+                        .await
+                }
+                Ok(_) => {}
+                Err(db_err) => {
+                    self.logger.error(&format!(
+                        "Failed to update file status to Failed: {}",
+                        db_err
+                    ));
+                    // Left in the queue, the next attempt gives up again
+                    return;
+                }
+            }
+        }
+        if let Err(e) = self.job_service.finish(id).await {
+            self.logger
+                .error(&format!("Failed to remove job(id: {}): {e}", id));
+        }
+        self.logger.debug(&format!("Job(id: {}) completed.", id));
+    }
+
+    async fn execute(
+        &self,
+        compressed_file_id: Uuid,
+        kind: JobKind,
+        cancellation: &CancellationToken,
+    ) -> Result<(), JobError> {
+        let compressed_file = match self
+            .compressed_file_service
+            .find_any(compressed_file_id, &AccessFilter::Everything)
+            .await
+        {
+            Ok(Some(compressed_file)) => compressed_file,
+            // Purged before it ran, the job went with it
+            Ok(None) => return Ok(()),
+            Err(e) => return Err(JobError::Transient(e.to_string())),
+        };
+        // Finished by an attempt that was interrupted before removing its job
+        if !matches!(
+            compressed_file.status,
+            FileStatus::Queued | FileStatus::Compressing
+        ) {
+            return Ok(());
+        }
+        match self.compressed_file_service.start(compressed_file_id).await {
+            Ok(true) => {
+                self.publish_status(compressed_file_id, FileStatus::Compressing, None)
+                    .await
+            }
+            // Cancelled between the claim and now
+            Ok(false) => return Err(JobError::Cancelled),
+            Err(e) => return Err(JobError::Transient(e.to_string())),
+        }
+
+        match kind {
+            JobKind::Compress => {
+                self.compress(compressed_file_id, &compressed_file, cancellation)
+                    .await
+            }
+            JobKind::Archive => {
+                self.archive(compressed_file_id, &compressed_file, cancellation)
+                    .await
+            }
+        }
+    }
+
+    async fn compress(
+        &self,
+        id: Uuid,
+        compressed_file: &CompressedFile,
+        cancellation: &CancellationToken,
+    ) -> Result<(), JobError> {
+        let file_id: Uuid = compressed_file
+            .file_id
+            .as_deref()
+            .and_then(|file_id| file_id.parse().ok())
+            .ok_or_else(|| JobError::Permanent("Compressed file has no file".to_string()))?;
+        // The file may have been deleted since, it can still be restored
+        let file = match self
+            .file_service
+            .find_any(file_id, &AccessFilter::Everything)
+            .await
+        {
+            Ok(Some(file)) => file,
+            Ok(None) => return Err(JobError::Permanent(format!("File not found: {file_id}"))),
+            Err(e) => return Err(JobError::Transient(e.to_string())),
+        };
+        let input_path = FileHelper::get_file_path(&file, &self.env.uploads_dir)
+            .ok_or_else(|| JobError::Permanent(format!("File not found: {}", &file.file_ref)))?;
+
+        let (algorithm, level) = if compressed_file.alg.eq_ignore_ascii_case(auto::AUTO) {
+            self.select(id, compressed_file, &input_path).await?
+        } else {
+            let algorithm: Algorithm = compressed_file
+                .alg
+                .parse()
+                .map_err(|e| JobError::Permanent(format!("{e}")))?;
+            (algorithm, compressed_file.level as u32)
+        };
+        let output_path = FileHelper::get_compressed_file_path(
+            &compressed_file.id,
+            &self.env.compressed_dir,
+            algorithm,
+        )
+        .ok_or_else(|| {
+            JobError::Permanent(format!(
+                "Failed to create output path: {}",
+                &compressed_file.id
+            ))
+        })?;
+
+        let min_ratio = self.env.min_compression_ratio;
+        // Single-threaded so the trial doesn't count towards the pool's CPU time
+        let probe: Arc<dyn Compressor> = algorithm.compressor(level).into();
+        // Don't bother with content that won't get meaningfully smaller
+        match content::assess_file(&input_path, probe, min_ratio).await {
+            Ok(Assessment::Incompressible { reason }) => return self.skip(id, &reason).await,
+            Ok(Assessment::Compressible { .. }) => {}
+            Err(e) => self.logger.error(&format!(
+                "Compression task(id: {}) could not be assessed: {e}",
+                id
+            )),
+        }
+
+        let (progress_tx, progress_task) = self.track_progress(id);
+        let result = compress_file_cancellable(
+            &input_path,
+            &output_path,
+            self.compressor(algorithm, level),
+            move |progress| {
+                progress_tx.send_replace(progress);
+            },
+            cancellation.clone(),
+        )
+        .await;
+        // The sender is gone once compression ends, wait for the last progress write
+        let _ = progress_task.await;
+
+        match result {
+            Ok(stats) if stats.ratio() < min_ratio => {
+                // The sample was misleading, the output isn't worth keeping
+                if let Err(e) = tokio::fs::remove_file(&output_path).await {
+                    self.logger
+                        .error(&format!("Failed to remove {output_path}: {e}"));
+                }
+                let reason = format!("ratio {:.2} is below {:.2}", stats.ratio(), min_ratio);
+                self.skip(id, &reason).await
+            }
+            Ok(stats) => {
+                self.logger.debug(&format!(
+                    "Compression task(id: {}) ratio {:.2} in {:?}",
+                    id,
+                    stats.ratio(),
+                    stats.wall_time
+                ));
+                match self.compressed_file_service.complete(id, &stats).await {
+                    // Purged or cancelled while compressing, nothing refers to the output anymore
+                    Ok(result) if result.rows_affected() == 0 => {
+                        self.discard(id, &output_path).await;
+                        Ok(())
+                    }
+                    Ok(_) => {
+                        self.publish_status(id, FileStatus::Passed, None).await;
+                        Ok(())
+                    }
+                    Err(e) => Err(JobError::Transient(format!(
+                        "Failed to update file status to Passed: {e}"
+                    ))),
+                }
+            }
+            // The partial output is already gone
+            Err(e) if is_cancellation(&e) => Err(JobError::Cancelled),
+            Err(e) => Err(JobError::Transient(e.to_string())),
+        }
+    }
+
+    async fn archive(
+        &self,
+        id: Uuid,
+        compressed_file: &CompressedFile,
+        cancellation: &CancellationToken,
+    ) -> Result<(), JobError> {
+        let format: ArchiveFormat = compressed_file
+            .alg
+            .parse()
+            .map_err(|e| JobError::Permanent(format!("{e}")))?;
+        let output_path = FileHelper::get_archive_file_path(
+            &compressed_file.id,
+            &self.env.compressed_dir,
+            format,
+        )
+        .ok_or_else(|| {
+            JobError::Permanent(format!(
+                "Failed to create output path: {}",
+                &compressed_file.id
+            ))
+        })?;
+
+        let members = self
+            .archive_service
+            .find_members(id)
+            .await
+            .map_err(|e| JobError::Transient(e.to_string()))?;
+        let mut entries = Vec::with_capacity(members.len());
+        for member in members {
+            let file_id: Uuid = member.file_id.parse().map_err(|_| {
+                JobError::Permanent(format!("Invalid ID format: {}", member.file_id))
+            })?;
+            let file = match self
+                .file_service
+                .find_any(file_id, &AccessFilter::Everything)
+                .await
+            {
+                Ok(Some(file)) => file,
+                Ok(None) => return Err(JobError::Permanent(format!("File not found: {file_id}"))),
+                Err(e) => return Err(JobError::Transient(e.to_string())),
+            };
+            let path =
+                FileHelper::get_file_path(&file, &self.env.uploads_dir).ok_or_else(|| {
+                    JobError::Permanent(format!("File not found: {}", &file.file_ref))
+                })?;
+            entries.push(ArchiveEntry {
+                name: member.name,
+                path,
+            });
+        }
+
+        match create_archive_cancellable(
+            &output_path,
+            entries,
+            format,
+            compressed_file.level as u32,
+            cancellation.clone(),
+        )
+        .await
+        {
+            Ok(stats) => match self.compressed_file_service.complete(id, &stats).await {
+                // Purged or cancelled while building, nothing refers to the archive anymore
+                Ok(result) if result.rows_affected() == 0 => {
+                    self.discard(id, &output_path).await;
+                    Ok(())
+                }
+                Ok(_) => {
+                    self.publish_status(id, FileStatus::Passed, None).await;
+                    Ok(())
+                }
+                Err(e) => Err(JobError::Transient(format!(
+                    "Failed to update file status to Passed: {e}"
+                ))),
+            },
+            Err(e) if is_cancellation(&e) => Err(JobError::Cancelled),
+            Err(e) => {
+                let _ = tokio::fs::remove_file(&output_path).await;
+                Err(JobError::Transient(e.to_string()))
+            }
+        }
+    }
+}
+
+impl CompressionWorker {
+    // Keeps the job hidden from other workers for as long as it runs
+    // A job that can't be renewed was cancelled, purged or handed to another worker, so it stops too
+    fn heartbeat(&self, id: Uuid, cancellation: CancellationToken) -> JoinHandle<()> {
+        let job_service = self.job_service.clone();
+        let logger = self.logger.clone();
+        let worker_id = self.env.worker_id.clone();
+        let visibility_secs = self.env.job_visibility_timeout_secs;
+        tokio::spawn(async move {
+            let period = Duration::from_secs(visibility_secs).div_f64(3.0);
+            let mut interval = tokio::time::interval(period);
+            // The claim itself took the first lock
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                match job_service.heartbeat(id, &worker_id, visibility_secs).await {
+                    Ok(true) => {}
+                    Ok(false) => {
+                        cancellation.cancel();
+                        return;
+                    }
+                    Err(e) => logger.error(&format!("Failed to extend job(id: {}) lock: {e}", id)),
+                }
+            }
+        })
+    }
+
+    // Persists the latest progress reported by the compression task, throttled to PROGRESS_INTERVAL
+    fn track_progress(&self, id: Uuid) -> (watch::Sender<Progress>, JoinHandle<()>) {
+        let service = self.compressed_file_service.clone();
+        let event_service = self.event_service.clone();
+        let logger = self.logger.clone();
+        let (progress_tx, mut progress_rx) = watch::channel(Progress::default());
+        let task = tokio::spawn(async move {
+            while progress_rx.changed().await.is_ok() {
+                let progress = *progress_rx.borrow_and_update();
+                if let Err(e) = service.update_progress(id, progress).await {
+                    logger.error(&format!("Failed to update compression progress: {e}"));
+                }
+                let event = JobEvent::Progress {
+                    compressed_file_id: id.to_string(),
+                    bytes_processed: progress.bytes_read,
+                    total_bytes: progress.total_bytes,
+                    percent: progress.percent(),
+                };
+                if let Err(e) = event_service.publish(&event).await {
+                    logger.error(&format!("Failed to publish compression progress: {e}"));
+                }
+                tokio::time::sleep(PROGRESS_INTERVAL).await;
+            }
+        });
+
+        (progress_tx, task)
+    }
+
+    // Samples the file for the goal the compression was queued with and records the pick on the row
+    async fn select(
+        &self,
+        id: Uuid,
+        compressed_file: &CompressedFile,
+        input_path: &str,
+    ) -> Result<(Algorithm, u32), JobError> {
+        let goal: Goal = compressed_file
+            .goal
+            .as_deref()
+            .map(str::parse)
+            .transpose()
+            .map_err(|e| JobError::Permanent(format!("{e}")))?
+            .unwrap_or_default();
+        let selection = auto::select_for_file(input_path, goal)
+            .await
+            .map_err(|e| JobError::Transient(format!("Failed to select an algorithm: {e}")))?;
+        self.logger.debug(&format!(
+            "Compression task(id: {}) selected {} level {}: {}",
+            id, selection.algorithm, selection.level, selection.reason
+        ));
+        self.compressed_file_service
+            .select(id, &selection)
+            .await
+            .map_err(|e| JobError::Transient(e.to_string()))?;
+
+        Ok((selection.algorithm, selection.level))
+    }
+
+    async fn skip(&self, id: Uuid, reason: &str) -> Result<(), JobError> {
+        self.logger
+            .debug(&format!("Compression task(id: {}) skipped: {reason}", id));
+        match self.compressed_file_service.skip(id, reason).await {
+            Ok(result) if result.rows_affected() > 0 => {
+                self.publish_status(id, FileStatus::Skipped, None).await;
+                Ok(())
+            }
+            Ok(_) => Ok(()),
+            Err(e) => Err(JobError::Transient(format!(
+                "Failed to update file status to Skipped: {e}"
+            ))),
+        }
+    }
+
+    // Tells the event streams about the new status, and the webhooks once the compression passed or failed
+    async fn publish_status(&self, id: Uuid, status: FileStatus, error: Option<&str>) {
+        let event = JobEvent::Status {
+            compressed_file_id: id.to_string(),
+            status,
+            error: error.map(str::to_string),
+        };
+        if let Err(e) = self.event_service.publish(&event).await {
+            self.logger
+                .error(&format!("Failed to publish job(id: {}) status: {e}", id));
+        }
+
+        let webhook_event = match status {
+            FileStatus::Passed => WebhookEvent::CompressionPassed,
+            FileStatus::Failed => WebhookEvent::CompressionFailed,
+            _ => return,
+        };
+        let compressed_file = match self
+            .compressed_file_service
+            .find_any(id, &AccessFilter::Everything)
+            .await
+        {
+            Ok(Some(compressed_file)) => compressed_file,
+            Ok(None) => return,
+            Err(e) => {
+                self.logger
+                    .error(&format!("Failed to load compressed file(id: {}): {e}", id));
+                return;
+            }
+        };
+        match self
+            .webhook_service
+            .enqueue(
+                webhook_event,
+                &serde_json::json!(compressed_file),
+                self.env.webhook_max_attempts,
+            )
+            .await
+        {
+            Ok(0) => {}
+            Ok(_) => self.webhook_notify.notify_one(),
+            Err(e) => self.logger.error(&format!(
+                "Failed to queue webhook deliveries for job(id: {}): {e}",
+                id
+            )),
+        }
+    }
+
+    async fn discard(&self, id: Uuid, output_path: &str) {
+        self.logger.debug(&format!(
+            "Compression task(id: {}) was purged or cancelled, discarding its output",
+            id
+        ));
+        if let Err(e) = tokio::fs::remove_file(output_path).await {
+            self.logger
+                .error(&format!("Failed to remove {output_path}: {e}"));
+        }
+    }
+
+    // Gzip is spread over the compression pool, the other algorithms use a single thread
+    fn compressor(&self, algorithm: Algorithm, level: u32) -> Arc<dyn Compressor> {
+        match algorithm {
+            Algorithm::Gzip if self.compression_pool.current_num_threads() > 1 => {
+                let level = Compression::new(algorithm.normalize_level(level));
+                Arc::new(ParallelGzipCompressor::new(
+                    level,
+                    self.compression_pool.clone(),
+                ))
+            }
+            _ => algorithm.compressor(level).into(),
+        }
+    }
+
+    // Exponential, from RETRY_BASE_DELAY after the first attempt up to RETRY_MAX_DELAY
+    fn backoff(attempts: u32) -> Duration {
+        RETRY_BASE_DELAY
+            .saturating_mul(2u32.saturating_pow(attempts.saturating_sub(1)))
+            .min(RETRY_MAX_DELAY)
+    }
+}
@@ -0,0 +1,2 @@
+pub mod compression_worker;
+pub mod webhook_worker;
@@ -0,0 +1,211 @@
+use std::{sync::Arc, time::Duration};
+
+use axum::http::header;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use sqlx::types::Uuid;
+use tokio::sync::Notify;
+
+use crate::{
+    app::AppState,
+    helpers::logger::{DefaultLogger, Logger},
+    models::webhook::PendingDelivery,
+    services::webhook_service::WebhookService,
+};
+
+// Deliveries sent at the same time, so one slow receiver doesn't hold up the others
+const DELIVERY_WORKERS: usize = 4;
+// A receiver that doesn't answer within this time counts as a failed attempt
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+// Long enough for an attempt to time out, a delivery still locked after this was interrupted
+const VISIBILITY_TIMEOUT_SECS: u64 = 60;
+// Idle workers look for due deliveries this often, queuing on this instance wakes them right away
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+// Delay before the first retry, doubled on every further attempt
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(30);
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(60 * 60);
+
+/// Sends the queued webhook deliveries.
+/// Every request carries the delivery ID, the event name, a timestamp and an HMAC-SHA256 signature of
+/// `<timestamp>.<body>` keyed with the webhook's secret, as `X-Webhook-Signature: sha256=<hex>`.
+#[derive(Clone)]
+pub struct WebhookWorker {
+    webhook_notify: Arc<Notify>,
+    webhook_service: WebhookService,
+    client: reqwest::Client,
+    logger: Arc<dyn Logger>,
+}
+
+impl WebhookWorker {
+    pub fn new(state: Arc<AppState>) -> Self {
+        Self {
+            webhook_notify: state.webhook_notify.clone(),
+            webhook_service: WebhookService::new(state.pool.clone()),
+            client: reqwest::Client::builder()
+                .timeout(DELIVERY_TIMEOUT)
+                .build()
+                .unwrap_or_default(),
+
+            // Initialize the logger
+            logger: Arc::new(DefaultLogger::new::<WebhookWorker>()),
+        }
+    }
+}
+
+impl WebhookWorker {
+    pub fn start(self) {
+        for _ in 0..DELIVERY_WORKERS {
+            tokio::spawn(self.clone().run());
+        }
+    }
+
+    async fn run(self) {
+        loop {
+            match self.webhook_service.claim(VISIBILITY_TIMEOUT_SECS).await {
+                Ok(Some(delivery)) => self.deliver(delivery).await,
+                Ok(None) => {
+                    tokio::select! {
+                        _ = self.webhook_notify.notified() => {}
+                        _ = tokio::time::sleep(POLL_INTERVAL) => {}
+                    }
+                }
+                Err(e) => {
+                    self.logger
+                        .error(&format!("Failed to claim a webhook delivery: {e}"));
+                    tokio::time::sleep(POLL_INTERVAL).await;
+                }
+            }
+        }
+    }
+
+    async fn deliver(&self, delivery: PendingDelivery) {
+        let Ok(id) = Uuid::parse_str(&delivery.id) else {
+            return;
+        };
+
+        let timestamp = chrono::Utc::now().timestamp().to_string();
+        let signature = Self::signature(&delivery.secret, &timestamp, &delivery.payload);
+        let response = self
+            .client
+            .post(&delivery.url)
+            .header(header::CONTENT_TYPE.as_str(), "application/json")
+            .header("X-Webhook-Id", &delivery.id)
+            .header("X-Webhook-Event", &delivery.event)
+            .header("X-Webhook-Timestamp", &timestamp)
+            .header("X-Webhook-Signature", format!("sha256={signature}"))
+            .body(delivery.payload.clone())
+            .send()
+            .await;
+
+        let (response_status, error) = match response {
+            Ok(response) if response.status().is_success() => {
+                let status = response.status().as_u16();
+                if let Err(e) = self.webhook_service.delivered(id, status).await {
+                    self.logger.error(&format!(
+                        "Failed to mark webhook delivery(id: {}) as delivered: {e}",
+                        id
+                    ));
+                }
+                self.logger.debug(&format!(
+                    "Webhook delivery(id: {}) of {} delivered to {}",
+                    id, delivery.event, delivery.url
+                ));
+                return;
+            }
+            Ok(response) => (
+                Some(response.status().as_u16()),
+                format!("Webhook answered {}", response.status()),
+            ),
+            Err(e) => (None, format!("Failed to reach webhook: {e}")),
+        };
+
+        let result = if delivery.attempts < delivery.max_attempts {
+            let delay = Self::backoff(delivery.attempts as u32);
+            self.logger.warn(&format!(
+                "Webhook delivery(id: {}) failed, retrying in {:?}: {error}",
+                id, delay
+            ));
+            self.webhook_service
+                .retry(id, response_status, &error, delay)
+                .await
+        } else {
+            self.logger.error(&format!(
+                "Webhook delivery(id: {}) failed after {} attempts: {error}",
+                id, delivery.attempts
+            ));
+            self.webhook_service.fail(id, response_status, &error).await
+        };
+        if let Err(e) = result {
+            self.logger.error(&format!(
+                "Failed to record webhook delivery(id: {}) attempt: {e}",
+                id
+            ));
+        }
+    }
+}
+
+impl WebhookWorker {
+    // Hex encoded HMAC-SHA256 of `<timestamp>.<body>`, the timestamp lets receivers reject replays
+    fn signature(secret: &str, timestamp: &str, body: &str) -> String {
+        // HMAC takes keys of any size
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(timestamp.as_bytes());
+        mac.update(b".");
+        mac.update(body.as_bytes());
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    // Exponential, from RETRY_BASE_DELAY after the first attempt up to RETRY_MAX_DELAY
+    fn backoff(attempts: u32) -> Duration {
+        RETRY_BASE_DELAY
+            .saturating_mul(2u32.saturating_pow(attempts.saturating_sub(1)))
+            .min(RETRY_MAX_DELAY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "whsec_test";
+    const BODY: &str = r#"{"event":"file.uploaded","data":{"id":"1"}}"#;
+
+    #[test]
+    fn test_signature() {
+        // HMAC-SHA256 of `1747650000.<body>` keyed with the secret, computed independently
+        assert_eq!(
+            WebhookWorker::signature(SECRET, "1747650000", BODY),
+            "345d40f8140d2a40e5ce69c9c1a88b412ecd335ec9e1624d98e50cbd4ec1ea3e"
+        );
+        // The timestamp is signed too, a replayed body can't be given a new one
+        assert_eq!(
+            WebhookWorker::signature(SECRET, "1747650001", BODY),
+            "4bac3d450d823a91133324934f524d5f231b0e0fbad9309a99fad7ccc7fa1506"
+        );
+        assert_ne!(
+            WebhookWorker::signature("another secret", "1747650000", BODY),
+            WebhookWorker::signature(SECRET, "1747650000", BODY)
+        );
+    }
+
+    #[test]
+    fn test_backoff() {
+        let delays: Vec<_> = (1..=8).map(WebhookWorker::backoff).collect();
+        assert_eq!(
+            delays,
+            [30, 60, 120, 240, 480, 960, 1920, 3600].map(Duration::from_secs)
+        );
+        assert_eq!(WebhookWorker::backoff(0), RETRY_BASE_DELAY);
+    }
+
+    #[test]
+    fn test_backoff_cap() {
+        for attempts in [8, 9, 32, 33, 64, u32::MAX] {
+            assert_eq!(
+                WebhookWorker::backoff(attempts),
+                RETRY_MAX_DELAY,
+                "{attempts}"
+            );
+        }
+    }
+}